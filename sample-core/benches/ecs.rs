@@ -0,0 +1,237 @@
+//! Throughput of spawning, querying and updating Entities, next to the
+//! same work on a copy of the storage the ECS used before sparse sets.
+//!
+//! Run with `cargo bench -p sample-core`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use sample_core::{
+    ecs::{ECS, Entity},
+    component::Component,
+    time::Time,
+};
+
+const ENTITIES: usize = 50_000;
+const RUNS: usize = 10;
+
+struct Position(f32, f32, f32);
+struct Velocity(f32, f32, f32);
+struct Health(f32);
+
+impl Component for Position {
+    fn create(_entity: &mut Entity) -> Self {
+        Position(0.0, 0.0, 0.0)
+    }
+}
+
+impl Component for Velocity {
+    fn create(_entity: &mut Entity) -> Self {
+        Velocity(1.0, 0.5, 0.25)
+    }
+}
+
+impl Component for Health {
+    fn create(_entity: &mut Entity) -> Self {
+        Health(100.0)
+    }
+
+    fn update(&mut self, time: &Time) {
+        self.0 -= time.delta();
+    }
+}
+
+/// Spawns ENTITIES Entities with a Position, a Velocity on every other one,
+/// and a Health on every third one.
+fn spawn(ecs: &mut ECS) {
+    for i in 0..ENTITIES {
+        let mut entity = ecs.new_entity(&i.to_string());
+        entity.add_component::<Position>(&|_| {});
+        if i % 2 == 0 {
+            entity.add_component::<Velocity>(&|_| {});
+        }
+        if i % 3 == 0 {
+            entity.add_component::<Health>(&|_| {});
+        }
+    }
+}
+
+/// The storage of the ECS before sparse sets: every Entity keeps a
+/// HashMap from Component type to the index of its Component, and
+/// queries visit every Entity slot.
+///
+/// Only the storage is copied, without systems, hooks or change ticks.
+mod baseline {
+    use super::*;
+
+    struct EntitySlot {
+        generation: u32,
+        data: Option<EntityData>,
+    }
+
+    struct EntityData {
+        #[allow(dead_code)]
+        name: String,
+        comp_index: HashMap<TypeId, usize>,
+    }
+
+    struct ComponentStorage<C> {
+        components: Vec<C>,
+        owners: Vec<(u32, u32)>,
+    }
+
+    #[derive(Default)]
+    pub struct World {
+        entities: Vec<EntitySlot>,
+        entity_ids: HashMap<String, (u32, u32)>,
+        components: HashMap<TypeId, Box<dyn Any>>,
+    }
+
+    impl World {
+        pub fn new() -> Self {
+            World::default()
+        }
+
+        pub fn new_entity(&mut self, name: &str) -> usize {
+            let index = self.entities.len();
+            self.entities.push(EntitySlot {
+                generation: 0,
+                data: Some(EntityData {
+                    name: name.to_string(),
+                    comp_index: HashMap::new(),
+                }),
+            });
+            self.entity_ids.insert(name.to_string(), (index as u32, 0));
+            index
+        }
+
+        pub fn add_component<C: 'static>(&mut self, index: usize, comp: C) {
+            let generation = self.entities[index].generation;
+            let storage = self.components
+                .entry(TypeId::of::<C>())
+                .or_insert_with(|| Box::new(ComponentStorage::<C> { components: Vec::new(), owners: Vec::new() }))
+                .downcast_mut::<ComponentStorage<C>>()
+                .unwrap();
+            storage.components.push(comp);
+            storage.owners.push((index as u32, generation));
+
+            let comp_index = storage.components.len() - 1;
+            self.entities[index].data.as_mut().unwrap().comp_index.insert(TypeId::of::<C>(), comp_index);
+        }
+
+        fn components<C: 'static>(&mut self) -> *mut C {
+            self.components
+                .get_mut(&TypeId::of::<C>())
+                .unwrap()
+                .downcast_mut::<ComponentStorage<C>>()
+                .unwrap()
+                .components
+                .as_mut_ptr()
+        }
+
+        /// Calls f with the A and B of every Entity having both.
+        pub fn query<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(&mut A, &B)) {
+            let (a, b) = (self.components::<A>(), self.components::<B>());
+            for slot in &self.entities {
+                let data = match &slot.data {
+                    Some(data) => data,
+                    None => continue
+                };
+                if let (Some(i), Some(j)) = (data.comp_index.get(&TypeId::of::<A>()), data.comp_index.get(&TypeId::of::<B>())) {
+                    // A and B are different types, so the references never alias.
+                    unsafe { f(&mut *a.add(*i), &*b.add(*j)) }
+                }
+            }
+        }
+
+        /// Calls f on every Component of type C.
+        pub fn update<C: 'static>(&mut self, f: impl Fn(&mut C)) {
+            let storage = self.components
+                .get_mut(&TypeId::of::<C>())
+                .unwrap()
+                .downcast_mut::<ComponentStorage<C>>()
+                .unwrap();
+            storage.components.iter_mut().for_each(f);
+        }
+    }
+
+    /// Spawns the same Entities as super::spawn().
+    pub fn spawn(world: &mut World) {
+        for i in 0..ENTITIES {
+            let index = world.new_entity(&i.to_string());
+            world.add_component(index, Position(0.0, 0.0, 0.0));
+            if i % 2 == 0 {
+                world.add_component(index, Velocity(1.0, 0.5, 0.25));
+            }
+            if i % 3 == 0 {
+                world.add_component(index, Health(100.0));
+            }
+        }
+    }
+}
+
+/// Runs f RUNS times, returning the fastest run.
+fn measure<W>(mut setup: impl FnMut() -> W, mut f: impl FnMut(&mut W)) -> Duration {
+    (0..RUNS).map(|_| {
+        let mut world = setup();
+        let start = Instant::now();
+        f(&mut world);
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn report(name: &str, duration: Duration, baseline: Duration, count: usize) {
+    println!("{:<8} {:>10.3} ms {:>10.1} ns/entity   baseline {:>10.3} ms {:>10.1} ns/entity",
+             name,
+             duration.as_secs_f64() * 1000.0,
+             duration.as_nanos() as f64 / count as f64,
+             baseline.as_secs_f64() * 1000.0,
+             baseline.as_nanos() as f64 / count as f64);
+}
+
+fn main() {
+    let spawned = || {
+        let mut ecs = ECS::new();
+        spawn(&mut ecs);
+        ecs
+    };
+    let baseline_spawned = || {
+        let mut world = baseline::World::new();
+        baseline::spawn(&mut world);
+        world
+    };
+
+    report("spawn",
+           measure(ECS::new, spawn),
+           measure(baseline::World::new, baseline::spawn),
+           ENTITIES);
+
+    report("query",
+           measure(spawned, |ecs| {
+               for (mut position, velocity) in ecs.query::<(&mut Position, &Velocity)>() {
+                   position.0 += velocity.0;
+                   position.1 += velocity.1;
+                   position.2 += velocity.2;
+               }
+           }),
+           measure(baseline_spawned, |world| {
+               world.query::<Position, Velocity>(|position, velocity| {
+                   position.0 += velocity.0;
+                   position.1 += velocity.1;
+                   position.2 += velocity.2;
+               });
+           }),
+           ENTITIES);
+
+    report("update",
+           measure(spawned, |ecs| {
+               ecs.advance_time(1.0 / 60.0);
+               ecs.update();
+           }),
+           measure(baseline_spawned, |world| {
+               let mut time = Time::new();
+               time.advance(1.0 / 60.0);
+               world.update::<Health>(|health| health.update(&time));
+           }),
+           ENTITIES);
+}
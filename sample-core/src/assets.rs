@@ -1,17 +1,22 @@
-use std::rc::Rc;
+use std::sync::Arc;
 use std::collections::HashMap;
 use std::path::Path;
 use crate::{
+    main_thread::MainThread,
     mesh::Mesh,
+    prefab::PrefabHandle,
     shader::Shader,
     texture::Texture,
 };
+
+/// Meshes, shaders, textures and prefabs, by the names scenes refer to them by.
 pub struct Assets {
-    meshes: HashMap<String, Rc<Mesh>>,
-    shaders: HashMap<String, Rc<Shader>>,
-    textures: HashMap<String, Rc<Texture>>,
-    //materials: Vec<Rc<Material>>,
-    //models: Vec<Rc<Model>>
+    meshes: HashMap<String, Arc<Mesh>>,
+    shaders: HashMap<String, Arc<Shader>>,
+    textures: HashMap<String, Arc<Texture>>,
+    prefabs: HashMap<String, PrefabHandle>,
+    //materials: Vec<Arc<Material>>,
+    //models: Vec<Arc<Model>>
 }
 
 impl Assets {
@@ -20,6 +25,7 @@ impl Assets {
             meshes: HashMap::new(),
             shaders: HashMap::new(),
             textures: HashMap::new(),
+            prefabs: HashMap::new(),
         }
     }
 
@@ -27,7 +33,55 @@ impl Assets {
         unimplemented!();
     }
 
-    pub fn get_mesh(&self, name: &str) -> Option<Rc<Mesh>> {
-        unimplemented!();
+    /// Adds a Mesh under its name, replacing any Mesh of the same name.
+    pub fn add_mesh(&mut self, mesh: Arc<Mesh>) {
+        self.meshes.insert(mesh.name().to_string(), mesh);
+    }
+
+    /// Gets a Mesh by its name.
+    pub fn get_mesh(&self, name: &str) -> Option<Arc<Mesh>> {
+        self.meshes.get(name).cloned()
+    }
+
+    /// Adds a Shader under its name, replacing any Shader of the same name.
+    pub fn add_shader(&mut self, shader: Arc<Shader>) {
+        self.shaders.insert(shader.name().to_string(), shader);
     }
-}
\ No newline at end of file
+
+    /// Gets a Shader by its name.
+    pub fn get_shader(&self, name: &str) -> Option<Arc<Shader>> {
+        self.shaders.get(name).cloned()
+    }
+
+    /// Adds a Texture under its path, replacing any Texture of the same path.
+    pub fn add_texture(&mut self, texture: Arc<Texture>) {
+        self.textures.insert(texture.path().to_string(), texture);
+    }
+
+    /// Gets a Texture by the path it was loaded from.
+    pub fn get_texture(&self, path: &str) -> Option<Arc<Texture>> {
+        self.textures.get(path).cloned()
+    }
+
+    /// Gets a Texture by its path, loading it first if it was not added yet.
+    pub fn load_texture(&mut self, path: &str, main_thread: &MainThread) -> Result<Arc<Texture>, String> {
+        if let Some(texture) = self.get_texture(path) {
+            return Ok(texture);
+        }
+
+        let texture = Arc::new(Texture::new(path, main_thread)?);
+        self.add_texture(texture.clone());
+        Ok(texture)
+    }
+
+    /// Gets a prefab by its path, loading it first if it was not loaded yet.
+    pub fn load_prefab(&mut self, path: &str) -> Result<PrefabHandle, String> {
+        if let Some(prefab) = self.prefabs.get(path) {
+            return Ok(prefab.clone());
+        }
+
+        let prefab = PrefabHandle::load(path)?;
+        self.prefabs.insert(path.to_string(), prefab.clone());
+        Ok(prefab)
+    }
+}
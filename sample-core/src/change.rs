@@ -0,0 +1,116 @@
+use std::ops::{Deref, DerefMut};
+
+/// When a Component was added and last changed.
+///
+/// Ticks count up every time a system runs, so comparing them to the
+/// tick a system last ran at tells if a Component changed since then.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComponentTicks {
+    added: u64,
+    changed: u64,
+}
+
+impl ComponentTicks {
+    /// Ticks of a Component added at the given tick.
+    pub(crate) fn new(tick: u64) -> Self {
+        ComponentTicks {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    /// Tick the Component was added at.
+    pub fn added(&self) -> u64 {
+        self.added
+    }
+
+    /// Tick the Component was last changed at, including when added.
+    pub fn changed(&self) -> u64 {
+        self.changed
+    }
+
+    /// Checks if the Component was added after the given tick.
+    pub fn is_added(&self, last_run: u64) -> bool {
+        self.added > last_run
+    }
+
+    /// Checks if the Component was added or changed after the given tick.
+    pub fn is_changed(&self, last_run: u64) -> bool {
+        self.changed > last_run
+    }
+
+    pub(crate) fn set_changed(&mut self, tick: u64) {
+        self.changed = tick;
+    }
+}
+
+/// Ticks used to detect changes while a system or query runs.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub struct Ticks {
+    /// Tick the reader last ran at. Changes after it are detected.
+    pub(crate) last_run: u64,
+    /// Tick changes are recorded at.
+    pub(crate) this_run: u64,
+}
+
+/// Mutable access to a Component, flagging it as changed when written.
+///
+/// Returned by ECS::get_component_mut() and by queries for `&mut C`.
+pub struct Mut<'a, C> {
+    value: &'a mut C,
+    component_ticks: &'a mut ComponentTicks,
+    ticks: Ticks,
+}
+
+impl<'a, C> Mut<'a, C> {
+    pub(crate) fn new(value: &'a mut C, component_ticks: &'a mut ComponentTicks, ticks: Ticks) -> Self {
+        Mut {
+            value,
+            component_ticks,
+            ticks,
+        }
+    }
+
+    /// Checks if the Component was added since the reader last ran.
+    pub fn is_added(&self) -> bool {
+        self.component_ticks.is_added(self.ticks.last_run)
+    }
+
+    /// Checks if the Component was added or changed since the reader last ran.
+    pub fn is_changed(&self) -> bool {
+        self.component_ticks.is_changed(self.ticks.last_run)
+    }
+
+    /// Flags the Component as changed without writing to it.
+    pub fn set_changed(&mut self) {
+        self.component_ticks.set_changed(self.ticks.this_run);
+    }
+
+    /// Writes to the Component without flagging it as changed,
+    /// e.g. to update a cache derived from other Components.
+    pub fn bypass_change_detection(&mut self) -> &mut C {
+        self.value
+    }
+
+    /// Flags the Component as changed and returns the plain reference.
+    pub fn into_inner(mut self) -> &'a mut C {
+        self.set_changed();
+        self.value
+    }
+}
+
+impl<C> Deref for Mut<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.value
+    }
+}
+
+impl<C> DerefMut for Mut<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.set_changed();
+        self.value
+    }
+}
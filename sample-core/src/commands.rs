@@ -0,0 +1,150 @@
+use crate::component::Component;
+use crate::ecs::{ECS, EntityId};
+
+/// A recorded change, applied with the ids of the Entities spawned so far.
+type Command = Box<dyn FnOnce(&mut ECS, &mut Vec<EntityId>) + Send>;
+
+/// Entity a command applies to.
+#[derive(Clone, Copy)]
+enum Target {
+    /// An Entity which already exists.
+    Existing(EntityId),
+    /// The nth Entity spawned by the same batch of commands.
+    Spawned(usize),
+}
+
+impl Target {
+    fn resolve(self, spawned: &[EntityId]) -> EntityId {
+        match self {
+            Target::Existing(id) => id,
+            Target::Spawned(index) => spawned[index],
+        }
+    }
+}
+
+/// Queue of structural changes to the ECS.
+///
+/// Scripts and systems record into it with ECS::commands(), and the ECS
+/// applies the changes in order after each stage of an update.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+    /// Number of Entities spawned by the queued commands.
+    spawn_count: usize,
+}
+
+impl Commands {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Commands::default()
+    }
+
+    /// Checks if there are no commands queued.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues spawning a new Entity.
+    ///
+    /// The returned EntityCommands queue changes to the Entity once spawned.
+    pub fn spawn(&mut self, name: &str) -> EntityCommands<'_> {
+        let name = name.to_string();
+        self.queue.push(Box::new(move |ecs, spawned| {
+            let id = ecs.new_entity(&name).id();
+            spawned.push(id);
+        }));
+
+        let target = Target::Spawned(self.spawn_count);
+        self.spawn_count += 1;
+        EntityCommands {
+            commands: self,
+            target,
+        }
+    }
+
+    /// Queues changes to an existing Entity.
+    pub fn entity(&mut self, id: EntityId) -> EntityCommands<'_> {
+        EntityCommands {
+            commands: self,
+            target: Target::Existing(id),
+        }
+    }
+
+    /// Queues deleting an Entity along with its Components.
+    pub fn despawn(&mut self, id: EntityId) {
+        self.entity(id).despawn();
+    }
+
+    /// Queues inserting a global resource.
+    pub fn insert_resource<T: Send + 'static>(&mut self, resource: T) {
+        self.add(move |ecs| {
+            ecs.insert_resource(resource);
+        });
+    }
+
+    /// Queues removing a global resource.
+    pub fn remove_resource<T: 'static>(&mut self) {
+        self.add(|ecs| {
+            ecs.remove_resource::<T>();
+        });
+    }
+
+    /// Queues any change to the ECS.
+    pub fn add<F: FnOnce(&mut ECS) + Send + 'static>(&mut self, f: F) {
+        self.queue.push(Box::new(move |ecs, _| f(ecs)));
+    }
+
+    /// Applies all queued commands in order, then empties the queue.
+    ///
+    /// Commands on Entities which were deleted in the meantime do nothing.
+    pub fn apply(self, ecs: &mut ECS) {
+        let mut spawned = Vec::with_capacity(self.spawn_count);
+        for command in self.queue {
+            command(ecs, &mut spawned);
+        }
+    }
+}
+
+/// Queues changes to a single Entity. Created by Commands::spawn()
+/// and Commands::entity().
+pub struct EntityCommands<'a> {
+    commands: &'a mut Commands,
+    target: Target,
+}
+
+impl<'a> EntityCommands<'a> {
+    /// Queues adding a Component, set up by f once created.
+    pub fn add_component<C: Component + 'static, F: Fn(&mut C) + Send + 'static>(self, f: F) -> EntityCommands<'a> {
+        let target = self.target;
+        self.commands.queue.push(Box::new(move |ecs, spawned| {
+            ecs.add_component::<C>(target.resolve(spawned), &f);
+        }));
+        self
+    }
+
+    /// Queues removing a Component.
+    pub fn remove_component<C: Component + 'static>(self) -> EntityCommands<'a> {
+        let target = self.target;
+        self.commands.queue.push(Box::new(move |ecs, spawned| {
+            ecs.remove_component::<C>(target.resolve(spawned));
+        }));
+        self
+    }
+
+    /// Queues making the Entity the child of another.
+    pub fn set_parent(self, parent: EntityId) -> EntityCommands<'a> {
+        let target = self.target;
+        self.commands.queue.push(Box::new(move |ecs, spawned| {
+            ecs.set_parent(target.resolve(spawned), parent);
+        }));
+        self
+    }
+
+    /// Queues deleting the Entity along with its Components.
+    pub fn despawn(self) {
+        let target = self.target;
+        self.commands.queue.push(Box::new(move |ecs, spawned| {
+            ecs.despawn(target.resolve(spawned));
+        }));
+    }
+}
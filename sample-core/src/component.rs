@@ -4,30 +4,120 @@ use crate::{
     mesh::Mesh,
     texture::Texture
 };
-use std::rc::Rc;
+use std::sync::Arc;
 use std::iter;
-use glam::{Vec3, Quat};
-use crate::ecs::Entity;
+use glam::{Vec3, Quat, Mat4};
+use crate::ecs::{ECS, Entity, EntityId};
+use crate::hierarchy::GlobalTransform;
+use crate::main_thread::MainThread;
+use crate::parallel::SystemView;
+use crate::query::{Changed, With};
+use crate::reflect::{self, Reflect};
+use crate::scene::{SceneComponent, SceneLoader, Value};
 use crate::scriptable::Scriptable;
+use crate::schedule::Stage;
+use crate::time::Time;
 use itertools::izip;
+use serde::{Deserialize, Serialize};
+use sdl2::event::Event;
 
 pub struct Material;
 
-pub trait Component {
+/// Data attached to an Entity.
+///
+/// Components are Send and Sync, so systems on other threads can use them.
+pub trait Component: Send + Sync {
+    /// Stage the updater of this Component runs in.
+    const STAGE: Stage = Stage::Update;
+
+    /// Whether update() runs as a parallel system, possibly at the same
+    /// time as the updaters of other Component types. update_all() is
+    /// not used then.
+    const PARALLEL: bool = false;
+
     fn create(_entity: &mut Entity) -> Self;
-    fn update(&mut self) {}
+    fn update(&mut self, _time: &Time) {}
+
+    /// Called when the Component is removed from its Entity,
+    /// including when the Entity is despawned.
+    fn destroy(&mut self, _entity: &mut Entity) {}
+
+    /// Hook called after a Component of this type is added to an Entity
+    /// which had none, before any observers.
+    fn on_add(_entity: &mut Entity) where Self: Sized {}
+
+    /// Hook called before a Component of this type is overwritten by
+    /// adding another one, while the old one is still reachable.
+    fn on_replace(_entity: &mut Entity) where Self: Sized {}
+
+    /// Hook called before a Component of this type is removed, while it
+    /// is still reachable. Runs before destroy() and any observers.
+    fn on_remove(_entity: &mut Entity) where Self: Sized {}
+
+    /// Registers the systems of this Component, when the first Component
+    /// of this type is added to the ECS.
+    ///
+    /// Adds update_all() to STAGE by default, labelled with the type name,
+    /// or a parallel system writing this type if PARALLEL is set.
+    fn register(ecs: &mut ECS) where Self: Sized + 'static {
+        let label = std::any::type_name::<Self>();
+        if Self::PARALLEL {
+            ecs.add_parallel_system(Self::STAGE, update_parallel::<Self>)
+                .writes::<Self>()
+                .label(label);
+        } else {
+            ecs.add_system(Self::STAGE, Self::update_all)
+                .label(label);
+        }
+    }
+
+    /// Updates every Component of this type in the ECS.
+    ///
+    /// Calls update() on each Component by default. Override this when
+    /// the update needs access to the Entity or the rest of the ECS.
+    /// Changes made by update() do not flag Components as changed.
+    fn update_all(ecs: &mut ECS) where Self: Sized + 'static {
+        let time = *ecs.time();
+        if let Some(storage) = ecs.storage_mut::<Self>() {
+            for comp in &mut storage.components {
+                comp.update(&time);
+            }
+        }
+    }
 }
 
+/// Calls update() on every Component of type C, from a parallel system.
+fn update_parallel<C: Component + 'static>(view: &mut SystemView) {
+    let time = *view.time();
+    for mut comp in view.query::<&mut C>() {
+        comp.bypass_change_detection().update(&time);
+    }
+}
+
+/// Meshes drawn with their shaders and textures.
+///
+/// Drawn from an exclusive system in the Render stage, as drawing takes
+/// the MainThread token. Nothing is drawn while the ECS has none.
+#[derive(Reflect)]
 pub struct MeshComponent {
-    meshes: Vec<Rc<Mesh>>,
-    textures: Vec<Rc<Texture>>,
-    shaders: Vec<Rc<Shader>>,
+    #[reflect(ignore)]
+    meshes: Vec<Arc<Mesh>>,
+    /// Texture of every mesh, if it has one.
+    #[reflect(ignore)]
+    textures: Vec<Option<Arc<Texture>>>,
+    #[reflect(ignore)]
+    shaders: Vec<Arc<Shader>>,
     transforms: Vec<Transform>,
+    /// World transformation of every mesh, recomputed when the
+    /// Component or the GlobalTransform of its Entity changes, or the
+    /// GlobalTransform is removed.
+    #[reflect(ignore)]
+    models: Vec<Mat4>,
 }
 
 impl MeshComponent {
     // TODO: Make this more refined, don't mass change
-    pub fn set_shader(&mut self, new_shader: Rc<Shader>) {
+    pub fn set_shader(&mut self, new_shader: Arc<Shader>) {
         self.shaders = self.shaders.splice(
             ..self.shaders.len(), 
             iter::once(new_shader).cycle())
@@ -57,86 +147,360 @@ impl MeshComponent {
     }
     
     pub fn add_mstm(&mut self, 
-                    mesh: Rc<Mesh>, 
-                    shader: Rc<Shader>, 
-                    texture: Option<Rc<Texture>>,
+                    mesh: Arc<Mesh>, 
+                    shader: Arc<Shader>, 
+                    texture: Option<Arc<Texture>>,
                     _material: Option<Material>) {
         self.meshes.push(mesh);
         self.shaders.push(shader);
-        if !texture.is_none() {
-            self.textures.push(texture.unwrap());
-        }
+        self.textures.push(texture);
         /*if !material.is_none() {
             self.materials.push(texture.unwrap());
         }*/
         self.transforms.push(Transform::new());
     }
 
-    pub fn textures(&self) -> &[Rc<Texture>]{
+    /// Texture of every mesh, None for meshes drawn without one.
+    pub fn textures(&self) -> &[Option<Arc<Texture>>]{
         &self.textures
     }
+
+    /// Draws every mesh, placed relative to the given world transform.
+    pub fn draw(&self, global: &Mat4, main_thread: &MainThread) {
+        let models: Vec<Mat4> = self.transforms.iter()
+            .map(|transform| *global * transform.transformation())
+            .collect();
+        self.draw_models(&models, main_thread);
+    }
+
+    /// Helper function for drawing every mesh with its world transformation.
+    fn draw_models(&self, models: &[Mat4], main_thread: &MainThread) {
+        let draw = izip!(
+            self.meshes.iter(),
+            self.textures.iter(),
+            self.shaders.iter(),
+            models.iter(),
+        );
+
+        for (mesh, texture, shader, model) in draw {
+            shader.set_model(model, main_thread);
+            if let Some(texture) = texture {
+                texture.enable(main_thread);
+            }
+            mesh.draw(main_thread);
+        }
+    }
 }
 
 impl Component for MeshComponent {
+    const STAGE: Stage = Stage::Render;
+
     fn create(_entity: &mut Entity) -> Self {
         MeshComponent {
             meshes: Vec::new(),
             textures: Vec::new(),
             shaders: Vec::new(),
             transforms: Vec::new(),
+            models: Vec::new(),
+        }
+    }
+
+    /// Draws every MeshComponent, placed by the GlobalTransform
+    /// of its Entity if it has one, or else by ECS::world_matrix().
+    ///
+    /// Model matrices are only recomputed for Components which changed
+    /// or whose Entity moved since the last frame.
+    fn update_all(ecs: &mut ECS) {
+        let mut dirty: Vec<EntityId> = ecs.query_filtered::<EntityId, (With<Self>, Changed<GlobalTransform>)>().collect();
+        dirty.extend(ecs.query_filtered::<EntityId, Changed<Self>>());
+
+        for id in dirty {
+            let global = ecs.get_component::<GlobalTransform>(id)
+                .map(GlobalTransform::matrix)
+                .unwrap_or_else(|| ecs.world_matrix(id));
+
+            let mut comp = ecs.get_component_mut::<Self>(id).unwrap();
+            let comp = comp.bypass_change_detection();
+            comp.models = comp.transforms.iter()
+                .map(|transform| global * transform.transformation())
+                .collect();
+        }
+
+        let main_thread = match ecs.main_thread() {
+            Some(main_thread) => main_thread,
+            None => return
+        };
+        if let Some(storage) = ecs.storage::<Self>() {
+            for comp in &storage.components {
+                comp.draw_models(&comp.models, &main_thread);
+            }
         }
     }
+}
 
-    fn update(&mut self) {
-        if self.textures.is_empty() {
-            let draw = izip!(
-                self.meshes.iter(),
-                self.shaders.iter(),
-                self.transforms.iter(),
-            );
+/// A mesh of a MeshComponent in a scene, referring to assets by name.
+#[derive(Serialize, Deserialize)]
+struct SceneMesh {
+    mesh: String,
+    shader: String,
+    #[serde(default)]
+    texture: Option<String>,
+    #[serde(default = "Transform::new")]
+    transform: Transform,
+}
 
-            for (mesh, shader, transform) in draw {
-                shader.set_transform(&transform);
-                mesh.draw();
+/// Saved as the names of its meshes, shaders and textures in Assets.
+impl SceneComponent for MeshComponent {
+    fn save(&self, _ecs: &ECS) -> Result<Value, String> {
+        let mut meshes = Vec::with_capacity(self.meshes.len());
+        for (i, (mesh, shader)) in self.meshes.iter().zip(&self.shaders).enumerate() {
+            if mesh.name().is_empty() || shader.name().is_empty() {
+                return Err(String::from("Meshes and shaders need names to be saved"));
             }
-        } else {
-            let draw = izip!(
-                self.meshes.iter(),
-                self.textures.iter(),
-                self.shaders.iter(),
-                self.transforms.iter(),
-            );
-
-            for (mesh, texture, shader, transform) in draw {
-                shader.set_transform(&transform);
-                texture.enable();
-                mesh.draw();
+
+            meshes.push(SceneMesh {
+                mesh: mesh.name().to_string(),
+                shader: shader.name().to_string(),
+                texture: self.textures[i].as_ref().map(|texture| texture.path().to_string()),
+                transform: self.transforms[i],
+            });
+        }
+
+        serde_json::to_value(meshes).map_err(|e| e.to_string())
+    }
+
+    fn load(value: Value, loader: &mut SceneLoader, id: EntityId) -> Result<(), String> {
+        let meshes: Vec<SceneMesh> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+
+        let main_thread = loader.ecs().main_thread();
+        let mut resolved = Vec::with_capacity(meshes.len());
+        for scene_mesh in meshes {
+            let assets = loader.assets();
+            let mesh = assets.get_mesh(&scene_mesh.mesh)
+                .ok_or_else(|| format!("Mesh {} is not in the Assets", scene_mesh.mesh))?;
+            let shader = assets.get_shader(&scene_mesh.shader)
+                .ok_or_else(|| format!("Shader {} is not in the Assets", scene_mesh.shader))?;
+            // Textures not in the Assets yet are uploaded, which takes the token.
+            let texture = match scene_mesh.texture {
+                Some(path) => match (assets.get_texture(&path), main_thread) {
+                    (Some(texture), _) => Some(texture),
+                    (None, Some(main_thread)) => Some(assets.load_texture(&path, &main_thread)?),
+                    (None, None) => return Err(format!("Texture {} needs the MainThread token to be loaded", path))
+                },
+                None => None
+            };
+            resolved.push((mesh, shader, texture, scene_mesh.transform));
+        }
+
+        let added = loader.ecs().add_component::<Self>(id, &|comp| {
+            for (mesh, shader, texture, transform) in &resolved {
+                comp.add_mstm(mesh.clone(), shader.clone(), texture.clone(), None);
+                *comp.transforms.last_mut().unwrap() = *transform;
             }
+        });
+
+        match added {
+            Some(_) => Ok(()),
+            None => Err(String::from("Entity was deleted while loading"))
         }
     }
 }
 
+/// Component running a Scriptable.
+///
+/// The script is taken out of the Component while one of its callbacks
+/// runs, so it can freely use the Entity it receives.
 #[derive(Debug)]
 pub struct ScriptComponent<T: Scriptable> {
-    script: T,
-    entity: *mut Entity
+    script: Option<T>,
+    /// Whether the script should be enabled.
+    enabled: bool,
+    /// Whether the script was last told it is enabled.
+    active: bool,
 }
 
-impl<T: Scriptable> Component for ScriptComponent<T> {
-    fn create(entity: &mut Entity) -> Self {
-        let mut comp = ScriptComponent {
-            script: T::create(),
-            entity: entity as *mut _
+impl<T: Scriptable> ScriptComponent<T> {
+    /// The script, unless one of its own callbacks is running.
+    pub fn script(&self) -> Option<&T> {
+        self.script.as_ref()
+    }
+
+    /// The mutable script, unless one of its own callbacks is running.
+    pub fn script_mut(&mut self) -> Option<&mut T> {
+        self.script.as_mut()
+    }
+
+    /// Whether the script is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables the script.
+    ///
+    /// on_enable() or on_disable() is called before the next update,
+    /// fixed update or event. Disabled scripts receive none of them.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+impl<T: Scriptable + 'static> ScriptComponent<T> {
+    /// Helper function for running a callback of the script of an Entity.
+    ///
+    /// Does nothing if the script is disabled, unless it still has to be
+    /// told so. If the callback removed the script's own Component, or
+    /// replaced it with a new one, on_destroy() is called on the script
+    /// before it is dropped.
+    ///
+    /// Taking the script out does not flag the Component as changed.
+    fn run(ecs: &mut ECS, id: EntityId, f: &dyn Fn(&mut T, &mut Entity)) {
+        let script = ecs.get_component_mut::<Self>(id)
+            .filter(|comp| comp.active)
+            .and_then(|mut comp| comp.bypass_change_detection().script.take());
+
+        if let Some(mut script) = script {
+            f(&mut script, &mut ecs.entity_unchecked(id));
+
+            // A Component added while the callback ran has a script of its own.
+            match ecs.get_component_mut::<Self>(id) {
+                Some(mut comp) if comp.script.is_none() => comp.bypass_change_detection().script = Some(script),
+                _ => script.on_destroy(&mut ecs.entity_unchecked(id))
+            }
+        }
+    }
+
+    /// Calls on_enable() or on_disable() if the script was enabled or disabled.
+    fn sync_enabled(ecs: &mut ECS, id: EntityId) {
+        let enabled = match ecs.get_component_mut::<Self>(id) {
+            Some(mut comp) if comp.enabled != comp.active => {
+                comp.bypass_change_detection().active = true;
+                comp.enabled
+            },
+            _ => return
         };
-        unsafe {
-            comp.script.on_create(&mut *comp.entity as &mut _);
+
+        if enabled {
+            Self::run(ecs, id, &|script, entity| script.on_enable(entity));
+        } else {
+            Self::run(ecs, id, &|script, entity| script.on_disable(entity));
+            if let Some(mut comp) = ecs.get_component_mut::<Self>(id) {
+                comp.bypass_change_detection().active = false;
+            }
+        }
+    }
+
+    /// Runs on_fixed_update() of every enabled script.
+    fn fixed_update_all(ecs: &mut ECS) {
+        let dt = ecs.time().fixed_step();
+        for id in ecs.entities_with::<Self>() {
+            Self::sync_enabled(ecs, id);
+            Self::run(ecs, id, &|script, entity| script.on_fixed_update(entity, dt));
+        }
+    }
+
+    /// Runs on_event() of every enabled script.
+    fn handle_event_all(ecs: &mut ECS, event: &Event) {
+        for id in ecs.entities_with::<Self>() {
+            Self::sync_enabled(ecs, id);
+            Self::run(ecs, id, &|script, entity| script.on_event(entity, event));
+        }
+    }
+}
+
+/// Reflected as whether the script is enabled, and the script itself
+/// unless one of its callbacks is running.
+impl<T: Scriptable + Reflect> Reflect for ScriptComponent<T> {
+    fn field_names(&self) -> Vec<String> {
+        match self.script {
+            Some(_) => vec![String::from("enabled"), String::from("script")],
+            None => vec![String::from("enabled")]
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        match name {
+            "enabled" => Some(&self.enabled),
+            "script" => self.script.as_ref().map(|script| script as &dyn Reflect),
+            _ => None
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match name {
+            "enabled" => Some(&mut self.enabled),
+            "script" => self.script.as_mut().map(|script| script as &mut dyn Reflect),
+            _ => None
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        reflect::struct_to_value(self)
+    }
+
+    fn set_value(&mut self, value: Value) -> Result<(), String> {
+        reflect::struct_set_value(self, value)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Saved as whether the script is enabled. The script itself is made
+/// by create(), as when added by hand.
+impl<T: Scriptable + 'static> SceneComponent for ScriptComponent<T> {
+    fn save(&self, _ecs: &ECS) -> Result<Value, String> {
+        Ok(serde_json::json!({ "enabled": self.enabled }))
+    }
+
+    fn load(value: Value, loader: &mut SceneLoader, id: EntityId) -> Result<(), String> {
+        let enabled = value.get("enabled")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+
+        match loader.ecs().add_component::<Self>(id, &|comp| comp.set_enabled(enabled)) {
+            Some(_) => Ok(()),
+            None => Err(String::from("Entity was deleted while loading"))
         }
-        comp
     }
+}
 
-    fn update(&mut self) {
-        unsafe {
-            self.script.on_update(&mut *self.entity as &mut _);
+impl<T: Scriptable + 'static> Component for ScriptComponent<T> {
+    fn create(entity: &mut Entity) -> Self {
+        let mut script = T::create();
+        script.on_create(entity);
+        script.on_enable(entity);
+        ScriptComponent {
+            script: Some(script),
+            enabled: true,
+            active: true,
+        }
+    }
+
+    fn destroy(&mut self, entity: &mut Entity) {
+        if let Some(script) = self.script.as_mut() {
+            script.on_destroy(entity);
+        }
+    }
+
+    fn register(ecs: &mut ECS) {
+        let label = std::any::type_name::<Self>();
+        ecs.add_system(Self::STAGE, Self::update_all)
+            .label(label);
+        ecs.add_system(Stage::FixedUpdate, Self::fixed_update_all)
+            .label(label);
+        ecs.add_event_handler(Self::handle_event_all);
+    }
+
+    fn update_all(ecs: &mut ECS) {
+        for id in ecs.entities_with::<Self>() {
+            Self::sync_enabled(ecs, id);
+            let time = *ecs.time();
+            Self::run(ecs, id, &|script, entity| script.on_update(entity, &time));
         }
     }
 }
@@ -146,4 +510,41 @@ pub mod components {
         MeshComponent,
         ScriptComponent
     };
-}
\ No newline at end of file
+    pub use crate::hierarchy::{
+        TransformComponent,
+        GlobalTransform,
+        Parent,
+        Children
+    };
+    pub use crate::tag::Tags;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hierarchy::TransformComponent;
+
+    #[test]
+    fn models_follow_the_entity_without_a_global_transform() {
+        let mut ecs = ECS::new();
+        let parent = ecs.new_entity("Parent").id();
+        ecs.add_component::<TransformComponent>(parent, &|comp| comp.local.translation = Vec3::X);
+        let mut entity = ecs.new_entity("Ship");
+        entity.add_component::<TransformComponent>(&|comp| comp.local.translation = Vec3::Y);
+        entity.add_component::<MeshComponent>(&|comp| {
+            comp.add_mstm(Arc::new(Mesh::unloaded("Quad")), Arc::new(Shader::unloaded("default")), None, None);
+        });
+        let id = entity.id();
+        ecs.set_parent(id, parent);
+
+        let translation = |ecs: &ECS| ecs.get_component::<MeshComponent>(id).unwrap().models[0].w_axis.truncate();
+        ecs.update();
+        assert!(translation(&ecs).abs_diff_eq(Vec3::Y, 1e-6));
+
+        // Placed by the transforms of the Entity and its parent instead.
+        ecs.remove_component::<GlobalTransform>(id);
+        ecs.get_component_mut::<TransformComponent>(parent).unwrap().local.translation = Vec3::Z;
+        ecs.update();
+        assert!(translation(&ecs).abs_diff_eq(Vec3::new(-1.0, 1.0, 1.0), 1e-6));
+    }
+}
@@ -1,163 +1,1310 @@
 use std::any::TypeId;
 use std::any::Any;
-use std::collections::HashMap;
-use crate::component::Component;
-use crate::types::Id;
+use std::collections::{HashMap, HashSet};
+use crate::change::{ComponentTicks, Mut, Ticks};
+use crate::commands::Commands;
+use crate::component::{Component, MeshComponent};
+use crate::observer::{Lifecycle, Observer, Observers};
+use crate::parallel::{self, Job, ParallelSystem};
+use crate::query::{Query, QueryData, QueryFilter, StorageSource, Storages};
+use crate::main_thread::MainThread;
+use crate::hierarchy::{Children, GlobalTransform, Parent, TransformComponent};
+use crate::schedule::{Schedule, Stage, System, SystemBuilder};
+use crate::snapshot::{Snapshot, Snapshotters, WorldSnapshot};
+use crate::storage::ComponentStorage;
+use crate::tag::Tags;
+use crate::time::Time;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use sdl2::event::Event;
 //use crate::instance::GameInstance;
 
+/// Handle to an Entity in the ECS.
+///
+/// The index points at a slot in the ECS, and the generation is bumped
+/// every time the slot is freed, so handles to deleted Entities never
+/// point at an Entity that later reuses the slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        EntityId {
+            index: index,
+            generation: generation,
+        }
+    }
+
+    /// Index of the slot the Entity lives in.
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    /// Generation of the slot at the time the Entity was created.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Data the ECS keeps for every Entity.
+#[derive(Clone)]
+pub(crate) struct EntityData {
+    /// Name of Entity
+    pub(crate) name: String,
+}
+
+/// A slot in the list of Entities.
+#[derive(Clone)]
+pub(crate) struct EntitySlot {
+    /// Current generation of the slot.
+    pub(crate) generation: u32,
+    /// The Entity occupying the slot, if any.
+    pub(crate) data: Option<EntityData>,
+}
+
 pub struct ECS {
-    /// Lists of all Components that exist in the system.
-    components: HashMap<TypeId, Box<dyn Any>>,
+    /// Sparse sets of all Components that exist in the system.
+    components: Storages,
     /// Hashmap of Ids by Entity name
-    entity_ids: HashMap<String, Id>,
-    /// List of Entities. Index is their Id.
-    entities: Vec<Entity>,
-    /// List of all updaters of each individual Component.
-    updaters: Vec<fn(&mut HashMap<TypeId, Box<dyn Any>>)>,
+    entity_ids: HashMap<String, EntityId>,
+    /// List of Entity slots. Index is the index of their EntityId.
+    entities: Vec<EntitySlot>,
+    /// Indices of free Entity slots, reused before growing the list.
+    free: Vec<u32>,
+    /// Systems run on update, including the updaters of each individual Component.
+    schedule: Schedule,
+    /// Functions removing a Component of each type from an Entity, in the
+    /// order the types were first added.
+    removers: Vec<(TypeId, Remover)>,
+    /// Functions passing events to Components.
+    event_handlers: Vec<fn(&mut ECS, &Event)>,
+    /// Types of the event queues whose buffers are swapped by a system,
+    /// kept apart from the queues, which can be removed.
+    event_types: HashSet<TypeId>,
+    /// Functions called when Components are added, replaced or removed.
+    observers: Observers,
+    /// Global resources, one of each type.
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    /// Structural changes queued until the end of the current stage.
+    commands: Commands,
+    /// Thread pool parallel systems run on, the global one of rayon if None.
+    thread_pool: Option<ThreadPool>,
+    /// Token for GL calls, set by the owner of the GL context. It also
+    /// keeps the ECS on the main thread.
+    main_thread: Option<MainThread>,
+    /// Functions copying the Components and resources saved in snapshots.
+    snapshotters: Snapshotters,
+    /// Tick changes to Components are recorded at. Bumped around every system run.
+    change_tick: u64,
+    /// Tick the running system last ran at, or the end of the last update
+    /// outside of systems. Changes after it are detected.
+    last_run: u64,
     //instance: Box<GameInstance>
 }
 
+/// Function removing a Component of a single type from an Entity.
+type Remover = fn(&mut ECS, EntityId);
+
+/// Deletes the GL objects dropped since the last frame, once the ECS has
+/// the MainThread token.
+fn delete_dropped(ecs: &mut ECS) {
+    if let Some(main_thread) = ecs.main_thread() {
+        main_thread.delete_dropped();
+    }
+}
+
 impl ECS {
     /// Creates a new ECS system.
+    ///
+    /// The ECS starts out with a Time resource, and a Render system
+    /// labelled "delete_dropped" deleting the GL objects of dropped
+    /// meshes, shaders and textures on the main thread. Time, Tags and the
+    /// hierarchy Components are saved in snapshots, and Tags, Parents,
+    /// TransformComponents and MeshComponents in scenes. Tags,
+    /// TransformComponents and MeshComponents are reflected.
+    ///
+    /// Nothing is drawn until the MainThread token is given with
+    /// set_main_thread().
     pub fn new() -> Self {
-        ECS {
+        let mut ecs = ECS {
             components: HashMap::new(),
             entity_ids: HashMap::new(),
             entities: Vec::new(),
-            updaters: Vec::new(),
+            free: Vec::new(),
+            schedule: Schedule::new(),
+            removers: Vec::new(),
+            event_handlers: Vec::new(),
+            event_types: HashSet::new(),
+            observers: Observers::default(),
+            resources: HashMap::new(),
+            commands: Commands::new(),
+            thread_pool: None,
+            main_thread: None,
+            snapshotters: Snapshotters::default(),
+            change_tick: 1,
+            last_run: 0,
             //instance: Box::new(GameInstance::new())
-        }
+        };
+        ecs.insert_resource(Time::new());
+        ecs.register_resource_snapshot::<Time>();
+        ecs.register_snapshot::<Tags>();
+        ecs.register_snapshot::<TransformComponent>();
+        ecs.register_snapshot::<GlobalTransform>();
+        ecs.register_snapshot::<Parent>();
+        ecs.register_snapshot::<Children>();
+        ecs.register_component::<Tags>("Tags");
+        // Parents first, as set_parent() changes the local transform.
+        ecs.register_scene_component::<Parent>("Parent");
+        ecs.register_component::<TransformComponent>("Transform");
+        ecs.register_component::<MeshComponent>("Mesh");
+        ecs.add_system(Stage::Render, delete_dropped)
+            .label("delete_dropped");
+        ecs
     }
 
-    /// Helper function for adding a Component.
+    /// Adds a Component to an Entity.
     ///
     /// When adding a component, it first checks to see if any Component Vec is available,
     /// and if not, then inserts a new Vec of the associated Vec.
-    /// Then, it also inserts an updater function for the associated Component,
-    /// which is will be run on update.
-    fn add_component<C: Component + 'static>(&mut self, entity: &mut Entity, data_type: TypeId) -> (Id, &mut C) {
+    /// Then, it also registers the systems of the associated Component.
+    ///
+    /// Runs the on_replace() hook and observers before overwriting an
+    /// existing Component, or the on_add() hook and observers after
+    /// adding a new one.
+    ///
+    /// Returns None if the Entity was deleted.
+    pub fn add_component<C: Component + 'static>(&mut self, id: EntityId, f: &dyn Fn(&mut C)) -> Option<&mut C> {
+        if !self.is_alive(id) {
+            return None;
+        }
+
+        let mut comp = C::create(&mut Entity { ecs: self, id: id });
+        f(&mut comp);
+
+        let data_type = TypeId::of::<C>();
         if !self.components.contains_key(&data_type) {
-            self.components.insert(data_type, Box::new(Vec::new() as Vec<C>));
-            self.updaters.push(ECS::updater::<C>);
+            self.components.insert(data_type, Box::new(ComponentStorage::<C>::new()));
+            C::register(self);
+            self.removers.push((data_type, |ecs, id| {
+                ecs.remove_component::<C>(id);
+            }));
+        }
+
+        // Component creation may have deleted the Entity.
+        if !self.is_alive(id) {
+            return None;
         }
 
-        let comps = self.components.get_mut(&data_type)
-            .unwrap()
-            .as_mut()
-            .downcast_mut::<Vec<C>>()
-            .unwrap();
+        let replacing = self.storage::<C>().unwrap().get(id).is_some();
+        if replacing {
+            self.trigger(Lifecycle::Replace, C::on_replace, TypeId::of::<C>(), id);
+            if !self.is_alive(id) {
+                return None;
+            }
+        }
 
-        comps.push(C::create(entity));
-        let index = comps.len() - 1;
-        let comp = comps.get_mut(index).unwrap();
+        let tick = self.change_tick;
+        self.storage_mut::<C>().unwrap().insert(id, comp, tick);
+        if !replacing {
+            self.trigger(Lifecycle::Add, C::on_add, TypeId::of::<C>(), id);
+        }
 
-        (index, comp)
+        self.get_component_mut::<C>(id).map(Mut::into_inner)
     }
 
-    /// Helper function for getting a Component.
-    fn get_component<C: Component + 'static>(&mut self, comp_id: TypeId, index: Id) -> Option<&mut C> {
-        let comps = self.components
-            .get_mut(&comp_id)
-            .unwrap()
-            .as_mut()
-            .downcast_mut::<Vec<C>>()
-            .unwrap();
+    /// Gets a Component of an Entity.
+    pub fn get_component<C: Component + 'static>(&self, id: EntityId) -> Option<&C> {
+        self.storage::<C>()?.get(id)
+    }
 
-        comps.get_mut(index)
+    /// Gets a mutable Component of an Entity.
+    ///
+    /// Writing to the Component flags it as changed.
+    pub fn get_component_mut<C: Component + 'static>(&mut self, id: EntityId) -> Option<Mut<'_, C>> {
+        let ticks = self.ticks();
+        self.storage_mut::<C>()?.get_mut(id, ticks)
     }
 
-    /// Adds a new Entity to the system.
-    pub fn new_entity(&mut self, name: &str) -> &mut Entity {
-        let id = self.entities.len();
-        let entity = Entity::new(name, self as *mut _);
-        self.entity_ids.insert(name.to_string(), id);
-        self.entities.push(entity);
-        self.entities.get_mut(id).unwrap()
+    /// Gets the change ticks of a Component of an Entity.
+    pub fn component_ticks<C: Component + 'static>(&self, id: EntityId) -> Option<ComponentTicks> {
+        self.storage::<C>()?.ticks(id)
     }
 
-    /// Updates the ECS.
-    pub fn update(&mut self) {
-        for updater in &mut self.updaters {
-            updater(&mut self.components);
+    /// Checks if a Component of an Entity was added since the running
+    /// system last ran, or outside of systems, since the last update.
+    pub fn is_added<C: Component + 'static>(&self, id: EntityId) -> bool {
+        self.component_ticks::<C>(id)
+            .is_some_and(|ticks| ticks.is_added(self.last_run))
+    }
+
+    /// Checks if a Component of an Entity was added or changed since the
+    /// running system last ran, or outside of systems, since the last update.
+    pub fn is_changed<C: Component + 'static>(&self, id: EntityId) -> bool {
+        self.component_ticks::<C>(id)
+            .is_some_and(|ticks| ticks.is_changed(self.last_run))
+    }
+
+    /// Removes a Component from an Entity and returns it.
+    ///
+    /// The on_remove() hook and observers run first, then the last
+    /// Component of the type is swapped into the freed spot, and the
+    /// Component's destroy() is run while the Entity still exists.
+    pub fn remove_component<C: Component + 'static>(&mut self, id: EntityId) -> Option<C> {
+        self.storage::<C>()?.get(id)?;
+
+        self.trigger(Lifecycle::Remove, C::on_remove, TypeId::of::<C>(), id);
+        let mut comp = self.storage_mut::<C>()?.remove(id)?;
+        comp.destroy(&mut Entity { ecs: self, id });
+        Some(comp)
+    }
+
+    /// Ids of all Entities with a Component of type C.
+    pub fn entities_with<C: Component + 'static>(&self) -> Vec<EntityId> {
+        match self.storage::<C>() {
+            Some(storage) => storage.owners.clone(),
+            None => Vec::new()
         }
     }
-    
-    /// Used for updating the components, pushed in AddComponent().
-    fn updater<C: Component + 'static>(map: &mut HashMap<TypeId, Box<dyn Any>>) {
-        let data_type = TypeId::of::<C>();
-        let comps = map.get_mut(&data_type)
-            .unwrap()
-            .as_mut()
-            .downcast_mut::<Vec<C>>()
-            .unwrap();
 
-        for comp in comps {
-            comp.update();
+    /// Iterates over all Entities having the Components in Q,
+    /// e.g. `ecs.query::<(&mut MeshComponent, &ScriptComponent<Plane>)>()`.
+    ///
+    /// Panics if Q accesses a Component mutably more than once.
+    pub fn query<'w, Q: QueryData<'w>>(&'w mut self) -> Query<'w, Q> {
+        let ticks = self.ticks();
+        Query::new(&self.entities, StorageSource::new(&mut self.components, None), ticks)
+    }
+
+    /// Iterates over all Entities having the Components in Q and passing
+    /// the filter F, made of With, Without, Added and Changed.
+    ///
+    /// Panics if Q accesses a Component mutably more than once.
+    pub fn query_filtered<'w, Q: QueryData<'w>, F: QueryFilter>(&'w mut self) -> Query<'w, Q, F> {
+        let ticks = self.ticks();
+        Query::new(&self.entities, StorageSource::new(&mut self.components, None), ticks)
+    }
+
+    /// Helper function for getting the storage of a Component type.
+    pub(crate) fn storage<C: Component + 'static>(&self) -> Option<&ComponentStorage<C>> {
+        self.components
+            .get(&TypeId::of::<C>())?
+            .as_any()
+            .downcast_ref::<ComponentStorage<C>>()
+    }
+
+    /// Helper function for getting the mutable storage of a Component type.
+    pub(crate) fn storage_mut<C: Component + 'static>(&mut self) -> Option<&mut ComponentStorage<C>> {
+        self.components
+            .get_mut(&TypeId::of::<C>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<C>>()
+    }
+
+    /// Adds a new Entity to the system.
+    ///
+    /// Names are unique: if the name is taken, a suffix is added, e.g.
+    /// "Enemy (1)". Slots of deleted Entities are reused, with their
+    /// generation bumped.
+    pub fn new_entity(&mut self, name: &str) -> Entity<'_> {
+        let id = match self.free.pop() {
+            Some(index) => EntityId {
+                index: index,
+                generation: self.entities[index as usize].generation,
+            },
+            None => {
+                self.entities.push(EntitySlot {
+                    generation: 0,
+                    data: None,
+                });
+                EntityId {
+                    index: (self.entities.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        };
+
+        let name = self.unique_name(name);
+        self.entity_ids.insert(name.clone(), id);
+        self.entities[id.index()].data = Some(EntityData {
+            name,
+        });
+
+        Entity {
+            ecs: self,
+            id: id,
         }
     }
-}
 
-pub struct Entity {
-    /// Name of Entity
-    name: String,
-    /// Hashmap of Ids defined by TypeId
-    comp_index: HashMap<TypeId, Id>,
-    /// Pointer to ECS system
-    ecs: *mut ECS,
-}
+    /// Ids of all living Entities, in the order of their slots.
+    pub fn entities(&self) -> Vec<EntityId> {
+        self.entities.iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.data.is_some())
+            .map(|(index, slot)| EntityId::new(index as u32, slot.generation))
+            .collect()
+    }
+
+    /// Checks if the Entity behind the id still exists.
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.data(id).is_some()
+    }
 
-impl Entity {
-    /// Helper function to create a new Entity.
-    fn new(name: &str, ecs: *mut ECS) -> Self {
+    /// Gets an Entity by its id.
+    ///
+    /// Returns None if the Entity was deleted.
+    pub fn entity(&mut self, id: EntityId) -> Option<Entity<'_>> {
+        if !self.is_alive(id) {
+            return None;
+        }
+
+        Some(Entity {
+            ecs: self,
+            id: id,
+        })
+    }
+
+    /// Helper function for creating a view of an Entity which may be deleted.
+    pub(crate) fn entity_unchecked(&mut self, id: EntityId) -> Entity<'_> {
         Entity {
-            name: name.to_string(),
-            comp_index: HashMap::new(),
-            ecs: ecs,
+            ecs: self,
+            id,
         }
     }
 
-    /// Add a Component to the Entity.
-    pub fn add_component<C: Component + 'static>(&mut self, f: &dyn Fn(&mut C)) {
-        let data_type = TypeId::of::<C>(); 
-        let (index, comp) = unsafe {
-            self.ecs.as_mut().unwrap().add_component::<C>(self, data_type)
-        };
+    /// Gets the id of an Entity by its name.
+    pub fn entity_id(&self, name: &str) -> Option<EntityId> {
+        self.entity_ids.get(name).copied()
+    }
 
-        self.comp_index.insert(data_type, index);
+    /// Finds an Entity by its name, e.g. `ecs.find_by_name("Player")`.
+    pub fn find_by_name(&mut self, name: &str) -> Option<Entity<'_>> {
+        let id = self.entity_id(name)?;
+        self.entity(id)
+    }
 
-        f(comp);
+    /// Gets the name of an Entity.
+    pub fn entity_name(&self, id: EntityId) -> Option<&str> {
+        self.data(id).map(|data| data.name.as_str())
     }
 
-    /// Get a Component from the Entity.
-    pub fn get_component<C: Component + 'static>(&self) -> Option<&mut C> {
-        let data_type = TypeId::of::<C>(); 
-        let index = self.comp_index.get(&data_type);
-        if index.is_none() {
+    /// Renames an Entity, adding a suffix if the name is taken by another.
+    ///
+    /// Returns the new name, or None if the Entity was deleted.
+    pub fn set_entity_name(&mut self, id: EntityId, name: &str) -> Option<&str> {
+        let old = self.data(id)?.name.clone();
+        if old == name {
+            return self.entity_name(id);
+        }
+
+        self.entity_ids.remove(&old);
+        let name = self.unique_name(name);
+        self.entity_ids.insert(name.clone(), id);
+        self.entities[id.index()].data.as_mut().unwrap().name = name;
+        self.entity_name(id)
+    }
+
+    /// Helper function for suffixing a name until no Entity has it.
+    fn unique_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut suffix = 1;
+        while self.entity_ids.contains_key(&unique) {
+            unique = format!("{} ({})", name, suffix);
+            suffix += 1;
+        }
+
+        unique
+    }
+
+    /// Deletes an Entity from the system, along with all of its Components.
+    ///
+    /// Components are removed in the order their types were first added to
+    /// the ECS, apart from Children, which go first. Returns false if the
+    /// Entity was already deleted.
+    pub fn despawn(&mut self, id: EntityId) -> bool {
+        if !self.is_alive(id) {
+            return false;
+        }
+
+        // Children are detached while the transform of the Entity is still
+        // there, so they keep their world transform.
+        self.remove_component::<Children>(id);
+
+        // Components may add others when destroyed, so loop until none are left.
+        loop {
+            if !self.is_alive(id) {
+                return true;
+            }
+
+            let remover = self.removers.iter()
+                .find(|(data_type, _)| self.components[data_type].contains(id))
+                .map(|(_, remover)| *remover);
+
+            match remover {
+                Some(remover) => remover(self, id),
+                None => break
+            }
+        }
+
+        let slot = &mut self.entities[id.index()];
+        let data = slot.data.take().unwrap();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(id.index);
+
+        if self.entity_ids.get(&data.name) == Some(&id) {
+            self.entity_ids.remove(&data.name);
+        }
+
+        true
+    }
+
+    /// Helper function for getting the data of a living Entity.
+    fn data(&self, id: EntityId) -> Option<&EntityData> {
+        let slot = self.entities.get(id.index())?;
+        if slot.generation != id.generation {
             return None;
         }
 
-        unsafe {
-            self.ecs
-                .as_mut()
-                .unwrap()
-                .get_component::<C>(data_type, *index.unwrap())
+        slot.data.as_ref()
+    }
+
+    /// Adds a system to run every update in the given stage.
+    ///
+    /// The returned builder can label the system and order it
+    /// before or after other labelled systems of the stage.
+    pub fn add_system(&mut self, stage: Stage, system: System) -> SystemBuilder<'_> {
+        self.schedule.add_system(stage, system)
+    }
+
+    /// Adds a function called when a Component of type C reaches a point
+    /// in its Lifecycle, after the hook of the Component type.
+    ///
+    /// e.g. `ecs.observe::<Collider>(Lifecycle::Add, |ecs, id| ...)`
+    pub fn observe<C: Component + 'static>(&mut self, lifecycle: Lifecycle, observer: Observer) {
+        self.observers.add(TypeId::of::<C>(), lifecycle, observer);
+    }
+
+    /// Helper function for running the hook and observers of a Lifecycle point.
+    fn trigger(&mut self, lifecycle: Lifecycle, hook: fn(&mut Entity), data_type: TypeId, id: EntityId) {
+        hook(&mut self.entity_unchecked(id));
+        for observer in self.observers.get(data_type, lifecycle) {
+            observer(self, id);
+        }
+    }
+
+    /// Adds a system to a stage, running on the thread pool at the same
+    /// time as other parallel systems it does not conflict with.
+    ///
+    /// The Components it uses are declared with reads() and writes(),
+    /// e.g. `ecs.add_parallel_system(Stage::Update, step).writes::<Position>().reads::<Velocity>()`.
+    pub fn add_parallel_system(&mut self, stage: Stage, system: ParallelSystem) -> SystemBuilder<'_> {
+        self.schedule.add_parallel_system(stage, system)
+    }
+
+    /// Records that the event queue of the given type has its system,
+    /// returning false if it already had one.
+    pub(crate) fn register_event_type(&mut self, data_type: TypeId) -> bool {
+        self.event_types.insert(data_type)
+    }
+
+    /// Token for making GL calls, like drawing meshes, from exclusive
+    /// systems. None until set with set_main_thread().
+    pub fn main_thread(&self) -> Option<MainThread> {
+        self.main_thread
+    }
+
+    /// Gives the ECS the token for GL calls, which makes it draw.
+    pub fn set_main_thread(&mut self, main_thread: MainThread) {
+        self.main_thread = Some(main_thread);
+    }
+
+    /// Runs parallel systems on a thread pool of the given size, owned by
+    /// the ECS, instead of the global thread pool.
+    ///
+    /// Exclusive systems keep running on the thread calling update().
+    pub fn set_threads(&mut self, threads: usize) {
+        self.thread_pool = Some(ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("Could not create the thread pool"));
+    }
+
+    /// Adds a function called with every event passed to handle_event().
+    pub fn add_event_handler(&mut self, handler: fn(&mut ECS, &Event)) {
+        self.event_handlers.push(handler);
+    }
+
+    /// Passes an event to all event handlers.
+    pub fn handle_event(&mut self, event: &Event) {
+        for i in 0..self.event_handlers.len() {
+            let handler = self.event_handlers[i];
+            handler(self, event);
+        }
+    }
+
+    /// Inserts a global resource, returning the previous one of the same type.
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .map(|old| *old.downcast::<T>().unwrap())
+    }
+
+    /// Removes a global resource and returns it.
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .map(|old| *old.downcast::<T>().unwrap())
+    }
+
+    /// Checks if a global resource of type T exists.
+    pub fn contains_resource<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Gets a global resource.
+    pub fn resource<T: 'static>(&self) -> Option<&T> {
+        self.resources
+            .get(&TypeId::of::<T>())?
+            .downcast_ref::<T>()
+    }
+
+    /// Gets a mutable global resource.
+    pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut::<T>()
+    }
+
+    /// Runs a function with a global resource taken out of the ECS,
+    /// so both can be used mutably at the same time.
+    ///
+    /// Returns None if there is no resource of type T.
+    pub fn resource_scope<T: 'static, R>(&mut self, f: impl FnOnce(&mut ECS, &mut T) -> R) -> Option<R> {
+        let mut resource = self.remove_resource::<T>()?;
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        Some(result)
+    }
+
+    /// Queue of structural changes, applied after the current stage.
+    pub fn commands(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+
+    /// Applies all queued commands, including ones queued while applying.
+    pub fn apply_commands(&mut self) {
+        while !self.commands.is_empty() {
+            let commands = std::mem::take(&mut self.commands);
+            commands.apply(self);
+        }
+    }
+
+    /// Frame timing of the ECS.
+    ///
+    /// If the Time resource was removed, this is a Time that never
+    /// advanced, so updates see no time pass.
+    pub fn time(&self) -> &Time {
+        self.resource::<Time>().unwrap_or(&Time::START)
+    }
+
+    /// Mutable frame timing of the ECS, e.g. to pause or scale time.
+    ///
+    /// Inserts a new Time if the Time resource was removed.
+    pub fn time_mut(&mut self) -> &mut Time {
+        if !self.contains_resource::<Time>() {
+            self.insert_resource(Time::new());
+        }
+        self.resource_mut::<Time>().unwrap()
+    }
+
+    /// Advances time by the real duration of the last frame, in seconds,
+    /// then runs a fixed update for every fixed step that passed.
+    pub fn advance_time(&mut self, raw_delta: f32) {
+        self.time_mut().advance(raw_delta);
+        while self.time_mut().consume_fixed_step() {
+            self.fixed_update();
+        }
+    }
+
+    /// Runs the systems of the FixedUpdate stage once.
+    pub fn fixed_update(&mut self) {
+        self.run_stages(&[Stage::FixedUpdate]);
+    }
+
+    /// Updates the ECS, running every stage of systems in order.
+    pub fn update(&mut self) {
+        self.run_stages(&Stage::FRAME);
+    }
+
+    /// Runs frames of a fixed length, each advancing time, running the
+    /// fixed updates, then updating, like the game loop.
+    ///
+    /// No real time is read, so stepping a restored snapshot by the same
+    /// frames always ends in the same state.
+    pub fn step(&mut self, frames: u32, raw_delta: f32) {
+        for _ in 0..frames {
+            self.advance_time(raw_delta);
+            self.update();
+        }
+    }
+
+    /// Saves Components of type C in snapshots.
+    pub fn register_snapshot<C: Component + Snapshot>(&mut self) {
+        self.snapshotters.add_component::<C>();
+    }
+
+    /// Saves the resource of type T in snapshots.
+    pub fn register_resource_snapshot<T: Snapshot>(&mut self) {
+        self.snapshotters.add_resource::<T>();
+    }
+
+    /// Saves the Entities, the Components and resources of every
+    /// registered type, and the change ticks.
+    ///
+    /// Meant to be taken between updates, as systems are not reachable
+    /// while they run.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            entities: self.entities.clone(),
+            free: self.free.clone(),
+            entity_ids: self.entity_ids.clone(),
+            components: self.components.iter()
+                .filter_map(|(data_type, storage)| {
+                    Some((*data_type, self.snapshotters.component(*data_type, storage.as_ref())?))
+                })
+                .collect(),
+            resources: self.resources.iter()
+                .filter_map(|(data_type, resource)| {
+                    Some((*data_type, self.snapshotters.resource(*data_type, resource.as_ref())?))
+                })
+                .collect(),
+            change_tick: self.change_tick,
+            last_run: self.last_run,
+            system_ticks: self.schedule.ticks(),
         }
     }
 
+    /// Puts the ECS back into the state of a snapshot.
+    ///
+    /// Components and resources of registered types are replaced by the
+    /// saved ones, or dropped if none were saved. Components of other
+    /// types are kept, except those of Entities which do not exist in
+    /// the snapshot. Queued commands are dropped, and no hooks or
+    /// observers run.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        self.commands = Commands::new();
+        self.entities = snapshot.entities.clone();
+        self.free = snapshot.free.clone();
+        self.entity_ids = snapshot.entity_ids.clone();
+
+        let entities = &self.entities;
+        let alive = |id: EntityId| entities.get(id.index())
+            .is_some_and(|slot| slot.generation == id.generation && slot.data.is_some());
+        for (data_type, storage) in self.components.iter_mut() {
+            if !self.snapshotters.has_component(*data_type) {
+                storage.retain(&alive);
+                continue;
+            }
+
+            match snapshot.components.get(data_type) {
+                Some(saved) => *storage = self.snapshotters.component(*data_type, saved.as_ref()).unwrap(),
+                None => storage.retain(&|_| false)
+            }
+        }
+
+        for data_type in self.snapshotters.resource_types() {
+            match snapshot.resources.get(data_type) {
+                Some(saved) => {
+                    let resource = self.snapshotters.resource(*data_type, saved.as_ref()).unwrap();
+                    self.resources.insert(*data_type, resource);
+                }
+                None => {
+                    self.resources.remove(data_type);
+                }
+            }
+        }
+
+        self.change_tick = snapshot.change_tick;
+        self.last_run = snapshot.last_run;
+        self.schedule.set_ticks(&snapshot.system_ticks);
+    }
+
+    /// Current change tick of the ECS.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    /// Helper function for getting the ticks of the current reader.
+    fn ticks(&self) -> Ticks {
+        Ticks {
+            last_run: self.last_run,
+            this_run: self.change_tick,
+        }
+    }
+
+    /// Runs a system, which detects changes made since last_run.
+    ///
+    /// last_run is set to the tick of this run, and the tick is bumped
+    /// again afterwards, so later changes are seen by the next run.
+    pub(crate) fn run_system(&mut self, system: System, last_run: &mut u64) {
+        let outside = self.last_run;
+        self.change_tick += 1;
+        self.last_run = *last_run;
+        *last_run = self.change_tick;
+
+        system(self);
+
+        self.last_run = outside;
+        self.change_tick += 1;
+    }
+
+    /// Runs a batch of parallel systems, queuing the commands of each one.
+    pub(crate) fn run_parallel(&mut self, jobs: Vec<Job>) {
+        self.change_tick += 1;
+        let time = *self.time();
+        let queued = parallel::run_batch(&mut self.components, &self.entities, self.thread_pool.as_ref(), time, self.change_tick, jobs);
+        self.change_tick += 1;
+
+        for commands in queued {
+            if !commands.is_empty() {
+                self.commands.add(move |ecs| commands.apply(ecs));
+            }
+        }
+    }
+
+    /// Helper function for running stages of the Schedule.
+    fn run_stages(&mut self, stages: &[Stage]) {
+        let mut schedule = std::mem::take(&mut self.schedule);
+        schedule.run(stages, self);
+
+        // Code outside of systems detects changes since the end of the update.
+        self.last_run = self.change_tick;
+        self.change_tick += 1;
+
+        // Systems added while running ended up in the placeholder Schedule.
+        let added = std::mem::replace(&mut self.schedule, schedule);
+        self.schedule.append(added);
+    }
+}
+
+/// A living Entity in the ECS.
+///
+/// Borrows the ECS, so it can be used to reach the rest of the scene
+/// as well.
+pub struct Entity<'a> {
+    /// The ECS the Entity lives in.
+    ecs: &'a mut ECS,
+    /// Id of Entity
+    id: EntityId,
+}
+
+impl<'a> Entity<'a> {
+    /// Name of the Entity.
+    pub fn name(&self) -> &str {
+        self.ecs.entity_name(self.id).unwrap_or("")
+    }
+
+    /// Id of the Entity.
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    /// Add a Component to the Entity.
+    pub fn add_component<C: Component + 'static>(&mut self, f: &dyn Fn(&mut C)) -> Option<&mut C> {
+        self.ecs.add_component::<C>(self.id, f)
+    }
+
+    /// Get a Component from the Entity.
+    pub fn get_component<C: Component + 'static>(&self) -> Option<&C> {
+        self.ecs.get_component::<C>(self.id)
+    }
+
+    /// Remove a Component from the Entity.
+    pub fn remove_component<C: Component + 'static>(&mut self) -> Option<C> {
+        self.ecs.remove_component::<C>(self.id)
+    }
+
+    /// Queue of structural changes of the ECS, applied after the current stage.
+    pub fn commands(&mut self) -> &mut Commands {
+        self.ecs.commands()
+    }
+
+    /// Get a global resource of the ECS.
+    pub fn resource<T: 'static>(&self) -> Option<&T> {
+        self.ecs.resource::<T>()
+    }
+
+    /// Get a mutable global resource of the ECS.
+    pub fn resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.ecs.resource_mut::<T>()
+    }
+
+    /// Get a mutable Component from the Entity.
+    pub fn get_component_mut<C: Component + 'static>(&mut self) -> Option<Mut<'_, C>> {
+        self.ecs.get_component_mut::<C>(self.id)
+    }
+
     /// Delete the Entity.
     pub fn delete(self) {
-        unsafe {
-            let ecs = self.ecs
-                .as_mut()
-                .unwrap();
-            let id = ecs
-                .entity_ids[&self.name];
+        self.ecs.despawn(self.id);
+    }
+
+    /// The ECS the Entity lives in.
+    pub fn scene(&mut self) -> &mut ECS {
+        self.ecs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{Added, Changed, With, Without};
+    use crate::observer::Lifecycle;
+    use crate::schedule::Stage;
+    use crate::component::ScriptComponent;
+    use crate::scriptable::Scriptable;
+
+    struct Counter(u32);
+
+    impl Component for Counter {
+        fn create(_entity: &mut Entity) -> Self {
+            Counter(0)
+        }
+
+        fn update(&mut self, _time: &Time) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn deleted_ids_are_stale() {
+        let mut ecs = ECS::new();
+        let a = ecs.new_entity("a").id();
+        let b = ecs.new_entity("b").id();
+
+        assert!(ecs.despawn(a));
+        assert!(!ecs.is_alive(a));
+        assert!(!ecs.despawn(a));
+        assert!(ecs.entity(a).is_none());
+
+        assert!(ecs.is_alive(b));
+        assert_eq!(ecs.entity(b).unwrap().name(), "b");
+    }
+
+    #[test]
+    fn slots_are_reused_with_new_generation() {
+        let mut ecs = ECS::new();
+        let a = ecs.new_entity("a").id();
+        ecs.despawn(a);
+        let c = ecs.new_entity("c").id();
+
+        assert_eq!(a.index(), c.index());
+        assert_ne!(a, c);
+        assert!(ecs.entity(a).is_none());
+        assert_eq!(ecs.entity(c).unwrap().name(), "c");
+        assert_eq!(ecs.entity_id("a"), None);
+        assert_eq!(ecs.entity_id("c"), Some(c));
+    }
+
+    struct Tag;
+
+    impl Component for Tag {
+        fn create(_entity: &mut Entity) -> Self {
+            Tag
+        }
+    }
+
+    #[test]
+    fn query_visits_matching_entities() {
+        let mut ecs = ECS::new();
+        let a = ecs.new_entity("a").id();
+        let b = ecs.new_entity("b").id();
+        let c = ecs.new_entity("c").id();
+        ecs.add_component::<Counter>(a, &|c| c.0 = 1);
+        ecs.add_component::<Counter>(b, &|c| c.0 = 2);
+        ecs.add_component::<Tag>(b, &|_| {});
+        ecs.add_component::<Tag>(c, &|_| {});
+
+        for (mut counter, _) in ecs.query::<(&mut Counter, &Tag)>() {
+            counter.0 *= 10;
+        }
+        assert_eq!(ecs.get_component::<Counter>(a).unwrap().0, 1);
+        assert_eq!(ecs.get_component::<Counter>(b).unwrap().0, 20);
+
+        let without: Vec<EntityId> = ecs.query_filtered::<EntityId, Without<Tag>>().collect();
+        assert_eq!(without, vec![a]);
+
+        let with: Vec<EntityId> = ecs.query_filtered::<EntityId, (With<Tag>, Without<Counter>)>().collect();
+        assert_eq!(with, vec![c]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn query_rejects_aliasing() {
+        let mut ecs = ECS::new();
+        ecs.query::<(&mut Counter, &Counter)>().count();
+    }
+
+    struct Log(Vec<String>);
+
+    impl Component for Log {
+        fn create(_entity: &mut Entity) -> Self {
+            Log(Vec::new())
+        }
+    }
+
+    fn log(ecs: &mut ECS, name: &'static str) {
+        for mut log in ecs.query::<&mut Log>() {
+            log.0.push(name.to_string());
+        }
+    }
+
+    #[test]
+    fn systems_run_in_stage_and_label_order() {
+        let mut ecs = ECS::new();
+        let id = ecs.new_entity("log").id();
+        ecs.add_component::<Log>(id, &|_| {});
+
+        ecs.add_system(Stage::Render, |ecs| log(ecs, "render"));
+        ecs.add_system(Stage::Update, |ecs| log(ecs, "b"))
+            .label("b")
+            .after("a");
+        ecs.add_system(Stage::Update, |ecs| log(ecs, "c"))
+            .label("c");
+        ecs.add_system(Stage::Update, |ecs| log(ecs, "a"))
+            .label("a")
+            .before("c");
+        ecs.add_system(Stage::PreUpdate, |ecs| log(ecs, "pre"));
+
+        ecs.update();
+        assert_eq!(ecs.get_component::<Log>(id).unwrap().0, vec!["pre", "a", "b", "c", "render"]);
+    }
+
+    #[test]
+    fn removal_keeps_other_components_reachable() {
+        let mut ecs = ECS::new();
+        let ids: Vec<EntityId> = (0..4).map(|i| {
+            let mut entity = ecs.new_entity(&i.to_string());
+            entity.add_component::<Counter>(&|c| c.0 = i);
+            entity.id()
+        }).collect();
+
+        assert_eq!(ecs.remove_component::<Counter>(ids[1]).unwrap().0, 1);
+        assert!(ecs.remove_component::<Counter>(ids[1]).is_none());
+        assert!(ecs.is_alive(ids[1]));
+
+        assert!(ecs.despawn(ids[0]));
+        assert_eq!(ecs.entities_with::<Counter>().len(), 2);
+        assert_eq!(ecs.get_component::<Counter>(ids[2]).unwrap().0, 2);
+        assert_eq!(ecs.get_component::<Counter>(ids[3]).unwrap().0, 3);
+
+        ecs.update();
+        let counts: Vec<u32> = ecs.query::<&Counter>().map(|c| c.0).collect();
+        assert_eq!(counts, vec![3, 4]);
+    }
+
+    #[derive(Default)]
+    struct Calls {
+        enable: u32,
+        disable: u32,
+        update: u32,
+        fixed: u32,
+    }
+
+    impl Scriptable for Calls {
+        fn create() -> Self {
+            Calls::default()
+        }
+
+        fn on_update(&mut self, _entity: &mut Entity, _time: &Time) {
+            self.update += 1;
+        }
+
+        fn on_fixed_update(&mut self, _entity: &mut Entity, dt: f32) {
+            assert_eq!(dt, 0.5);
+            self.fixed += 1;
+        }
+
+        fn on_enable(&mut self, _entity: &mut Entity) {
+            self.enable += 1;
+        }
+
+        fn on_disable(&mut self, _entity: &mut Entity) {
+            self.disable += 1;
+        }
+
+        fn on_destroy(&mut self, entity: &mut Entity) {
+            let name = entity.name().to_string();
+            let ecs = entity.scene();
+            let log = ecs.entity_id("log").unwrap();
+            ecs.get_component_mut::<Log>(log).unwrap().0.push(name);
+        }
+    }
+
+    #[test]
+    fn script_lifecycle_hooks_are_called() {
+        let mut ecs = ECS::new();
+        let log = ecs.new_entity("log").id();
+        ecs.add_component::<Log>(log, &|_| {});
+        let a = ecs.new_entity("a").id();
+        ecs.add_component::<ScriptComponent<Calls>>(a, &|_| {});
+        let b = ecs.new_entity("b").id();
+        ecs.add_component::<ScriptComponent<Calls>>(b, &|_| {});
+
+        ecs.time_mut().set_fixed_step(0.5);
+        ecs.advance_time(0.5);
+        ecs.update();
+        ecs.get_component_mut::<ScriptComponent<Calls>>(a).unwrap().set_enabled(false);
+        ecs.update();
+        ecs.advance_time(0.5);
+
+        let calls = ecs.get_component::<ScriptComponent<Calls>>(a).unwrap().script().unwrap();
+        assert_eq!((calls.enable, calls.disable, calls.update, calls.fixed), (1, 1, 1, 1));
+        let calls = ecs.get_component::<ScriptComponent<Calls>>(b).unwrap().script().unwrap();
+        assert_eq!((calls.enable, calls.disable, calls.update, calls.fixed), (1, 0, 2, 2));
+
+        // Disabled scripts get no fixed updates, even before the next update.
+        ecs.get_component_mut::<ScriptComponent<Calls>>(b).unwrap().set_enabled(false);
+        ecs.advance_time(0.5);
+        let calls = ecs.get_component::<ScriptComponent<Calls>>(b).unwrap().script().unwrap();
+        assert_eq!((calls.disable, calls.fixed), (1, 2));
+
+        ecs.remove_component::<ScriptComponent<Calls>>(a);
+        ecs.despawn(b);
+        assert_eq!(ecs.get_component::<Log>(log).unwrap().0, vec!["a", "b"]);
+    }
+
+    /// Script replacing its own Component with a new one on its first update.
+    #[derive(Default)]
+    struct Restart {
+        generation: u32,
+    }
+
+    impl Scriptable for Restart {
+        fn create() -> Self {
+            Restart::default()
+        }
+
+        fn on_update(&mut self, entity: &mut Entity, _time: &Time) {
+            if self.generation == 0 {
+                entity.remove_component::<ScriptComponent<Restart>>();
+                entity.add_component::<ScriptComponent<Restart>>(&|comp| comp.script_mut().unwrap().generation = 1);
+            }
+        }
+
+        fn on_destroy(&mut self, entity: &mut Entity) {
+            let generation = self.generation.to_string();
+            let ecs = entity.scene();
+            let log = ecs.entity_id("log").unwrap();
+            ecs.get_component_mut::<Log>(log).unwrap().0.push(generation);
+        }
+    }
+
+    #[test]
+    fn scripts_replaced_by_their_callback_are_destroyed() {
+        let mut ecs = ECS::new();
+        let log = ecs.new_entity("log").id();
+        ecs.add_component::<Log>(log, &|_| {});
+        let id = ecs.new_entity("a").id();
+        ecs.add_component::<ScriptComponent<Restart>>(id, &|_| {});
+
+        ecs.update();
+        let script = ecs.get_component::<ScriptComponent<Restart>>(id).unwrap().script().unwrap();
+        assert_eq!(script.generation, 1);
+        assert_eq!(ecs.get_component::<Log>(log).unwrap().0, vec!["0"]);
+    }
 
-            ecs.entities.remove(id);
+    struct Score(u32);
+
+    #[test]
+    fn resources_are_stored_by_type() {
+        let mut ecs = ECS::new();
+        assert!(ecs.resource::<Score>().is_none());
+        assert!(ecs.insert_resource(Score(1)).is_none());
+        ecs.resource_mut::<Score>().unwrap().0 += 1;
+        assert_eq!(ecs.resource::<Score>().unwrap().0, 2);
+
+        let id = ecs.new_entity("a").id();
+        ecs.add_component::<Counter>(id, &|_| {});
+        ecs.resource_scope::<Score, _>(|ecs, score| {
+            for mut counter in ecs.query::<&mut Counter>() {
+                counter.0 = score.0;
+            }
+        });
+        assert_eq!(ecs.get_component::<Counter>(id).unwrap().0, 2);
+
+        assert_eq!(ecs.entity(id).unwrap().resource::<Score>().unwrap().0, 2);
+        assert_eq!(ecs.remove_resource::<Score>().unwrap().0, 2);
+        assert!(!ecs.contains_resource::<Score>());
+        assert!(ecs.contains_resource::<Time>());
+
+        // Without Time, updates see no time pass until it is advanced again.
+        ecs.advance_time(0.5);
+        ecs.remove_resource::<Time>();
+        ecs.update();
+        assert_eq!(ecs.time().delta(), 0.0);
+        ecs.advance_time(0.25);
+        assert_eq!(ecs.resource::<Time>().unwrap().delta(), 0.25);
+    }
+
+    struct Spawner;
+
+    impl Scriptable for Spawner {
+        fn create() -> Self {
+            Spawner
+        }
+
+        fn on_update(&mut self, entity: &mut Entity, _time: &Time) {
+            let id = entity.id();
+            entity.commands()
+                .spawn("bullet")
+                .add_component::<Counter, _>(|c| c.0 = 7)
+                .set_parent(id);
+            entity.commands().insert_resource(Score(1));
+        }
+    }
+
+    #[test]
+    fn commands_are_applied_after_stage() {
+        let mut ecs = ECS::new();
+        let gun = ecs.new_entity("gun").id();
+        ecs.add_component::<ScriptComponent<Spawner>>(gun, &|_| {});
+        ecs.add_system(Stage::Update, |ecs| {
+            assert!(ecs.entity_id("bullet").is_none());
+        }).after(std::any::type_name::<ScriptComponent<Spawner>>());
+
+        ecs.update();
+        let bullet = ecs.entity_id("bullet").unwrap();
+        assert_eq!(ecs.get_component::<Counter>(bullet).unwrap().0, 7);
+        assert_eq!(ecs.parent(bullet), Some(gun));
+        assert!(ecs.contains_resource::<Score>());
+
+        ecs.commands().despawn(bullet);
+        ecs.commands().entity(gun).remove_component::<ScriptComponent<Spawner>>();
+        ecs.apply_commands();
+        assert!(!ecs.is_alive(bullet));
+        assert!(ecs.get_component::<ScriptComponent<Spawner>>(gun).is_none());
+    }
+
+    #[test]
+    fn components_survive_reallocation() {
+        let mut ecs = ECS::new();
+        let first = ecs.new_entity("first").id();
+        ecs.add_component::<Counter>(first, &|c| c.0 = 10);
+
+        for i in 0..100 {
+            let mut entity = ecs.new_entity(&i.to_string());
+            entity.add_component::<Counter>(&|_| {});
+        }
+
+        ecs.update();
+        assert_eq!(ecs.get_component::<Counter>(first).unwrap().0, 11);
+    }
+
+    struct Health(u32);
+
+    impl Component for Health {
+        fn create(_entity: &mut Entity) -> Self {
+            Health(100)
         }
     }
 
-    /*pub fn scene(&mut self) -> &mut ECS {
-        unsafe { &mut *self.ecs as &mut _ }
-    }*/
+    /// Entities seen by the change detection test system, as (added, changed).
+    #[derive(Default)]
+    struct Seen(Vec<EntityId>, Vec<EntityId>);
+
+    #[test]
+    fn changes_are_detected_since_last_run() {
+        let mut ecs = ECS::new();
+        ecs.insert_resource(Seen::default());
+        ecs.add_system(Stage::Update, |ecs| {
+            let added = ecs.query_filtered::<EntityId, Added<Health>>().collect();
+            let changed = ecs.query_filtered::<EntityId, Changed<Health>>().collect();
+            *ecs.resource_mut::<Seen>().unwrap() = Seen(added, changed);
+        });
+
+        let a = ecs.new_entity("a").id();
+        let b = ecs.new_entity("b").id();
+        ecs.add_component::<Health>(a, &|_| {});
+        ecs.add_component::<Health>(b, &|_| {});
+        ecs.update();
+        assert_eq!(ecs.resource::<Seen>().unwrap().0, vec![a, b]);
+        assert_eq!(ecs.resource::<Seen>().unwrap().1, vec![a, b]);
+
+        ecs.update();
+        assert!(ecs.resource::<Seen>().unwrap().1.is_empty());
+
+        ecs.get_component_mut::<Health>(b).unwrap().0 -= 10;
+        assert!(ecs.is_changed::<Health>(b));
+        assert!(!ecs.is_changed::<Health>(a));
+        let _ = ecs.get_component_mut::<Health>(a).unwrap().0;
+        ecs.update();
+        assert!(ecs.resource::<Seen>().unwrap().0.is_empty());
+        assert_eq!(ecs.resource::<Seen>().unwrap().1, vec![b]);
+
+        // Changes from systems are seen by later systems and the next frame.
+        ecs.add_system(Stage::PreUpdate, |ecs| {
+            for mut health in ecs.query::<&mut Health>() {
+                if health.0 > 95 {
+                    health.0 = 95;
+                }
+            }
+        });
+        ecs.update();
+        assert_eq!(ecs.resource::<Seen>().unwrap().1, vec![a]);
+        ecs.update();
+        assert!(ecs.resource::<Seen>().unwrap().1.is_empty());
+    }
+
+    /// Physics bodies registered by the observers of Collider.
+    #[derive(Default)]
+    struct Bodies(Vec<EntityId>);
+
+    struct Collider(f32);
+
+    impl Component for Collider {
+        fn create(_entity: &mut Entity) -> Self {
+            Collider(1.0)
+        }
+
+        fn on_add(entity: &mut Entity) {
+            let radius = entity.get_component::<Collider>().unwrap().0;
+            entity.add_component::<Log>(&|log| log.0.push(format!("add {}", radius)));
+        }
+
+        fn on_replace(entity: &mut Entity) {
+            let radius = entity.get_component::<Collider>().unwrap().0;
+            entity.get_component_mut::<Log>().unwrap().0.push(format!("replace {}", radius));
+        }
+
+        fn on_remove(entity: &mut Entity) {
+            entity.get_component_mut::<Log>().unwrap().0.push("remove".to_string());
+        }
+    }
+
+    #[test]
+    fn hooks_and_observers_follow_component_lifecycle() {
+        let mut ecs = ECS::new();
+        ecs.insert_resource(Bodies::default());
+        ecs.observe::<Collider>(Lifecycle::Add, |ecs, id| {
+            ecs.resource_mut::<Bodies>().unwrap().0.push(id);
+        });
+        ecs.observe::<Collider>(Lifecycle::Remove, |ecs, id| {
+            assert!(ecs.get_component::<Collider>(id).is_some());
+            ecs.resource_mut::<Bodies>().unwrap().0.retain(|body| *body != id);
+        });
+
+        let a = ecs.new_entity("a").id();
+        let b = ecs.new_entity("b").id();
+        ecs.add_component::<Collider>(a, &|c| c.0 = 2.0);
+        ecs.add_component::<Collider>(b, &|_| {});
+        ecs.add_component::<Collider>(a, &|c| c.0 = 3.0);
+        assert_eq!(ecs.resource::<Bodies>().unwrap().0, vec![a, b]);
+
+        ecs.remove_component::<Collider>(a);
+        ecs.despawn(b);
+        assert!(ecs.resource::<Bodies>().unwrap().0.is_empty());
+        assert_eq!(ecs.get_component::<Log>(a).unwrap().0, vec!["add 2", "replace 2", "remove"]);
+    }
 }
@@ -0,0 +1,268 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+use crate::ecs::{ECS, Entity};
+use crate::schedule::Stage;
+use crate::snapshot::Snapshot;
+
+/// Double-buffered queue of events of type T, stored as a resource.
+///
+/// Events stay readable during the update they were sent in and the
+/// next one, so every reader running once per update sees each event,
+/// regardless of the order systems run in.
+pub struct Events<T> {
+    /// Events sent before the last swap.
+    old: Vec<T>,
+    /// Events sent since the last swap.
+    new: Vec<T>,
+    /// Number of the first event in old.
+    old_start: usize,
+    /// Number of events ever sent.
+    event_count: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Events {
+            old: Vec::new(),
+            new: Vec::new(),
+            old_start: 0,
+            event_count: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Events::default()
+    }
+
+    /// Sends an event to all readers.
+    pub fn send(&mut self, event: T) {
+        self.new.push(event);
+        self.event_count += 1;
+    }
+
+    /// Drops the events of the buffer before last, and starts a new one.
+    ///
+    /// Run once per update by the system added with ECS::add_event().
+    pub fn update(&mut self) {
+        self.old_start += self.old.len();
+        self.old = std::mem::take(&mut self.new);
+    }
+
+    /// Number of events which can still be read.
+    pub fn len(&self) -> usize {
+        self.old.len() + self.new.len()
+    }
+
+    /// Checks if there are no events which can still be read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all events.
+    pub fn clear(&mut self) {
+        self.old_start = self.event_count;
+        self.old.clear();
+        self.new.clear();
+    }
+
+    /// System swapping the buffers of the queue.
+    fn update_system(ecs: &mut ECS) where T: 'static {
+        if let Some(events) = ecs.resource_mut::<Events<T>>() {
+            events.update();
+        }
+    }
+}
+
+impl<T: Clone + 'static> Snapshot for Events<T> {
+    fn snapshot(&self) -> Self {
+        Events {
+            old: self.old.clone(),
+            new: self.new.clone(),
+            old_start: self.old_start,
+            event_count: self.event_count,
+        }
+    }
+}
+
+/// Sends events of type T. Created by ECS::event_writer().
+pub struct EventWriter<'a, T> {
+    events: &'a mut Events<T>,
+}
+
+impl<T> EventWriter<'_, T> {
+    /// Sends an event to all readers.
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+
+    /// Sends every event of an iterator to all readers.
+    pub fn send_batch<I: IntoIterator<Item = T>>(&mut self, events: I) {
+        for event in events {
+            self.events.send(event);
+        }
+    }
+}
+
+/// Cursor reading events of type T, each one once.
+///
+/// Owned by whoever reads, e.g. a script, or a resource for systems,
+/// so any number of readers consume the same events independently.
+pub struct EventReader<T> {
+    /// Number of the next event to read.
+    next: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        EventReader {
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    /// Creates a reader which starts at the oldest event still stored.
+    pub fn new() -> Self {
+        EventReader::default()
+    }
+
+    /// Iterates over the events sent since the last read.
+    ///
+    /// Events dropped before being read are skipped.
+    pub fn read<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let skip = self.next.saturating_sub(events.old_start);
+        self.next = events.event_count;
+        events.old.iter()
+            .chain(events.new.iter())
+            .skip(skip)
+    }
+
+    /// Number of events sent since the last read.
+    ///
+    /// Zero for readers ahead of the queue, like after it was restored
+    /// or added again.
+    pub fn len(&self, events: &Events<T>) -> usize {
+        events.event_count.saturating_sub(self.next.max(events.old_start))
+    }
+
+    /// Checks if no events were sent since the last read.
+    pub fn is_empty(&self, events: &Events<T>) -> bool {
+        self.len(events) == 0
+    }
+}
+
+impl ECS {
+    /// Adds the event queue of type T, if missing. The first time, also
+    /// adds a PreUpdate system labelled "event_update" swapping its
+    /// buffers, which stays when the queue is removed.
+    pub fn add_event<T: 'static>(&mut self) {
+        if !self.contains_resource::<Events<T>>() {
+            self.insert_resource(Events::<T>::new());
+        }
+
+        if self.register_event_type(TypeId::of::<T>()) {
+            self.add_system(Stage::PreUpdate, Events::<T>::update_system)
+                .label("event_update");
+        }
+    }
+
+    /// Writer for events of type T, adding the queue if missing.
+    pub fn event_writer<T: 'static>(&mut self) -> EventWriter<'_, T> {
+        self.add_event::<T>();
+        EventWriter {
+            events: self.resource_mut::<Events<T>>().unwrap(),
+        }
+    }
+
+    /// Sends an event of type T, adding the queue if missing.
+    pub fn send_event<T: 'static>(&mut self, event: T) {
+        self.event_writer::<T>().send(event);
+    }
+
+    /// Iterates over the events of type T the reader has not read yet.
+    ///
+    /// Nothing is read if no event of type T was ever added.
+    pub fn read_events<'a, T: 'static>(&'a self, reader: &mut EventReader<T>) -> impl Iterator<Item = &'a T> {
+        self.resource::<Events<T>>()
+            .map(|events| reader.read(events))
+            .into_iter()
+            .flatten()
+    }
+}
+
+impl Entity<'_> {
+    /// Sends an event of type T to all readers.
+    pub fn send_event<T: 'static>(&mut self, event: T) {
+        self.scene().send_event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Collision(u32);
+
+    #[test]
+    fn events_last_two_updates_and_are_read_once() {
+        let mut ecs = ECS::new();
+        let mut first = EventReader::<Collision>::new();
+        let mut second = EventReader::<Collision>::new();
+
+        ecs.send_event(Collision(1));
+        ecs.event_writer().send_batch([Collision(2), Collision(3)]);
+        let read: Vec<&Collision> = ecs.read_events(&mut first).collect();
+        assert_eq!(read, vec![&Collision(1), &Collision(2), &Collision(3)]);
+        assert_eq!(ecs.read_events(&mut first).count(), 0);
+
+        ecs.update();
+        ecs.send_event(Collision(4));
+        let read: Vec<&Collision> = ecs.read_events(&mut first).collect();
+        assert_eq!(read, vec![&Collision(4)]);
+
+        // Events older than the last two updates are dropped.
+        ecs.update();
+        ecs.update();
+        let read: Vec<&Collision> = ecs.read_events(&mut second).collect();
+        assert!(read.is_empty());
+        assert!(ecs.resource::<Events<Collision>>().unwrap().is_empty());
+
+        // Adding the queue again after removing it does not swap the
+        // buffers twice per update.
+        ecs.remove_resource::<Events<Collision>>();
+        ecs.send_event(Collision(5));
+        ecs.update();
+        let read: Vec<&Collision> = ecs.read_events(&mut EventReader::new()).collect();
+        assert_eq!(read, vec![&Collision(5)]);
+    }
+
+    #[test]
+    fn readers_see_events_regardless_of_order() {
+        let mut events = Events::new();
+        let mut late = EventReader::new();
+
+        events.send(1);
+        events.update();
+        events.send(2);
+        assert_eq!(late.len(&events), 2);
+        assert_eq!(late.read(&events).copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        events.update();
+        events.update();
+        events.send(3);
+        assert_eq!(late.read(&events).copied().collect::<Vec<_>>(), vec![3]);
+        assert!(late.is_empty(&events));
+
+        // Readers ahead of a new queue have nothing to read.
+        let mut events = Events::new();
+        events.send(4);
+        assert_eq!(late.len(&events), 0);
+        assert_eq!(late.read(&events).count(), 0);
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::HashSet;
+use glam::Mat4;
+use serde::{Deserialize, Serialize};
+use crate::Transform;
+use crate::component::{Component, MeshComponent};
+use crate::ecs::{ECS, Entity, EntityId};
+use crate::query::{Added, Changed};
+use crate::reflect::Reflect;
+use crate::scene::{SceneComponent, SceneLoader, Value};
+use crate::schedule::Stage;
+use crate::snapshot::Snapshot;
+
+/// Transform of an Entity, relative to its parent.
+///
+/// Adds a GlobalTransform to the Entity, which is computed
+/// from the transforms of its parents every frame.
+#[derive(Clone, Debug, Serialize, Deserialize, Reflect)]
+pub struct TransformComponent {
+    pub local: Transform,
+}
+
+impl Component for TransformComponent {
+    fn create(entity: &mut Entity) -> Self {
+        entity.add_component::<GlobalTransform>(&|_| {});
+        TransformComponent {
+            local: Transform::new(),
+        }
+    }
+
+    fn register(ecs: &mut ECS) {
+        ecs.add_system(Stage::PostUpdate, propagate_transforms)
+            .label("transform_propagate");
+    }
+}
+
+/// Transform of an Entity in world space.
+///
+/// Computed in PostUpdate from the TransformComponents of the Entity
+/// and its parents.
+#[derive(Clone, Debug)]
+pub struct GlobalTransform {
+    matrix: Mat4,
+}
+
+impl GlobalTransform {
+    /// The world space transformation matrix.
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    /// The world space position.
+    pub fn translation(&self) -> glam::Vec3 {
+        self.matrix.w_axis.truncate()
+    }
+}
+
+impl Snapshot for TransformComponent {
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl Component for GlobalTransform {
+    fn create(_entity: &mut Entity) -> Self {
+        GlobalTransform {
+            matrix: Mat4::IDENTITY,
+        }
+    }
+
+    /// Flags the MeshComponent of the Entity as changed, so its meshes
+    /// are placed without the GlobalTransform from then on.
+    fn on_remove(entity: &mut Entity) {
+        if let Some(mut mesh) = entity.get_component_mut::<MeshComponent>() {
+            mesh.set_changed();
+        }
+    }
+}
+
+impl Snapshot for GlobalTransform {
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// Parent of an Entity. Set with ECS::set_parent().
+///
+/// Adding a Parent any other way only makes the Entity a root, as it
+/// does not say which Entity the parent is.
+#[derive(Clone, Debug)]
+pub struct Parent(EntityId);
+
+impl Parent {
+    /// Id of the parent Entity.
+    pub fn get(&self) -> EntityId {
+        self.0
+    }
+}
+
+impl Component for Parent {
+    /// Makes the Entity a root, with itself as a placeholder parent until
+    /// set_parent() sets the real one.
+    fn create(entity: &mut Entity) -> Self {
+        let id = entity.id();
+        entity.scene().remove_parent(id);
+        Parent(id)
+    }
+
+    /// Removes the Parent again unless it is alive and not the Entity
+    /// itself or one of its descendants.
+    fn on_add(entity: &mut Entity) {
+        let id = entity.id();
+        let ecs = entity.scene();
+        let parent = ecs.parent(id).unwrap();
+        if !ecs.is_alive(parent) || ecs.is_ancestor(id, parent) {
+            ecs.remove_component::<Parent>(id);
+        }
+    }
+
+    /// Keeps the world transform of the Entity as it becomes a root, if
+    /// it was the child of its parent.
+    fn on_remove(entity: &mut Entity) {
+        let id = entity.id();
+        let ecs = entity.scene();
+        let parent = ecs.parent(id).unwrap();
+        if ecs.children(parent).contains(&id) {
+            let world = ecs.world_matrix(id);
+            ecs.set_local_matrix(id, world);
+        }
+    }
+
+    fn destroy(&mut self, entity: &mut Entity) {
+        // The Entity becomes a root, so its GlobalTransform needs updating.
+        if let Some(mut transform) = entity.get_component_mut::<TransformComponent>() {
+            transform.set_changed();
+        }
+
+        let id = entity.id();
+        if let Some(mut children) = entity.scene().get_component_mut::<Children>(self.0) {
+            children.0.retain(|child| *child != id);
+        }
+    }
+}
+
+impl Snapshot for Parent {
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// Saved as the name of the parent Entity.
+impl SceneComponent for Parent {
+    fn save(&self, ecs: &ECS) -> Result<Value, String> {
+        match ecs.entity_name(self.0) {
+            Some(name) => Ok(Value::from(name)),
+            None => Err(String::from("Parent no longer exists"))
+        }
+    }
+
+    fn load(value: Value, loader: &mut SceneLoader, id: EntityId) -> Result<(), String> {
+        let name = value.as_str().ok_or("Parent is not the name of an Entity")?;
+        let parent = loader.entity(name)
+            .ok_or_else(|| format!("Parent {} is not in the scene", name))?;
+
+        if !loader.ecs().set_parent(id, parent) {
+            return Err(format!("{} cannot be the parent", name));
+        }
+        Ok(())
+    }
+}
+
+/// Children of an Entity. Kept in sync by ECS::set_parent().
+///
+/// When removed, the children lose their parent and become roots,
+/// keeping their world transform.
+#[derive(Clone, Debug)]
+pub struct Children(Vec<EntityId>);
+
+impl Children {
+    /// Ids of the child Entities.
+    pub fn get(&self) -> &[EntityId] {
+        &self.0
+    }
+}
+
+impl Component for Children {
+    fn create(_entity: &mut Entity) -> Self {
+        Children(Vec::new())
+    }
+
+    fn on_remove(entity: &mut Entity) {
+        let id = entity.id();
+        let ecs = entity.scene();
+        for child in ecs.children(id).to_vec() {
+            ecs.remove_component::<Parent>(child);
+        }
+    }
+}
+
+impl Snapshot for Children {
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl ECS {
+    /// Gets the parent of an Entity.
+    pub fn parent(&self, id: EntityId) -> Option<EntityId> {
+        self.get_component::<Parent>(id).map(Parent::get)
+    }
+
+    /// Gets the children of an Entity.
+    pub fn children(&self, id: EntityId) -> &[EntityId] {
+        match self.get_component::<Children>(id) {
+            Some(children) => children.get(),
+            None => &[]
+        }
+    }
+
+    /// Makes an Entity the child of another, keeping its world transform.
+    ///
+    /// Returns false if either Entity was deleted, or if the parent is
+    /// the Entity itself or one of its descendants.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return false;
+        }
+
+        if self.is_ancestor(child, parent) {
+            return false;
+        }
+
+        let world = self.world_matrix(child);
+        let parent_world = self.world_matrix(parent);
+        self.remove_component::<Parent>(child);
+        self.add_component::<Parent>(child, &|comp| comp.0 = parent);
+        self.set_local_matrix(child, parent_world.inverse() * world);
+
+        if self.get_component::<Children>(parent).is_none() {
+            self.add_component::<Children>(parent, &|_| {});
+        }
+        self.get_component_mut::<Children>(parent)
+            .unwrap()
+            .0
+            .push(child);
+
+        true
+    }
+
+    /// Checks if an Entity is another one or one of its ancestors.
+    fn is_ancestor(&self, ancestor: EntityId, id: EntityId) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            current = self.parent(id);
+        }
+        false
+    }
+
+    /// Makes an Entity a root, keeping its world transform.
+    ///
+    /// Same as removing its Parent.
+    pub fn remove_parent(&mut self, child: EntityId) {
+        self.remove_component::<Parent>(child);
+    }
+
+    /// Deletes an Entity along with all of its descendants.
+    pub fn despawn_recursive(&mut self, id: EntityId) -> bool {
+        for child in self.children(id).to_vec() {
+            self.despawn_recursive(child);
+        }
+
+        self.despawn(id)
+    }
+
+    /// Computes the world transformation of an Entity from the
+    /// TransformComponents of it and its parents.
+    pub fn world_matrix(&self, id: EntityId) -> Mat4 {
+        let local = match self.get_component::<TransformComponent>(id) {
+            Some(transform) => transform.local.transformation(),
+            None => Mat4::IDENTITY
+        };
+
+        match self.parent(id) {
+            Some(parent) => self.world_matrix(parent) * local,
+            None => local
+        }
+    }
+
+    /// Helper function for setting the local transform of an Entity from a matrix.
+    fn set_local_matrix(&mut self, id: EntityId, matrix: Mat4) {
+        if let Some(mut transform) = self.get_component_mut::<TransformComponent>(id) {
+            let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+            transform.local.scale = scale;
+            transform.local.rotation = rotation;
+            transform.local.translation = translation;
+        }
+    }
+}
+
+/// Computes the GlobalTransform of every Entity whose TransformComponent
+/// or Parent changed since the last run, along with its descendants.
+pub fn propagate_transforms(ecs: &mut ECS) {
+    let mut dirty: Vec<EntityId> = ecs.query_filtered::<EntityId, Changed<TransformComponent>>().collect();
+    dirty.extend(ecs.query_filtered::<EntityId, Changed<Parent>>());
+    dirty.extend(ecs.query_filtered::<EntityId, Added<GlobalTransform>>());
+
+    let dirty_set: HashSet<EntityId> = dirty.iter().copied().collect();
+    let mut done = HashSet::new();
+    for id in dirty {
+        // Descendants of a dirty Entity are updated along with it.
+        let mut ancestor = ecs.parent(id);
+        while let Some(parent) = ancestor {
+            if dirty_set.contains(&parent) {
+                break;
+            }
+            ancestor = ecs.parent(parent);
+        }
+
+        if ancestor.is_none() && done.insert(id) {
+            let parent = match ecs.parent(id) {
+                Some(parent) => ecs.world_matrix(parent),
+                None => Mat4::IDENTITY
+            };
+            propagate(ecs, id, parent);
+        }
+    }
+}
+
+/// Helper function for computing the GlobalTransform of an Entity and its descendants.
+fn propagate(ecs: &mut ECS, id: EntityId, parent: Mat4) {
+    let matrix = match ecs.get_component::<TransformComponent>(id) {
+        Some(transform) => parent * transform.local.transformation(),
+        None => parent
+    };
+
+    if let Some(mut global) = ecs.get_component_mut::<GlobalTransform>(id) {
+        global.matrix = matrix;
+    }
+
+    for child in ecs.children(id).to_vec() {
+        propagate(ecs, child, matrix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Quat, Vec3};
+
+    fn spawn(ecs: &mut ECS, name: &str, translation: Vec3) -> EntityId {
+        let mut entity = ecs.new_entity(name);
+        entity.add_component::<TransformComponent>(&|comp| comp.local.translation = translation);
+        entity.id()
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn global_transforms_follow_parents() {
+        let mut ecs = ECS::new();
+        let player = spawn(&mut ecs, "player", Vec3::new(1.0, 0.0, 0.0));
+        let hand = spawn(&mut ecs, "hand", Vec3::new(1.0, 1.0, 0.0));
+        let weapon = spawn(&mut ecs, "weapon", Vec3::new(1.0, 2.0, 0.0));
+        assert!(ecs.set_parent(hand, player));
+        assert!(ecs.set_parent(weapon, hand));
+
+        ecs.update();
+        let global = ecs.get_component::<GlobalTransform>(weapon).unwrap();
+        assert_near(global.translation(), Vec3::new(1.0, 2.0, 0.0));
+        let local = ecs.get_component::<TransformComponent>(weapon).unwrap().local;
+        assert_near(local.translation, Vec3::new(0.0, 1.0, 0.0));
+
+        ecs.get_component_mut::<TransformComponent>(player).unwrap().local.rotation =
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        ecs.update();
+        let global = ecs.get_component::<GlobalTransform>(weapon).unwrap();
+        assert_near(global.translation(), Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn reparenting_keeps_world_position() {
+        let mut ecs = ECS::new();
+        let a = spawn(&mut ecs, "a", Vec3::new(5.0, 0.0, 0.0));
+        let b = spawn(&mut ecs, "b", Vec3::new(0.0, 3.0, 0.0));
+        let child = spawn(&mut ecs, "child", Vec3::new(1.0, 1.0, 1.0));
+
+        ecs.set_parent(child, a);
+        ecs.set_parent(child, b);
+        assert_eq!(ecs.children(a), &[]);
+        assert_eq!(ecs.children(b), &[child]);
+        assert_near(ecs.world_matrix(child).w_axis.truncate(), Vec3::new(1.0, 1.0, 1.0));
+
+        ecs.remove_parent(child);
+        assert_eq!(ecs.parent(child), None);
+        assert_near(ecs.world_matrix(child).w_axis.truncate(), Vec3::new(1.0, 1.0, 1.0));
+
+        // Removing the Parent directly, or the Children of the parent,
+        // keeps it too.
+        ecs.set_parent(child, a);
+        ecs.remove_component::<Parent>(child);
+        assert_near(ecs.world_matrix(child).w_axis.truncate(), Vec3::new(1.0, 1.0, 1.0));
+        ecs.set_parent(child, b);
+        ecs.remove_component::<Children>(b);
+        assert_eq!(ecs.parent(child), None);
+        assert_near(ecs.world_matrix(child).w_axis.truncate(), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn cycles_are_rejected_and_despawn_cleans_up() {
+        let mut ecs = ECS::new();
+        let a = spawn(&mut ecs, "a", Vec3::ZERO);
+        let b = spawn(&mut ecs, "b", Vec3::ZERO);
+        let c = spawn(&mut ecs, "c", Vec3::ZERO);
+        ecs.set_parent(b, a);
+        ecs.set_parent(c, b);
+        assert!(!ecs.set_parent(a, c));
+        assert!(!ecs.set_parent(a, a));
+
+        // Adding a Parent directly only makes the Entity a root.
+        ecs.add_component::<Parent>(c, &|_| {});
+        assert_eq!(ecs.parent(c), None);
+        assert_eq!(ecs.children(b), &[]);
+        ecs.update();
+        assert_near(ecs.world_matrix(c).w_axis.truncate(), Vec3::ZERO);
+        ecs.set_parent(c, b);
+
+        ecs.despawn(b);
+        assert_eq!(ecs.children(a), &[]);
+        assert_eq!(ecs.parent(c), None);
+
+        ecs.set_parent(c, a);
+        ecs.despawn_recursive(a);
+        assert!(!ecs.is_alive(a));
+        assert!(!ecs.is_alive(c));
+    }
+
+    #[test]
+    fn only_changed_subtrees_are_propagated() {
+        let mut ecs = ECS::new();
+        let parent = spawn(&mut ecs, "parent", Vec3::new(1.0, 0.0, 0.0));
+        let child = spawn(&mut ecs, "child", Vec3::new(1.0, 1.0, 0.0));
+        let other = spawn(&mut ecs, "other", Vec3::ZERO);
+        ecs.set_parent(child, parent);
+        ecs.update();
+
+        let tick = ecs.change_tick();
+        ecs.get_component_mut::<TransformComponent>(parent).unwrap().local.translation.x = 2.0;
+        ecs.update();
+        let global = ecs.get_component::<GlobalTransform>(child).unwrap();
+        assert_near(global.translation(), Vec3::new(2.0, 1.0, 0.0));
+        assert!(ecs.component_ticks::<GlobalTransform>(child).unwrap().changed() > tick);
+        assert!(ecs.component_ticks::<GlobalTransform>(other).unwrap().changed() < tick);
+
+        // Children of a despawned parent stay where they are.
+        ecs.despawn(parent);
+        ecs.update();
+        let global = ecs.get_component::<GlobalTransform>(child).unwrap();
+        assert_near(global.translation(), Vec3::new(2.0, 1.0, 0.0));
+    }
+}
@@ -3,22 +3,44 @@
 pub mod shader;
 pub mod texture;
 pub mod mesh;
+pub mod change;
+pub mod commands;
 pub mod component;
 pub mod ecs;
+pub mod event;
+pub mod hierarchy;
+pub mod main_thread;
+pub mod observer;
+pub mod parallel;
+pub mod prefab;
+pub mod query;
+pub mod reflect;
+pub mod scene;
+pub mod schedule;
+pub mod snapshot;
+pub mod tag;
+pub mod time;
 pub mod scriptable;
 pub mod scripting;
 pub mod assets;
 
+// Lets derived code refer to sample_core from within the crate too.
+extern crate self as sample_core;
+
 //mod instance;
+mod registry;
+mod storage;
 mod types;
 
 use glam::{
     Vec3,
     Quat
 };
+use serde::{Deserialize, Serialize};
+use reflect::Reflect;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Reflect)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
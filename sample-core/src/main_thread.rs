@@ -0,0 +1,78 @@
+use std::marker::PhantomData;
+use std::sync::{Mutex, PoisonError};
+use gl::types::GLuint;
+
+/// Proof that the code holding it runs on the main thread, where the GL
+/// context is current.
+///
+/// Meshes, shaders and textures can be shared with other threads, but
+/// everything making GL calls takes a MainThread. It is neither Send nor
+/// Sync, so it never leaves the thread it was made on, and parallel
+/// systems have no way to get one. Exclusive systems get it from
+/// ECS::main_thread(), once it was given one with ECS::set_main_thread().
+///
+/// Meshes, shaders and textures dropped on any thread queue their GL
+/// objects, which are deleted by delete_dropped().
+#[derive(Clone, Copy, Debug)]
+pub struct MainThread {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl MainThread {
+    /// Creates the token for the current thread.
+    ///
+    /// # Safety
+    ///
+    /// The GL context has to be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        MainThread {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl MainThread {
+    /// Deletes the GL objects of the meshes, shaders and textures dropped
+    /// since the last call.
+    ///
+    /// The ECS calls this every frame in the Render stage.
+    pub fn delete_dropped(&self) {
+        let dropped = std::mem::take(&mut *DROPPED.lock().unwrap_or_else(PoisonError::into_inner));
+        for object in dropped {
+            unsafe {
+                match object {
+                    GlObject::VertexArray(id) => gl::DeleteVertexArrays(1, &id),
+                    GlObject::Buffers(ids) => gl::DeleteBuffers(ids.len() as _, ids.as_ptr()),
+                    GlObject::Shader(id) => gl::DeleteShader(id),
+                    GlObject::Texture(id) => gl::DeleteTextures(1, &id),
+                }
+            }
+        }
+    }
+}
+
+/// A GL object of a dropped mesh, shader or texture.
+#[derive(Debug, PartialEq)]
+pub(crate) enum GlObject {
+    VertexArray(GLuint),
+    Buffers(Vec<GLuint>),
+    Shader(GLuint),
+    Texture(GLuint),
+}
+
+/// GL objects waiting for MainThread::delete_dropped().
+static DROPPED: Mutex<Vec<GlObject>> = Mutex::new(Vec::new());
+
+/// Queues a GL object to be deleted on the main thread.
+///
+/// The last reference to a mesh, shader or texture may be dropped on
+/// any thread, like by a parallel system replacing a MeshComponent.
+pub(crate) fn delete_later(object: GlObject) {
+    DROPPED.lock().unwrap_or_else(PoisonError::into_inner).push(object);
+}
+
+/// Takes the queued GL objects without deleting them, for tests without GL.
+#[cfg(test)]
+pub(crate) fn take_dropped() -> Vec<GlObject> {
+    std::mem::take(&mut *DROPPED.lock().unwrap_or_else(PoisonError::into_inner))
+}
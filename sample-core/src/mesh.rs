@@ -1,17 +1,123 @@
 use gl::{
     self,
     types::{
+        GLenum,
         GLfloat,
         GLuint,
     }
 };
+use crate::main_thread::{self, GlObject, MainThread};
+
+mod data;
+mod generate;
+mod layout;
+mod mikktspace;
+pub mod shapes;
+
+pub use data::{Aabb, BoundingSphere, MeshData};
+use data::to_bytes;
+pub use layout::{ComponentType, Semantic, VertexAttribute, VertexLayout};
+
+/// Type of the indices of a Mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexType {
+    U8,
+    U16,
+    U32,
+}
+
+impl IndexType {
+    /// Size of one index in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            IndexType::U8 => 1,
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        }
+    }
+
+    fn gl_enum(self) -> GLenum {
+        match self {
+            IndexType::U8 => gl::UNSIGNED_BYTE,
+            IndexType::U16 => gl::UNSIGNED_SHORT,
+            IndexType::U32 => gl::UNSIGNED_INT,
+        }
+    }
+
+    /// Reads indices of this type from native endian bytes.
+    pub fn read(self, bytes: &[u8]) -> Vec<u32> {
+        match self {
+            IndexType::U8 => bytes.iter().map(|i| *i as u32).collect(),
+            IndexType::U16 => bytes.chunks_exact(2)
+                .map(|i| u16::from_ne_bytes([i[0], i[1]]) as u32)
+                .collect(),
+            IndexType::U32 => bytes.chunks_exact(4)
+                .map(|i| u32::from_ne_bytes([i[0], i[1], i[2], i[3]]))
+                .collect(),
+        }
+    }
+}
+
+/// How the vertices of a Mesh are put together.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrimitiveMode {
+    Points,
+    Lines,
+    LineLoop,
+    LineStrip,
+    #[default]
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl PrimitiveMode {
+    fn gl_enum(self) -> GLenum {
+        match self {
+            PrimitiveMode::Points => gl::POINTS,
+            PrimitiveMode::Lines => gl::LINES,
+            PrimitiveMode::LineLoop => gl::LINE_LOOP,
+            PrimitiveMode::LineStrip => gl::LINE_STRIP,
+            PrimitiveMode::Triangles => gl::TRIANGLES,
+            PrimitiveMode::TriangleStrip => gl::TRIANGLE_STRIP,
+            PrimitiveMode::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+
+    /// Checks if a number of drawn vertices makes whole primitives.
+    pub fn fits(self, count: usize) -> bool {
+        match self {
+            PrimitiveMode::Points => count >= 1,
+            PrimitiveMode::Lines => count >= 2 && count.is_multiple_of(2),
+            PrimitiveMode::LineLoop | PrimitiveMode::LineStrip => count >= 2,
+            PrimitiveMode::Triangles => count >= 3 && count.is_multiple_of(3),
+            PrimitiveMode::TriangleStrip | PrimitiveMode::TriangleFan => count >= 3,
+        }
+    }
+}
+
+/// How normals generated by the MeshBuilder shade a Mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// Every triangle gets vertices of its own, with the normal of its face.
+    Flat,
+    /// Vertices get the normal of the faces around them, averaged.
+    Smooth,
+}
 
 /// Builder for a Mesh
 pub struct MeshBuilder {
     name: String,
-    vertices: Vec<u8>,
+    /// Vertex buffers, the first one set by vertices().
+    buffers: Vec<Vec<u8>>,
     indices: Vec<u8>,
+    index_type: IndexType,
+    mode: PrimitiveMode,
     uv: Vec<GLfloat>,
+    layout: Option<VertexLayout>,
+    keep_data: bool,
+    generate_normals: bool,
+    shading: Shading,
 }
 
 impl MeshBuilder {
@@ -20,90 +126,165 @@ impl MeshBuilder {
         self
     }
 
-    /// Add vertices to MeshBuilder.
+    /// Add vertices to MeshBuilder, as the first vertex buffer.
+    ///
+    /// Without a layout, these are positions of 3 floats each.
     pub fn vertices(mut self, v: Vec<u8>) -> MeshBuilder {
-        self.vertices = v;
+        match self.buffers.first_mut() {
+            Some(buffer) => *buffer = v,
+            None => self.buffers.push(v)
+        }
+        self
+    }
+
+    /// Add another vertex buffer to MeshBuilder, after the ones
+    /// supplied before. The layout refers to buffers by their order.
+    pub fn buffer(mut self, b: Vec<u8>) -> MeshBuilder {
+        if self.buffers.is_empty() {
+            self.buffers.push(Vec::new());
+        }
+        self.buffers.push(b);
         self
     }
 
-    /// Add indices to MeshBuilder.
+    /// Set how the attributes of the vertices are stored in the buffers.
+    pub fn layout(mut self, l: VertexLayout) -> MeshBuilder {
+        self.layout = Some(l);
+        self
+    }
+
+    /// Add indices to MeshBuilder, of the index type.
+    ///
+    /// Without indices, the vertices are drawn in order.
     pub fn indices(mut self, i: Vec<u8>) -> MeshBuilder {
         self.indices = i;
         self
     }
 
-    /// Add UVs to MeshBuilder.
+    /// Set the type of the indices, u16 by default.
+    pub fn index_type(mut self, t: IndexType) -> MeshBuilder {
+        self.index_type = t;
+        self
+    }
+
+    /// Set how the vertices are put together, triangles by default.
+    pub fn mode(mut self, m: PrimitiveMode) -> MeshBuilder {
+        self.mode = m;
+        self
+    }
+
+    /// Add UVs to MeshBuilder, of 2 floats each.
+    ///
+    /// Only used without a layout, which describes UVs itself otherwise.
     pub fn uv(mut self, u: Vec<GLfloat>) -> MeshBuilder {
         self.uv = u;
         self
     }
 
-    /// Converts the MeshBuilder into a Mesh.
+    /// Set the vertices, indices and primitive mode from CPU-side data,
+    /// laid out as described by MeshData.
+    pub fn data(mut self, d: MeshData) -> MeshBuilder {
+        let (layout, buffers) = d.buffers();
+        self.layout = Some(layout);
+        self.buffers = buffers;
+        self.indices = d.indices.iter().flat_map(|i| i.to_ne_bytes()).collect();
+        self.index_type = IndexType::U32;
+        self.mode = d.mode;
+        self
+    }
+
+    /// Keep a copy of the vertices and indices on the CPU, see Mesh::data().
+    pub fn keep_data(mut self, k: bool) -> MeshBuilder {
+        self.keep_data = k;
+        self
+    }
+
+    /// Compute normals for layouts without them, and tangents for those
+    /// with UVs but no tangents. On by default, only for triangles.
+    pub fn generate_normals(mut self, g: bool) -> MeshBuilder {
+        self.generate_normals = g;
+        self
+    }
+
+    /// Set how generated normals shade the Mesh, smooth by default.
     ///
-    /// If vertex, index, or shader data is not supplied,
-    /// the method errors.
-    pub fn build(self) -> Result<Mesh, String> {
-        if self.vertices.is_empty() {
-            return Err(String::from("Error: Did not supply vertices"));
-        }
+    /// glTF asks for flat shading of primitives without normals.
+    pub fn shading(mut self, s: Shading) -> MeshBuilder {
+        self.shading = s;
+        self
+    }
 
-        if self.indices.is_empty() {
-            return Err(String::from("Error: Did not supply indices"));
-        }
+    /// Converts the MeshBuilder into a Mesh.
+    ///
+    /// If vertex or name data is not supplied, the vertex buffers do not
+    /// match the layout, or the indices are out of range, the method errors.
+    pub fn build(mut self, _main_thread: &MainThread) -> Result<Mesh, String> {
+        let (mut layout, vertex_count) = self.validate()?;
 
-        if self.name.is_empty() {
-            return Err(String::from("Error: Did not supply name"));
+        let mut data = MeshData::read(&layout, &self.buffers, vertex_count, &self.indices, self.index_type, self.mode);
+        if self.generate_normals {
+            layout = self.generate_missing(layout, &mut data);
         }
+        // Flat normals give every triangle vertices of its own.
+        let vertex_count = data.positions.len();
+        let indexed = !self.indices.is_empty();
+        let aabb = data.aabb().unwrap();
+        let bounding_sphere = data.bounding_sphere().unwrap();
 
         let mut mesh = Mesh {
+            name: self.name,
             vao: 0,
-            vbo: 0,
+            vbos: vec![0; self.buffers.len()],
             ebo: 0,
-            uv: 0,
-            index_count: (self.indices.len() / std::mem::size_of::<u16>()) as _,
+            layout,
+            vertex_count,
+            index_type: if indexed { Some(self.index_type) } else { None },
+            mode: self.mode,
+            data: if self.keep_data { Some(data) } else { None },
+            aabb,
+            bounding_sphere,
+            index_count: (self.indices.len() / self.index_type.size()) as _,
         };
         
         unsafe {
             gl::GenVertexArrays(1, &mut mesh.vao);
             gl::BindVertexArray(mesh.vao);
 
-            gl::GenBuffers(1, &mut mesh.vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
-            gl::BufferData(gl::ARRAY_BUFFER, 
-                           //(self.vertices.len() /** std::mem::size_of::<f32>()*/) as gl::types::GLsizeiptr,
-                           (self.vertices.len()) as gl::types::GLsizeiptr,
-                           self.vertices.as_ptr() as *const _, 
-                           gl::STATIC_DRAW);
-
-            gl::GenBuffers(1, &mut mesh.ebo);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, 
-                           //(self.indices.len() * std::mem::size_of::<u16>()) as gl::types::GLsizeiptr,
-                           (self.indices.len()) as gl::types::GLsizeiptr,
-                           self.indices.as_ptr() as *const _, 
-                           gl::STATIC_DRAW);
-
-            gl::VertexAttribPointer(0, 3, 
-                                    gl::FLOAT, 
-                                    gl::FALSE, 
-                                    //(3 * std::mem::size_of::<f32>()) as gl::types::GLint,
-                                    0,
-                                    std::ptr::null());
-            gl::EnableVertexAttribArray(0);
-
-            if !self.uv.is_empty() {
-                gl::GenBuffers(1, &mut mesh.uv);
-                gl::BindBuffer(gl::ARRAY_BUFFER, mesh.uv);
+            gl::GenBuffers(self.buffers.len() as _, mesh.vbos.as_mut_ptr());
+            for (vbo, buffer) in mesh.vbos.iter().zip(&self.buffers) {
+                gl::BindBuffer(gl::ARRAY_BUFFER, *vbo);
                 gl::BufferData(gl::ARRAY_BUFFER, 
-                               (self.uv.len() * std::mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                               self.uv.as_ptr() as *const _, 
+                               buffer.len() as gl::types::GLsizeiptr,
+                               buffer.as_ptr() as *const _, 
                                gl::STATIC_DRAW);
-                gl::VertexAttribPointer(1, 2, 
-                                        gl::FLOAT, 
-                                        gl::FALSE, 
-                                        (2 * std::mem::size_of::<f32>()) as gl::types::GLint,
-                                        std::ptr::null());
-                gl::EnableVertexAttribArray(1);
+            }
+
+            if indexed {
+                gl::GenBuffers(1, &mut mesh.ebo);
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, 
+                               (self.indices.len()) as gl::types::GLsizeiptr,
+                               self.indices.as_ptr() as *const _, 
+                               gl::STATIC_DRAW);
+            }
+
+            for attribute in mesh.layout.attributes() {
+                gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbos[attribute.buffer]);
+                let offset = attribute.offset as *const std::ffi::c_void;
+                if attribute.component_type.is_integer() && !attribute.normalized && attribute.semantic == Semantic::Joints {
+                    // Joint indices are read as integers, not converted to floats.
+                    gl::VertexAttribIPointer(attribute.location, attribute.count as _,
+                                             attribute.component_type.gl_enum(),
+                                             attribute.stride as _,
+                                             offset);
+                } else {
+                    gl::VertexAttribPointer(attribute.location, attribute.count as _, 
+                                            attribute.component_type.gl_enum(), 
+                                            if attribute.normalized { gl::TRUE } else { gl::FALSE }, 
+                                            attribute.stride as _,
+                                            offset);
+                }
+                gl::EnableVertexAttribArray(attribute.location);
             }
 
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
@@ -112,15 +293,157 @@ impl MeshBuilder {
 
         Ok(mesh)
     }
+
+    /// Helper function for checking the data before anything is uploaded.
+    /// Returns the layout and the number of vertices.
+    fn validate(&mut self) -> Result<(VertexLayout, usize), String> {
+        if self.buffers.first().is_none_or(Vec::is_empty) {
+            return Err(String::from("Error: Did not supply vertices"));
+        }
+
+        if self.name.is_empty() {
+            return Err(String::from("Error: Did not supply name"));
+        }
+
+        let layout = match self.layout.take() {
+            Some(_) if !self.uv.is_empty() => {
+                return Err(String::from("Error: Supplied UVs along with a layout"));
+            }
+            Some(layout) => layout,
+            None => self.default_layout()
+        };
+        let buffer_sizes: Vec<usize> = self.buffers.iter().map(Vec::len).collect();
+        let vertex_count = layout.validate(&buffer_sizes)?;
+
+        let drawn = if self.indices.is_empty() {
+            vertex_count
+        } else {
+            if !self.indices.len().is_multiple_of(self.index_type.size()) {
+                return Err(format!("Error: Indices are not a whole number of {:?}", self.index_type));
+            }
+
+            let indices = self.index_type.read(&self.indices);
+            if let Some(index) = indices.iter().find(|i| **i as usize >= vertex_count) {
+                return Err(format!("Error: Index {} is out of range of {} vertices", index, vertex_count));
+            }
+            indices.len()
+        };
+        if !self.mode.fits(drawn) {
+            return Err(format!("Error: {} vertices do not make whole {:?}", drawn, self.mode));
+        }
+
+        Ok((layout, vertex_count))
+    }
+
+    /// Helper function for adding generated normals and tangents to the data,
+    /// and in buffers of their own to the layout. They take locations 2 and
+    /// 3 like in MeshData::buffers(), or the next free one if taken.
+    fn generate_missing(&mut self, mut layout: VertexLayout, data: &mut MeshData) -> VertexLayout {
+        let triangles = data.triangles();
+        if triangles.is_empty() {
+            return layout;
+        }
+
+        if layout.get(Semantic::Normal).is_none() {
+            match self.shading {
+                Shading::Flat => {
+                    layout = self.unindex(&layout, &triangles);
+                    data.compute_flat_normals();
+                }
+                Shading::Smooth => data.compute_smooth_normals()
+            }
+            let bytes = to_bytes(data.normals.iter().flat_map(|n| n.to_array()));
+            layout = self.add_buffer(layout, Semantic::Normal, 2, 3, bytes);
+        }
+
+        if layout.get(Semantic::Tangent).is_none() && !data.uvs.is_empty() && data.compute_tangents().is_ok() {
+            let bytes = to_bytes(data.tangents.iter().flat_map(|t| t.to_array()));
+            layout = self.add_buffer(layout, Semantic::Tangent, 3, 4, bytes);
+        }
+
+        layout
+    }
+
+    /// Helper function for giving every corner of the triangles a vertex of
+    /// its own, drawn as triangles without indices. Every attribute is moved
+    /// to a tightly packed buffer of its own.
+    fn unindex(&mut self, layout: &VertexLayout, triangles: &[[u32; 3]]) -> VertexLayout {
+        let buffers = std::mem::take(&mut self.buffers);
+        let mut unindexed = VertexLayout::new();
+        for attribute in layout.attributes() {
+            let (size, stride) = (attribute.size(), attribute.effective_stride());
+            let buffer = &buffers[attribute.buffer];
+            let bytes = triangles.iter()
+                .flatten()
+                .flat_map(|i| {
+                    let start = attribute.offset + *i as usize * stride;
+                    &buffer[start..start + size]
+                })
+                .copied()
+                .collect();
+
+            self.buffers.push(bytes);
+            unindexed = unindexed.attribute(VertexAttribute {
+                buffer: self.buffers.len() - 1,
+                offset: 0,
+                stride: 0,
+                ..*attribute
+            });
+        }
+
+        self.indices.clear();
+        self.mode = PrimitiveMode::Triangles;
+        unindexed
+    }
+
+    /// Helper function for adding an attribute of floats in a buffer of its own.
+    fn add_buffer(&mut self, layout: VertexLayout, semantic: Semantic, location: u32, count: u32, bytes: Vec<u8>) -> VertexLayout {
+        let locations: Vec<u32> = layout.attributes().iter().map(|attribute| attribute.location).collect();
+        let location = match locations.contains(&location) {
+            true => locations.iter().max().unwrap() + 1,
+            false => location
+        };
+
+        self.buffers.push(bytes);
+        layout.attribute(VertexAttribute::new(semantic, location, ComponentType::F32, count).buffer(self.buffers.len() - 1))
+    }
+
+    /// Helper function for the layout of meshes built without one:
+    /// positions of 3 floats at location 0 in the first buffer, and
+    /// UVs of 2 floats at location 1 in a buffer of their own.
+    fn default_layout(&mut self) -> VertexLayout {
+        let mut layout = VertexLayout::new()
+            .attribute(VertexAttribute::new(Semantic::Position, 0, ComponentType::F32, 3));
+
+        if !self.uv.is_empty() {
+            let uv = std::mem::take(&mut self.uv);
+            let bytes = uv.iter().flat_map(|f| f.to_ne_bytes()).collect();
+            layout = layout.attribute(VertexAttribute::new(Semantic::TexCoord(0), 1, ComponentType::F32, 2)
+                .buffer(self.buffers.len()));
+            self.buffers.push(bytes);
+        }
+
+        layout
+    }
 }
 
 /// The Mesh representation.
 #[derive(Debug)]
 pub struct Mesh {
+    name: String,
     pub vao: GLuint,
-    pub vbo: GLuint,
+    /// Vertex buffers, in the order the layout refers to them by.
+    pub vbos: Vec<GLuint>,
     pub ebo: GLuint,
-    pub uv: GLuint,
+    layout: VertexLayout,
+    vertex_count: usize,
+    /// None for meshes drawn without indices.
+    index_type: Option<IndexType>,
+    mode: PrimitiveMode,
+    /// Copy of the vertices and indices, if kept when building.
+    data: Option<MeshData>,
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
 
     pub index_count: i32,
 }
@@ -130,30 +453,255 @@ impl Mesh {
     pub fn new() -> MeshBuilder {
         MeshBuilder {
             name: String::new(),
-            vertices: Vec::new(),
+            buffers: Vec::new(),
             indices: Vec::new(),
+            index_type: IndexType::U16,
+            mode: PrimitiveMode::Triangles,
             uv: Vec::new(),
+            layout: None,
+            keep_data: false,
+            generate_normals: true,
+            shading: Shading::Smooth,
         }
     }
 
+    /// Name of the Mesh, which Assets and scenes refer to it by.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How the attributes of the vertices are stored in the vertex buffers.
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_count
+    }
+
+    /// Type of the indices, None if the Mesh has none.
+    pub fn index_type(&self) -> Option<IndexType> {
+        self.index_type
+    }
+
+    pub fn mode(&self) -> PrimitiveMode {
+        self.mode
+    }
+
+    /// CPU-side copy of the vertices and indices, if the Mesh was built
+    /// with keep_data(true).
+    pub fn data(&self) -> Option<&MeshData> {
+        self.data.as_ref()
+    }
+
+    /// Box around the vertices, in model space.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    /// Sphere around the vertices, in model space.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.bounding_sphere
+    }
+
     /// Draw the Mesh.
-    pub fn draw(&self) {
+    ///
+    /// Meshes without indices draw their vertices in order.
+    pub fn draw(&self, _main_thread: &MainThread) {
         unsafe { 
             gl::BindVertexArray(self.vao);
             gl::Enable(gl::DEPTH_TEST);
-            gl::DrawElements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_SHORT, std::ptr::null());
+            match self.index_type {
+                Some(index_type) => gl::DrawElements(self.mode.gl_enum(), self.index_count, index_type.gl_enum(), std::ptr::null()),
+                None => gl::DrawArrays(self.mode.gl_enum(), 0, self.vertex_count as _)
+            }
         }
     }
 
 }
 
+#[cfg(test)]
+impl Mesh {
+    /// A Mesh with only a name and nothing uploaded, for tests without GL.
+    pub(crate) fn unloaded(name: &str) -> Mesh {
+        Mesh {
+            name: name.to_string(),
+            vao: 0,
+            vbos: Vec::new(),
+            ebo: 0,
+            layout: VertexLayout::new(),
+            vertex_count: 0,
+            index_type: None,
+            mode: PrimitiveMode::Triangles,
+            data: None,
+            aabb: Aabb { min: glam::Vec3::ZERO, max: glam::Vec3::ZERO },
+            bounding_sphere: BoundingSphere { center: glam::Vec3::ZERO, radius: 0.0 },
+            index_count: 0,
+        }
+    }
+}
+
 impl Drop for Mesh {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteVertexArrays(1, &mut self.vao);
-            gl::DeleteBuffers(1, &mut self.vbo); 
-            gl::DeleteBuffers(1, &mut self.ebo); 
+        // Meshes which were never uploaded own no GL objects.
+        if self.vao == 0 {
+            return;
+        }
+
+        main_thread::delete_later(GlObject::VertexArray(self.vao));
+        let mut buffers = std::mem::take(&mut self.vbos);
+        if self.ebo != 0 {
+            buffers.push(self.ebo);
         }
+        main_thread::delete_later(GlObject::Buffers(buffers));
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Quat, Vec2, Vec3};
+    use super::*;
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|f| f.to_ne_bytes()).collect()
+    }
+
+    fn square() -> MeshBuilder {
+        Mesh::new()
+            .name("Square")
+            .vertices(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]))
+    }
+
+    #[test]
+    fn indices_of_every_type_are_checked_against_the_vertices() {
+        let u8s = vec![0, 1, 2, 0, 2, 3];
+        let u32s: Vec<u8> = [0u32, 1, 2, 0, 2, 3].iter().flat_map(|i| i.to_ne_bytes()).collect();
+        assert_eq!(IndexType::U32.read(&u32s), [0, 1, 2, 0, 2, 3]);
+        assert!(square().indices(u8s.clone()).index_type(IndexType::U8).validate().is_ok());
+        assert!(square().indices(u32s.clone()).index_type(IndexType::U32).validate().is_ok());
+
+        assert_eq!(IndexType::U16.read(&u32s).len(), 12);
+        assert_eq!(
+            square().indices(vec![0, 1, 2, 3]).index_type(IndexType::U8).validate().unwrap_err(),
+            "Error: 4 vertices do not make whole Triangles"
+        );
+        assert_eq!(
+            square().indices(vec![0, 1, 4]).index_type(IndexType::U8).validate().unwrap_err(),
+            "Error: Index 4 is out of range of 4 vertices"
+        );
+        assert!(square().indices(vec![0, 1, 2]).validate().is_err());
+    }
+
+    #[test]
+    fn data_is_read_back_from_any_layout() {
+        // Positions as normalized shorts, interleaved with float UVs.
+        let mut bytes = Vec::new();
+        for (position, uv) in [([-32767i16, 0, 0], [0.0f32, 0.0]), ([32767, 16384, 0], [1.0, 0.0]), ([0, 32767, -32767], [0.5, 1.0])].iter() {
+            bytes.extend(position.iter().flat_map(|p| p.to_ne_bytes()));
+            bytes.extend([0, 0]);
+            bytes.extend(uv.iter().flat_map(|uv| uv.to_ne_bytes()));
+        }
+        let layout = VertexLayout::new()
+            .attribute(VertexAttribute::new(Semantic::Position, 0, ComponentType::I16, 3).normalized(true).stride(16))
+            .attribute(VertexAttribute::new(Semantic::TexCoord(0), 1, ComponentType::F32, 2).offset(8).stride(16));
+        let mut builder = Mesh::new().name("Tilted").vertices(bytes).layout(layout);
+        let (layout, vertex_count) = builder.validate().unwrap();
+        let data = MeshData::read(&layout, &builder.buffers, vertex_count, &[], IndexType::U16, PrimitiveMode::Triangles);
+
+        assert_eq!(data.positions[0], Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(data.uvs[2], Vec2::new(0.5, 1.0));
+        assert!(data.normals.is_empty());
+        let aabb = data.aabb().unwrap();
+        assert_eq!((aabb.min, aabb.max), (Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 1.0, 0.0)));
+        let sphere = data.bounding_sphere().unwrap();
+        assert_eq!(sphere.center, Vec3::new(0.0, 0.5, -0.5));
+        assert!(data.positions.iter().all(|p| sphere.contains(*p)));
+
+        // Written out by MeshData and read back the same.
+        let mut builder = Mesh::new().name("Copy").data(data.clone());
+        let (layout, vertex_count) = builder.validate().unwrap();
+        assert_eq!(layout.get(Semantic::TexCoord(0)).unwrap().location, 1);
+        assert_eq!(MeshData::read(&layout, &builder.buffers, vertex_count, &builder.indices, builder.index_type, builder.mode), data);
+    }
+
+    #[test]
+    fn missing_normals_and_tangents_get_buffers_of_their_own() {
+        let mut builder = square()
+            .mode(PrimitiveMode::TriangleFan)
+            .uv(vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+        let (layout, vertex_count) = builder.validate().unwrap();
+        let mut data = MeshData::read(&layout, &builder.buffers, vertex_count, &[], IndexType::U16, PrimitiveMode::TriangleFan);
+        let layout = builder.generate_missing(layout, &mut data);
+
+        let buffer_sizes: Vec<usize> = builder.buffers.iter().map(Vec::len).collect();
+        assert_eq!(layout.validate(&buffer_sizes), Ok(4));
+        assert_eq!(layout.get(Semantic::Normal).unwrap().location, 2);
+        assert_eq!(layout.get(Semantic::Tangent).unwrap().location, 3);
+        assert_eq!(MeshData::read(&layout, &builder.buffers, vertex_count, &[], IndexType::U16, PrimitiveMode::TriangleFan), data);
+        assert_eq!(data.normals, vec![Vec3::Z; 4]);
+        assert!(data.tangents.iter().all(|t| *t == glam::Vec4::new(1.0, 0.0, 0.0, -1.0)));
+
+        // Flat normals split the vertices of a folded square, and every
+        // attribute with them.
+        let mut builder = square()
+            .vertices(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 1.0]))
+            .uv(vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0])
+            .indices(vec![0, 1, 2, 0, 2, 3])
+            .index_type(IndexType::U8)
+            .shading(Shading::Flat);
+        let (layout, vertex_count) = builder.validate().unwrap();
+        let mut data = MeshData::read(&layout, &builder.buffers, vertex_count, &builder.indices, builder.index_type, builder.mode);
+        let layout = builder.generate_missing(layout, &mut data);
+
+        assert!(builder.indices.is_empty());
+        let buffer_sizes: Vec<usize> = builder.buffers.iter().map(Vec::len).collect();
+        assert_eq!(layout.validate(&buffer_sizes), Ok(6));
+        assert_eq!(MeshData::read(&layout, &builder.buffers, 6, &[], IndexType::U16, PrimitiveMode::Triangles), data);
+        assert_eq!(data.uvs[4], Vec2::new(1.0, 0.0));
+        assert_eq!(data.normals[..3], [Vec3::Z; 3]);
+        assert_eq!(data.normals[3..], [Vec3::new(1.0, -1.0, 1.0).normalize(); 3]);
+
+        // Lines get neither.
+        let mut builder = square().mode(PrimitiveMode::Lines);
+        let (layout, vertex_count) = builder.validate().unwrap();
+        let mut data = MeshData::read(&layout, &builder.buffers, vertex_count, &[], IndexType::U16, PrimitiveMode::Lines);
+        assert_eq!(builder.generate_missing(layout.clone(), &mut data), layout);
+    }
+
+    #[test]
+    fn bounds_follow_transforms() {
+        let aabb = Aabb::from_points(&[Vec3::ZERO, Vec3::ONE]).unwrap();
+        let matrix = Mat4::from_scale_rotation_translation(Vec3::splat(2.0), Quat::from_rotation_z(std::f32::consts::FRAC_PI_2), Vec3::X);
+        let moved = aabb.transformed(&matrix);
+        assert!((moved.min - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((moved.max - Vec3::new(1.0, 2.0, 2.0)).length() < 1e-5);
+
+        let sphere = BoundingSphere::from_points(&[Vec3::ZERO, Vec3::ONE]).unwrap().transformed(&matrix);
+        assert!((sphere.radius - 3.0f32.sqrt()).abs() < 1e-5);
+        assert!(Aabb::from_points(&[]).is_none());
+    }
+
+    #[test]
+    fn vertices_without_indices_are_drawn_in_order() {
+        assert!(square().validate().is_err());
+        assert!(square().mode(PrimitiveMode::TriangleFan).validate().is_ok());
+        assert!(square().mode(PrimitiveMode::Lines).validate().is_ok());
+        assert!(square().mode(PrimitiveMode::Points).indices(vec![3]).index_type(IndexType::U8).validate().is_ok());
+        assert!(!PrimitiveMode::LineStrip.fits(1));
+    }
+
+    #[test]
+    fn meshes_dropped_on_other_threads_are_deleted_later() {
+        let mut mesh = Mesh::unloaded("Quad");
+        mesh.vao = 7;
+        mesh.vbos = vec![8, 9];
+        mesh.ebo = 10;
+
+        // GL is not loaded, so deleting right away would panic.
+        let mesh = std::sync::Arc::new(mesh);
+        std::thread::spawn(move || drop(mesh)).join().unwrap();
+        let dropped = main_thread::take_dropped();
+        assert!(dropped.contains(&GlObject::VertexArray(7)));
+        assert!(dropped.contains(&GlObject::Buffers(vec![8, 9, 10])));
+    }
+}
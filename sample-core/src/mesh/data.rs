@@ -0,0 +1,217 @@
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use super::{ComponentType, IndexType, PrimitiveMode, Semantic, VertexAttribute, VertexLayout};
+
+/// Vertices and indices of a Mesh, kept on the CPU.
+///
+/// Attributes other than positions are empty if the mesh has none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// Tangents, with the handedness of the bitangent in w.
+    pub tangents: Vec<Vec4>,
+    /// First set of texture coordinates.
+    pub uvs: Vec<Vec2>,
+    /// Empty for meshes drawn without indices.
+    pub indices: Vec<u32>,
+    pub mode: PrimitiveMode,
+}
+
+impl MeshData {
+    /// Triangles with the given positions and indices.
+    pub fn new(positions: Vec<Vec3>, indices: Vec<u32>) -> Self {
+        MeshData {
+            positions,
+            indices,
+            ..MeshData::default()
+        }
+    }
+
+    /// Box around the positions, None without positions.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(&self.positions)
+    }
+
+    /// Sphere around the positions, None without positions.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        BoundingSphere::from_points(&self.positions)
+    }
+
+    /// Layout of the data as built by MeshBuilder::data(): positions,
+    /// UVs, normals and tangents each in a buffer of their own, at
+    /// locations 0, 1, 2 and 3.
+    pub(crate) fn buffers(&self) -> (VertexLayout, Vec<Vec<u8>>) {
+        let streams = vec![
+            (Semantic::Position, 0, 3, to_bytes(self.positions.iter().flat_map(|p| p.to_array()))),
+            (Semantic::TexCoord(0), 1, 2, to_bytes(self.uvs.iter().flat_map(|uv| uv.to_array()))),
+            (Semantic::Normal, 2, 3, to_bytes(self.normals.iter().flat_map(|n| n.to_array()))),
+            (Semantic::Tangent, 3, 4, to_bytes(self.tangents.iter().flat_map(|t| t.to_array()))),
+        ];
+
+        let mut layout = VertexLayout::new();
+        let mut buffers = Vec::new();
+        for (semantic, location, count, bytes) in streams {
+            if bytes.is_empty() && semantic != Semantic::Position {
+                continue;
+            }
+
+            layout = layout.attribute(VertexAttribute::new(semantic, location, ComponentType::F32, count)
+                .buffer(buffers.len()));
+            buffers.push(bytes);
+        }
+
+        (layout, buffers)
+    }
+
+    /// Reads the data out of vertex buffers described by a layout, and
+    /// raw indices. Integer attributes are converted to floats.
+    pub(crate) fn read(layout: &VertexLayout, buffers: &[Vec<u8>], vertex_count: usize, indices: &[u8], index_type: IndexType, mode: PrimitiveMode) -> Self {
+        let read = |semantic| match layout.get(semantic) {
+            Some(attribute) => read_attribute(attribute, &buffers[attribute.buffer], vertex_count),
+            None => Vec::new()
+        };
+
+        MeshData {
+            positions: read(Semantic::Position).into_iter().map(|v| v.truncate()).collect(),
+            normals: read(Semantic::Normal).into_iter().map(|v| v.truncate()).collect(),
+            tangents: read(Semantic::Tangent),
+            uvs: read(Semantic::TexCoord(0)).into_iter().map(|v| Vec2::new(v.x, v.y)).collect(),
+            indices: index_type.read(indices),
+            mode,
+        }
+    }
+}
+
+/// Helper function for the native endian bytes of floats.
+pub(crate) fn to_bytes(values: impl Iterator<Item = f32>) -> Vec<u8> {
+    values.flat_map(f32::to_ne_bytes).collect()
+}
+
+/// Reads every value of an attribute, with missing components as 0.
+///
+/// Normalized integers are mapped to 0..1 or -1..1.
+pub(crate) fn read_attribute(attribute: &VertexAttribute, buffer: &[u8], vertex_count: usize) -> Vec<Vec4> {
+    let size = attribute.component_type.size();
+    (0..vertex_count)
+        .map(|vertex| {
+            let start = attribute.offset + vertex * attribute.effective_stride();
+            let mut value = [0.0; 4];
+            for (component, v) in value.iter_mut().enumerate().take(attribute.count as usize) {
+                let bytes = &buffer[start + component * size..start + (component + 1) * size];
+                *v = read_component(attribute.component_type, attribute.normalized, bytes);
+            }
+            Vec4::from(value)
+        })
+        .collect()
+}
+
+fn read_component(component_type: ComponentType, normalized: bool, bytes: &[u8]) -> f32 {
+    let (value, max) = match component_type {
+        ComponentType::I8 => (bytes[0] as i8 as f32, i8::MAX as f32),
+        ComponentType::U8 => (bytes[0] as f32, u8::MAX as f32),
+        ComponentType::I16 => (i16::from_ne_bytes([bytes[0], bytes[1]]) as f32, i16::MAX as f32),
+        ComponentType::U16 => (u16::from_ne_bytes([bytes[0], bytes[1]]) as f32, u16::MAX as f32),
+        ComponentType::I32 => (i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32, i32::MAX as f32),
+        ComponentType::U32 => (u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32, u32::MAX as f32),
+        ComponentType::F32 => return f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    };
+
+    match normalized {
+        true => (value / max).max(-1.0),
+        false => value
+    }
+}
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Smallest box holding every point, None without points.
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(points.iter().fold(Aabb { min: first, max: first }, |aabb, point| Aabb {
+            min: aabb.min.min(*point),
+            max: aabb.max.max(*point),
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half of the size along every axis.
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    /// Smallest box holding both boxes.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Box around the transformed box, e.g. in world space.
+    pub fn transformed(&self, matrix: &Mat4) -> Aabb {
+        let corners: Vec<Vec3> = (0..8)
+            .map(|i| Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            ))
+            .map(|corner| matrix.transform_point3(corner))
+            .collect();
+
+        Aabb::from_points(&corners).unwrap()
+    }
+}
+
+/// Bounding sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere around the center of the bounding box of the points,
+    /// reaching the furthest one. None without points.
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let center = Aabb::from_points(points)?.center();
+        let radius = points.iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        Some(BoundingSphere {
+            center,
+            radius,
+        })
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance_squared(self.center) <= self.radius * self.radius
+    }
+
+    /// Sphere around the transformed sphere, scaled by the largest scale
+    /// of the matrix.
+    pub fn transformed(&self, matrix: &Mat4) -> BoundingSphere {
+        let scale = matrix.x_axis.truncate().length()
+            .max(matrix.y_axis.truncate().length())
+            .max(matrix.z_axis.truncate().length());
+
+        BoundingSphere {
+            center: matrix.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use glam::{Vec3, Vec4};
+use super::{mikktspace, MeshData, PrimitiveMode};
+
+impl MeshData {
+    /// Corners of every triangle, in drawing order. Empty for points and
+    /// lines.
+    ///
+    /// Every other triangle of a strip is flipped back, so all of them
+    /// wind like the first.
+    pub fn triangles(&self) -> Vec<[u32; 3]> {
+        let count = match self.indices.is_empty() {
+            true => self.positions.len(),
+            false => self.indices.len()
+        };
+        let vertex = |i: usize| match self.indices.is_empty() {
+            true => i as u32,
+            false => self.indices[i]
+        };
+
+        match self.mode {
+            PrimitiveMode::Triangles => (0..count / 3)
+                .map(|i| [vertex(i * 3), vertex(i * 3 + 1), vertex(i * 3 + 2)])
+                .collect(),
+            PrimitiveMode::TriangleStrip => (0..count.saturating_sub(2))
+                .map(|i| match i % 2 {
+                    0 => [vertex(i), vertex(i + 1), vertex(i + 2)],
+                    _ => [vertex(i + 1), vertex(i), vertex(i + 2)]
+                })
+                .collect(),
+            PrimitiveMode::TriangleFan => (1..count.saturating_sub(1))
+                .map(|i| [vertex(0), vertex(i), vertex(i + 1)])
+                .collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Gives every triangle vertices of its own, with the normal of its
+    /// face. The result is drawn as triangles without indices.
+    ///
+    /// Does nothing for points and lines.
+    pub fn compute_flat_normals(&mut self) {
+        let corners: Vec<usize> = self.triangles().iter().flatten().map(|i| *i as usize).collect();
+        if corners.is_empty() {
+            return;
+        }
+
+        self.positions = corners.iter().map(|i| self.positions[*i]).collect();
+        self.normals = self.positions.chunks(3)
+            .flat_map(|p| {
+                let normal = (p[1] - p[0]).cross(p[2] - p[0]).normalize_or_zero();
+                vec![normal; 3]
+            })
+            .collect();
+        if !self.tangents.is_empty() {
+            self.tangents = corners.iter().map(|i| self.tangents[*i]).collect();
+        }
+        if !self.uvs.is_empty() {
+            self.uvs = corners.iter().map(|i| self.uvs[*i]).collect();
+        }
+        self.indices.clear();
+        self.mode = PrimitiveMode::Triangles;
+    }
+
+    /// Sets the normal of every vertex to the average of the faces around
+    /// it, weighted by the angle of their corner.
+    ///
+    /// Vertices at exactly the same position share their normal, so seams
+    /// of the UVs do not show. Does nothing for points and lines.
+    pub fn compute_smooth_normals(&mut self) {
+        let triangles = self.triangles();
+        if triangles.is_empty() {
+            return;
+        }
+
+        let key = |p: Vec3| p.to_array().map(bits);
+        let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+        for [a, b, c] in triangles {
+            let [a, b, c] = [a, b, c].map(|i| self.positions[i as usize]);
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            for (corner, next, previous) in [(a, b, c), (b, c, a), (c, a, b)] {
+                let angle = (next - corner).angle_between(previous - corner);
+                if angle.is_finite() {
+                    *sums.entry(key(corner)).or_insert(Vec3::ZERO) += normal * angle;
+                }
+            }
+        }
+
+        // Vertices of no triangle point up, like their default in shaders.
+        self.normals = self.positions.iter()
+            .map(|p| match sums.get(&key(*p)).map(|sum| sum.normalize_or_zero()) {
+                Some(normal) if normal != Vec3::ZERO => normal,
+                _ => Vec3::Y
+            })
+            .collect();
+    }
+
+    /// Sets the tangents from the normals and UVs with MikkTSpace, so
+    /// normal maps baked against it are shaded as intended.
+    ///
+    /// The w of the result is the handedness, with the bitangent being
+    /// w * normal x tangent. MikkTSpace gives every corner of a triangle a
+    /// tangent. Vertices are not split where corners sharing them get
+    /// different ones, which leaves them the tangent of the last corner.
+    pub fn compute_tangents(&mut self) -> Result<(), String> {
+        if self.normals.len() != self.positions.len() || self.uvs.len() != self.positions.len() {
+            return Err(String::from("Error: Tangents need a normal and a UV for every vertex"));
+        }
+
+        // Vertices of no triangle get any tangent.
+        let mut tangents: Vec<Vec4> = self.normals.iter()
+            .map(|n| n.any_orthonormal_vector().extend(1.0))
+            .collect();
+        let triangles = self.triangles();
+        let corners = mikktspace::generate(&self.positions, &self.normals, &self.uvs, &triangles);
+        for (vertex, tangent) in triangles.iter().flatten().zip(corners) {
+            tangents[*vertex as usize] = tangent;
+        }
+        self.tangents = tangents;
+
+        Ok(())
+    }
+}
+
+/// Helper function for the bits of a float to tell vertices apart by,
+/// the same for 0 and -0.
+pub(super) fn bits(value: f32) -> u32 {
+    (value + 0.0).to_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+    use super::*;
+    use super::super::shapes;
+
+    #[test]
+    fn normals_are_generated_flat_or_smooth() {
+        let strip = MeshData {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(1.0, 1.0, 0.0)],
+            mode: PrimitiveMode::TriangleStrip,
+            ..MeshData::default()
+        };
+        assert_eq!(strip.triangles(), vec![[0, 1, 2], [2, 1, 3]]);
+
+        // Corners of a cube touch three faces at right angles, however
+        // their quads are split.
+        let mut cube = shapes::cube(2.0);
+        cube.normals.clear();
+        cube.compute_smooth_normals();
+        for (position, normal) in cube.positions.iter().zip(&cube.normals) {
+            assert!(normal.abs_diff_eq(position.normalize(), 1e-5));
+        }
+
+        let mut sphere = shapes::uv_sphere(1.0, 16, 8);
+        let expected = sphere.normals.clone();
+        sphere.normals.clear();
+        sphere.compute_smooth_normals();
+        assert!(sphere.normals.iter().zip(&expected).all(|(n, e)| n.dot(*e) > 0.99));
+
+        cube.compute_flat_normals();
+        assert_eq!((cube.positions.len(), cube.uvs.len(), cube.indices.len()), (36, 36, 0));
+        for (p, n) in cube.positions.chunks(3).zip(cube.normals.chunks(3)) {
+            assert_eq!(n[0], (p[1] - p[0]).cross(p[2] - p[0]).normalize());
+            assert_eq!(n, [n[0]; 3]);
+        }
+    }
+
+    #[test]
+    fn tangents_follow_u_with_mirrored_uvs_flipped() {
+        for (name, mut data) in [("cube", shapes::cube(1.0)), ("sphere", shapes::uv_sphere(1.0, 16, 8)), ("torus", shapes::torus(2.0, 0.5, 16, 8))] {
+            let expected = std::mem::take(&mut data.tangents);
+            data.compute_tangents().unwrap();
+            for i in data.indices.iter().map(|i| *i as usize) {
+                let (t, e) = (data.tangents[i], expected[i]);
+                assert!(t.truncate().dot(e.truncate()) > 0.95 && t.w == e.w, "{}: {} against {}", name, t, e);
+            }
+        }
+
+        let mut quad = MeshData {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            normals: vec![Vec3::Z; 3],
+            uvs: vec![Vec2::ZERO, Vec2::X, Vec2::Y],
+            indices: vec![0, 1, 2],
+            ..MeshData::default()
+        };
+        quad.compute_tangents().unwrap();
+        assert_eq!(quad.tangents, vec![Vec4::new(1.0, 0.0, 0.0, 1.0); 3]);
+
+        quad.uvs = vec![Vec2::X, Vec2::ZERO, Vec2::new(1.0, 1.0)];
+        quad.compute_tangents().unwrap();
+        assert_eq!(quad.tangents, vec![Vec4::new(-1.0, 0.0, 0.0, -1.0); 3]);
+
+        quad.normals.clear();
+        assert!(quad.compute_tangents().is_err());
+    }
+
+    #[test]
+    fn tangents_match_mikktspace() {
+        let close = |tangents: &[Vec4], expected: &[Vec4]| {
+            tangents.len() == expected.len() && tangents.iter().zip(expected).all(|(t, e)| t.abs_diff_eq(*e, 1e-6))
+        };
+
+        // A fan with U along X in the first triangle and along (1, -1) in
+        // the second. Shared vertices average them by the angle of their
+        // corners, 90 and 45 degrees at the origin and the other way
+        // around at Y.
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let mut fan = MeshData {
+            positions: vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(-1.0, 1.0, 0.0)],
+            normals: vec![Vec3::Z; 4],
+            uvs: vec![Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::new(-1.0, 0.0)],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..MeshData::default()
+        };
+        fan.compute_tangents().unwrap();
+        let expected = [
+            Vec3::new(2.0 + s, -s, 0.0).normalize().extend(1.0),
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec3::new(1.0 + 2.0 * s, -2.0 * s, 0.0).normalize().extend(1.0),
+            Vec4::new(s, -s, 0.0, 1.0),
+        ];
+        assert!(close(&fan.tangents, &expected), "{:?}", fan.tangents);
+
+        // Corners with the same position, normal and UV are welded, so
+        // splitting the vertices changes nothing.
+        let mut split = fan.clone();
+        split.compute_flat_normals();
+        split.compute_tangents().unwrap();
+        let corners: Vec<Vec4> = fan.indices.iter().map(|i| fan.tangents[*i as usize]).collect();
+        assert_eq!(split.tangents, corners);
+
+        // Triangles with corners at the same position take the tangents of
+        // the corners they are welded with. Others get the X axis, with a
+        // flipped bitangent.
+        split.positions.extend([Vec3::ZERO, Vec3::X, Vec3::X]);
+        split.normals.extend([Vec3::Z; 3]);
+        split.uvs.extend([Vec2::ZERO, Vec2::X, Vec2::splat(0.5)]);
+        split.compute_tangents().unwrap();
+        assert_eq!(split.tangents[6..], [corners[0], corners[1], Vec4::new(1.0, 0.0, 0.0, -1.0)]);
+
+        // Mirrored UVs start a group of their own, even across vertices
+        // welded with the other triangle.
+        split.positions.truncate(6);
+        split.normals.truncate(6);
+        split.uvs = vec![Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ZERO, Vec2::Y, Vec2::X];
+        split.positions[5] = -Vec3::X;
+        split.compute_tangents().unwrap();
+        let expected = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(-1.0, 0.0, 0.0, -1.0)];
+        assert!(close(&split.tangents, &[expected[0], expected[0], expected[0], expected[1], expected[1], expected[1]]), "{:?}", split.tangents);
+    }
+}
@@ -0,0 +1,306 @@
+use gl::types::GLenum;
+
+/// What a vertex attribute holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Semantic {
+    Position,
+    Normal,
+    Tangent,
+    /// Vertex colors of the given set.
+    Color(u32),
+    /// Texture coordinates of the given set.
+    TexCoord(u32),
+    Joints,
+    Weights,
+}
+
+/// Type of the components of a vertex attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+}
+
+impl ComponentType {
+    /// Size of one component in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            ComponentType::I8 | ComponentType::U8 => 1,
+            ComponentType::I16 | ComponentType::U16 => 2,
+            ComponentType::I32 | ComponentType::U32 | ComponentType::F32 => 4,
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        self != ComponentType::F32
+    }
+
+    pub(crate) fn gl_enum(self) -> GLenum {
+        match self {
+            ComponentType::I8 => gl::BYTE,
+            ComponentType::U8 => gl::UNSIGNED_BYTE,
+            ComponentType::I16 => gl::SHORT,
+            ComponentType::U16 => gl::UNSIGNED_SHORT,
+            ComponentType::I32 => gl::INT,
+            ComponentType::U32 => gl::UNSIGNED_INT,
+            ComponentType::F32 => gl::FLOAT,
+        }
+    }
+}
+
+/// Where and how one attribute is stored in the vertex buffers of a Mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VertexAttribute {
+    pub semantic: Semantic,
+    /// Location of the attribute in the shader.
+    pub location: u32,
+    pub component_type: ComponentType,
+    /// Number of components, from 1 to 4.
+    pub count: u32,
+    /// Whether integer components are mapped to 0..1 or -1..1.
+    pub normalized: bool,
+    /// Index of the vertex buffer holding the attribute.
+    pub buffer: usize,
+    /// Bytes from the start of the buffer to the first value.
+    pub offset: usize,
+    /// Bytes from one vertex to the next, 0 for tightly packed values.
+    pub stride: usize,
+}
+
+impl VertexAttribute {
+    /// A tightly packed attribute at the start of the first buffer.
+    pub fn new(semantic: Semantic, location: u32, component_type: ComponentType, count: u32) -> Self {
+        VertexAttribute {
+            semantic,
+            location,
+            component_type,
+            count,
+            normalized: false,
+            buffer: 0,
+            offset: 0,
+            stride: 0,
+        }
+    }
+
+    pub fn normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+
+    pub fn buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer;
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    /// Size of the value of one vertex in bytes.
+    pub fn size(&self) -> usize {
+        self.component_type.size() * self.count as usize
+    }
+
+    /// Bytes from one vertex to the next, including for tightly packed values.
+    pub fn effective_stride(&self) -> usize {
+        match self.stride {
+            0 => self.size(),
+            stride => stride
+        }
+    }
+
+    /// Number of whole values in a buffer of the given size.
+    fn vertex_count(&self, buffer_size: usize) -> usize {
+        match buffer_size.checked_sub(self.offset + self.size()) {
+            Some(rest) => rest / self.effective_stride() + 1,
+            None => 0
+        }
+    }
+}
+
+/// Attributes of the vertices of a Mesh, in one or more vertex buffers.
+///
+/// Attributes may be interleaved in one buffer, each in a buffer of
+/// their own, or any mix of both.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        VertexLayout::default()
+    }
+
+    /// Adds an attribute.
+    pub fn attribute(mut self, attribute: VertexAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    /// Layout of one buffer interleaving the given attributes, in order.
+    /// Locations are given in the same order, starting at 0.
+    pub fn interleaved(attributes: &[(Semantic, ComponentType, u32)]) -> Self {
+        let stride = attributes.iter()
+            .map(|(_, component_type, count)| component_type.size() * *count as usize)
+            .sum();
+
+        let mut layout = VertexLayout::new();
+        let mut offset = 0;
+        for (location, (semantic, component_type, count)) in attributes.iter().enumerate() {
+            let attribute = VertexAttribute::new(*semantic, location as u32, *component_type, *count)
+                .offset(offset)
+                .stride(stride);
+            offset += attribute.size();
+            layout = layout.attribute(attribute);
+        }
+
+        layout
+    }
+
+    /// Layout of tightly packed attributes, each in a buffer of their own.
+    /// Locations and buffers are given in the same order, starting at 0.
+    pub fn planar(attributes: &[(Semantic, ComponentType, u32)]) -> Self {
+        attributes.iter()
+            .enumerate()
+            .fold(VertexLayout::new(), |layout, (index, (semantic, component_type, count))| {
+                layout.attribute(VertexAttribute::new(*semantic, index as u32, *component_type, *count).buffer(index))
+            })
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    /// Gets the attribute of a semantic.
+    pub fn get(&self, semantic: Semantic) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|attribute| attribute.semantic == semantic)
+    }
+
+    /// Checks the layout against the sizes of the vertex buffers, in bytes.
+    /// Returns the number of vertices.
+    ///
+    /// Every buffer has to end with the last vertex, or the padding after
+    /// it, so every attribute holds the same number of vertices.
+    pub fn validate(&self, buffer_sizes: &[usize]) -> Result<usize, String> {
+        if self.get(Semantic::Position).is_none() {
+            return Err(String::from("Error: Layout has no Position attribute"));
+        }
+
+        for (index, attribute) in self.attributes.iter().enumerate() {
+            let name = format!("{:?}", attribute.semantic);
+            if !(1..=4).contains(&attribute.count) {
+                return Err(format!("Error: {} has {} components", name, attribute.count));
+            }
+            if attribute.normalized && !attribute.component_type.is_integer() {
+                return Err(format!("Error: {} is normalized, but not made of integers", name));
+            }
+            if attribute.stride != 0 && attribute.offset % attribute.stride + attribute.size() > attribute.stride {
+                return Err(format!("Error: {} does not fit in its stride of {} bytes", name, attribute.stride));
+            }
+            if attribute.buffer >= buffer_sizes.len() {
+                return Err(format!("Error: {} is in buffer {}, but only {} were supplied", name, attribute.buffer, buffer_sizes.len()));
+            }
+
+            for other in &self.attributes[..index] {
+                if other.semantic == attribute.semantic {
+                    return Err(format!("Error: {} appears twice", name));
+                }
+                if other.location == attribute.location {
+                    return Err(format!("Error: {:?} and {} both use location {}", other.semantic, name, attribute.location));
+                }
+            }
+        }
+
+        let vertex_count = self.attributes.iter()
+            .map(|attribute| attribute.vertex_count(buffer_sizes[attribute.buffer]))
+            .min()
+            .unwrap();
+        if vertex_count == 0 {
+            return Err(String::from("Error: Did not supply vertices"));
+        }
+
+        for (buffer, size) in buffer_sizes.iter().enumerate() {
+            let attributes: Vec<&VertexAttribute> = self.attributes.iter()
+                .filter(|attribute| attribute.buffer == buffer)
+                .collect();
+            if attributes.is_empty() {
+                return Err(format!("Error: Buffer {} has no attributes", buffer));
+            }
+
+            // The last vertex may be padded up to the stride.
+            let end = attributes.iter()
+                .map(|attribute| attribute.offset + (vertex_count - 1) * attribute.effective_stride() + attribute.size())
+                .max()
+                .unwrap();
+            let padded = attributes.iter()
+                .map(|attribute| attribute.offset - attribute.offset % attribute.effective_stride() + vertex_count * attribute.effective_stride())
+                .max()
+                .unwrap();
+            if *size != end && *size != padded {
+                return Err(format!("Error: Buffer {} is {} bytes, but {} vertices take {}", buffer, size, vertex_count, end));
+            }
+        }
+
+        Ok(vertex_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved_and_planar_layouts_match_their_buffers() {
+        let interleaved = VertexLayout::interleaved(&[
+            (Semantic::Position, ComponentType::F32, 3),
+            (Semantic::Normal, ComponentType::F32, 3),
+            (Semantic::Color(0), ComponentType::U8, 4),
+        ]);
+        let normal = interleaved.get(Semantic::Normal).unwrap();
+        assert_eq!((normal.location, normal.offset, normal.stride), (1, 12, 28));
+        assert_eq!(interleaved.validate(&[28 * 4]), Ok(4));
+        assert!(interleaved.validate(&[28 * 4 + 10]).is_err());
+        assert!(interleaved.validate(&[28 * 4, 12]).is_err());
+
+        let planar = VertexLayout::planar(&[
+            (Semantic::Position, ComponentType::F32, 3),
+            (Semantic::TexCoord(0), ComponentType::F32, 2),
+        ]);
+        assert_eq!(planar.validate(&[12 * 3, 8 * 3]), Ok(3));
+        assert_eq!(
+            planar.validate(&[12 * 3, 8 * 4]),
+            Err(String::from("Error: Buffer 1 is 32 bytes, but 3 vertices take 24"))
+        );
+
+        // Positions and normals one after the other in a single buffer.
+        let batched = VertexLayout::new()
+            .attribute(VertexAttribute::new(Semantic::Position, 0, ComponentType::F32, 3))
+            .attribute(VertexAttribute::new(Semantic::Normal, 1, ComponentType::F32, 3).offset(12 * 5));
+        assert_eq!(batched.validate(&[12 * 10]), Ok(5));
+    }
+
+    #[test]
+    fn bad_attributes_are_errors() {
+        let position = VertexAttribute::new(Semantic::Position, 0, ComponentType::F32, 3);
+        let layout = |attribute| VertexLayout::new().attribute(position).attribute(attribute);
+
+        assert!(VertexLayout::new().validate(&[12]).is_err());
+        assert!(layout(VertexAttribute::new(Semantic::Normal, 1, ComponentType::F32, 5).buffer(1)).validate(&[12, 20]).is_err());
+        assert!(layout(VertexAttribute::new(Semantic::Normal, 1, ComponentType::F32, 3).normalized(true).buffer(1)).validate(&[12, 12]).is_err());
+        assert!(layout(VertexAttribute::new(Semantic::Normal, 0, ComponentType::F32, 3).buffer(1)).validate(&[12, 12]).is_err());
+        assert!(layout(VertexAttribute::new(Semantic::Color(0), 1, ComponentType::U8, 4).normalized(true).buffer(1)).validate(&[12, 4]).is_ok());
+        assert!(layout(VertexAttribute::new(Semantic::Normal, 1, ComponentType::F32, 3).offset(8).stride(16).buffer(1)).validate(&[12, 16]).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! Tangent spaces the way mikktspace.c by Morten S. Mikkelsen generates
+//! them, which is what normal maps are baked against.
+//!
+//! Ported from the reference implementation, for triangles only, which
+//! leaves out its handling of quads. Edges are sorted fully before finding
+//! neighbors, where the original skips sorting the last run of them. Only
+//! the tangent and the handedness of the bitangent are generated, like
+//! genTangSpaceDefault() with setTSpaceBasic().
+//!
+//! The original is under the following license.
+//!
+//! Copyright (C) 2011 by Morten S. Mikkelsen
+//!
+//! This software is provided 'as-is', without any express or implied
+//! warranty. In no event will the authors be held liable for any damages
+//! arising from the use of this software.
+//!
+//! Permission is granted to anyone to use this software for any purpose,
+//! including commercial applications, and to alter it and redistribute it
+//! freely, subject to the following restrictions:
+//!
+//! 1. The origin of this software must not be misrepresented; you must not
+//!    claim that you wrote the original software. If you use this software
+//!    in a product, an acknowledgment in the product documentation would be
+//!    appreciated but is not required.
+//! 2. Altered source versions must be plainly marked as such, and must not be
+//!    misrepresented as being the original software.
+//! 3. This notice may not be removed or altered from any source distribution.
+
+use std::collections::HashMap;
+use glam::{Vec2, Vec3, Vec4};
+use super::generate::bits;
+
+/// The triangle has no UV area, or no extent along U or V. It joins the
+/// groups of its neighbors without adding to their tangents.
+const GROUP_WITH_ANY: u8 = 1;
+/// The UVs wind the same way as the positions.
+const ORIENT_PRESERVING: u8 = 2;
+
+/// Cosine of the largest angle between the tangents of triangles that are
+/// still averaged. The original uses an angular threshold of 180 degrees,
+/// which averages all of them.
+const THRESHOLD_COS: f32 = -1.0;
+
+/// The positions, normals and UVs tangents are generated from.
+struct Geometry<'a> {
+    positions: &'a [Vec3],
+    normals: &'a [Vec3],
+    uvs: &'a [Vec2],
+    triangles: &'a [[u32; 3]],
+}
+
+impl<'a> Geometry<'a> {
+    /// Helper function for the vertex at a corner, counted over the corners
+    /// of all triangles.
+    fn vertex(&self, corner: usize) -> usize {
+        self.triangles[corner / 3][corner % 3] as usize
+    }
+
+    fn position(&self, corner: usize) -> Vec3 {
+        self.positions[self.vertex(corner)]
+    }
+
+    fn normal(&self, corner: usize) -> Vec3 {
+        self.normals[self.vertex(corner)]
+    }
+
+    fn uv(&self, corner: usize) -> Vec2 {
+        self.uvs[self.vertex(corner)]
+    }
+
+    /// Helper function for welding corners with the same position, normal
+    /// and UV. Every corner is replaced by the first one it is welded with.
+    fn weld(&self) -> Vec<usize> {
+        let mut firsts: HashMap<[u32; 8], usize> = HashMap::new();
+        (0..self.triangles.len() * 3)
+            .map(|corner| {
+                let (p, n, uv) = (self.position(corner), self.normal(corner), self.uv(corner));
+                *firsts.entry([p.x, p.y, p.z, n.x, n.y, n.z, uv.x, uv.y].map(bits)).or_insert(corner)
+            })
+            .collect()
+    }
+}
+
+/// A triangle without corners at the same position.
+struct TriInfo {
+    /// Triangles across the edges starting at each corner.
+    neighbors: [Option<usize>; 3],
+    /// Groups each corner is in.
+    groups: [Option<usize>; 3],
+    /// Direction U increases in, of unit length unless zero.
+    os: Vec3,
+    /// Direction V increases in, of unit length unless zero.
+    ot: Vec3,
+    flags: u8,
+}
+
+/// Triangles sharing a vertex and the orientation of their UVs, connected
+/// through their edges.
+struct Group {
+    /// Triangles in the order they were added.
+    faces: Vec<usize>,
+    /// Welded corner the triangles share.
+    vertex: usize,
+    orient_preserving: bool,
+}
+
+/// Tangents of every corner of the triangles, with the handedness of the
+/// bitangent in w.
+///
+/// Normals are expected to be normalized.
+pub(super) fn generate(positions: &[Vec3], normals: &[Vec3], uvs: &[Vec2], triangles: &[[u32; 3]]) -> Vec<Vec4> {
+    let geometry = Geometry { positions, normals, uvs, triangles };
+    let welded = geometry.weld();
+
+    // Triangles with corners at the same position go last, keeping the
+    // order of the others.
+    let (good, degenerate): (Vec<usize>, Vec<usize>) = (0..triangles.len())
+        .partition(|f| {
+            let [a, b, c] = [0, 1, 2].map(|i| geometry.position(f * 3 + i));
+            a != b && a != c && b != c
+        });
+    let good_count = good.len();
+    let faces: Vec<usize> = good.into_iter().chain(degenerate).collect();
+    let tri_list: Vec<usize> = faces.iter()
+        .flat_map(|f| welded[f * 3..f * 3 + 3].iter().copied())
+        .collect();
+
+    let mut infos = init_tri_info(&geometry, &tri_list, good_count);
+    let groups = build_4_rule_groups(&mut infos, &tri_list);
+
+    let mut tangents = vec![Vec4::new(1.0, 0.0, 0.0, -1.0); triangles.len() * 3];
+    generate_tspaces(&geometry, &infos, &groups, &tri_list, &faces, &mut tangents);
+    degen_epilogue(&tri_list, &faces, good_count, &mut tangents);
+    tangents
+}
+
+/// Helper function for the directions U and V increase in across the first
+/// count triangles of the list, and the neighbors of these triangles.
+fn init_tri_info(geometry: &Geometry, tri_list: &[usize], count: usize) -> Vec<TriInfo> {
+    let mut infos: Vec<TriInfo> = (0..count)
+        .map(|f| {
+            let [v1, v2, v3] = [0, 1, 2].map(|i| geometry.position(tri_list[f * 3 + i]));
+            let [t1, t2, t3] = [0, 1, 2].map(|i| geometry.uv(tri_list[f * 3 + i]));
+            let (t21, t31) = (t2 - t1, t3 - t1);
+            let (d1, d2) = (v2 - v1, v3 - v1);
+
+            let signed_area = t21.x * t31.y - t21.y * t31.x;
+            let mut os = d1 * t31.y - d2 * t21.y;
+            let mut ot = d1 * -t31.x + d2 * t21.x;
+            let mut flags = GROUP_WITH_ANY;
+            if signed_area > 0.0 {
+                flags |= ORIENT_PRESERVING;
+            }
+
+            if not_zero(signed_area) {
+                let area = signed_area.abs();
+                let (length_s, length_t) = (os.length(), ot.length());
+                let sign = if flags & ORIENT_PRESERVING != 0 { 1.0 } else { -1.0 };
+                if not_zero(length_s) {
+                    os *= sign / length_s;
+                }
+                if not_zero(length_t) {
+                    ot *= sign / length_t;
+                }
+                if not_zero(length_s / area) && not_zero(length_t / area) {
+                    flags &= !GROUP_WITH_ANY;
+                }
+            }
+
+            TriInfo {
+                neighbors: [None; 3],
+                groups: [None; 3],
+                os,
+                ot,
+                flags,
+            }
+        })
+        .collect();
+
+    build_neighbors(&mut infos, tri_list);
+    infos
+}
+
+/// Helper function for pairing up triangles sharing an edge in opposite
+/// directions. Where more than two share it, the first triangles pair up.
+fn build_neighbors(infos: &mut [TriInfo], tri_list: &[usize]) {
+    // Lower and higher corner of every edge, and its triangle.
+    let mut edges: Vec<(usize, usize, usize)> = (0..infos.len())
+        .flat_map(|f| (0..3).map(move |i| {
+            let (i0, i1) = (tri_list[f * 3 + i], tri_list[f * 3 + (i + 1) % 3]);
+            (i0.min(i1), i0.max(i1), f)
+        }))
+        .collect();
+    edges.sort_unstable();
+
+    for (i, &(i0, i1, f)) in edges.iter().enumerate() {
+        let (a, edge_a) = edge(&tri_list[f * 3..f * 3 + 3], i0, i1);
+        if infos[f].neighbors[edge_a].is_some() {
+            continue;
+        }
+
+        let found = edges[i + 1..].iter()
+            .take_while(|(j0, j1, _)| (*j0, *j1) == (i0, i1))
+            .map(|&(_, _, t)| (t, edge(&tri_list[t * 3..t * 3 + 3], i0, i1)))
+            .find(|(t, (b, edge_b))| (b.1, b.0) == a && infos[*t].neighbors[*edge_b].is_none());
+        if let Some((t, (_, edge_b))) = found {
+            infos[f].neighbors[edge_a] = Some(t);
+            infos[t].neighbors[edge_b] = Some(f);
+        }
+    }
+}
+
+/// Helper function for the corners of a triangle an edge goes between, in
+/// the order of the triangle, and the edge of the triangle it is.
+fn edge(corners: &[usize], i0: usize, i1: usize) -> ((usize, usize), usize) {
+    if corners[0] == i0 || corners[0] == i1 {
+        if corners[1] == i0 || corners[1] == i1 {
+            ((corners[0], corners[1]), 0)
+        } else {
+            ((corners[2], corners[0]), 2)
+        }
+    } else {
+        ((corners[1], corners[2]), 1)
+    }
+}
+
+/// Helper function for grouping the corners of the triangles. Every corner
+/// of a triangle with tangents starts a group unless it is in one already,
+/// which its neighbors around the corner join as long as they keep its
+/// orientation.
+fn build_4_rule_groups(infos: &mut [TriInfo], tri_list: &[usize]) -> Vec<Group> {
+    let mut groups = Vec::new();
+    for f in 0..infos.len() {
+        for i in 0..3 {
+            if infos[f].flags & GROUP_WITH_ANY != 0 || infos[f].groups[i].is_some() {
+                continue;
+            }
+
+            let index = groups.len();
+            let mut group = Group {
+                faces: vec![f],
+                vertex: tri_list[f * 3 + i],
+                orient_preserving: infos[f].flags & ORIENT_PRESERVING != 0,
+            };
+            infos[f].groups[i] = Some(index);
+            for neighbor in [infos[f].neighbors[i], infos[f].neighbors[(i + 2) % 3]].iter().flatten() {
+                assign(infos, tri_list, &mut group, index, *neighbor);
+            }
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+/// Helper function for adding a triangle to a group, followed by its
+/// neighbors around the vertex of the group, depth first.
+fn assign(infos: &mut [TriInfo], tri_list: &[usize], group: &mut Group, index: usize, triangle: usize) {
+    let mut stack = vec![triangle];
+    while let Some(t) = stack.pop() {
+        let i = (0..3).find(|i| tri_list[t * 3 + i] == group.vertex).unwrap();
+        let info = &mut infos[t];
+        if info.groups[i].is_some() {
+            continue;
+        }
+
+        // The first group a triangle without tangents joins decides its
+        // orientation.
+        if info.flags & GROUP_WITH_ANY != 0 && info.groups == [None; 3] {
+            info.flags &= !ORIENT_PRESERVING;
+            if group.orient_preserving {
+                info.flags |= ORIENT_PRESERVING;
+            }
+        }
+        if (info.flags & ORIENT_PRESERVING != 0) != group.orient_preserving {
+            continue;
+        }
+
+        group.faces.push(t);
+        info.groups[i] = Some(index);
+        // The left neighbor goes on top, to be visited first.
+        stack.extend([info.neighbors[(i + 2) % 3], info.neighbors[i]].iter().flatten());
+    }
+}
+
+/// Helper function for setting the tangents of the corners in every group.
+///
+/// The triangles of a group are split into subgroups of those whose
+/// tangents are close enough to the tangent of each triangle. Every
+/// distinct subgroup gets its tangents averaged.
+fn generate_tspaces(geometry: &Geometry, infos: &[TriInfo], groups: &[Group], tri_list: &[usize], faces: &[usize], tangents: &mut [Vec4]) {
+    for (g, group) in groups.iter().enumerate() {
+        let mut subgroups: Vec<(Vec<usize>, Vec3)> = Vec::new();
+        for &f in &group.faces {
+            let i = (0..3).find(|i| infos[f].groups[*i] == Some(g)).unwrap();
+            let n = geometry.normal(tri_list[f * 3 + i]);
+            let (os, ot) = (project(infos[f].os, n), project(infos[f].ot, n));
+
+            let mut members: Vec<usize> = group.faces.iter()
+                .copied()
+                .filter(|t| {
+                    let any = (infos[f].flags | infos[*t].flags) & GROUP_WITH_ANY != 0;
+                    let (os2, ot2) = (project(infos[*t].os, n), project(infos[*t].ot, n));
+                    any || f == *t || (os.dot(os2) > THRESHOLD_COS && ot.dot(ot2) > THRESHOLD_COS)
+                })
+                .collect();
+            members.sort_unstable();
+
+            let tangent = match subgroups.iter().find(|(faces, _)| *faces == members) {
+                Some((_, tangent)) => *tangent,
+                None => {
+                    let tangent = eval_tspace(geometry, infos, tri_list, &members, group.vertex);
+                    subgroups.push((members, tangent));
+                    tangent
+                }
+            };
+            tangents[faces[f] * 3 + i] = tangent.extend(if group.orient_preserving { 1.0 } else { -1.0 });
+        }
+    }
+}
+
+/// Helper function for averaging the tangents of triangles at a vertex,
+/// weighted by the angle of their corner.
+fn eval_tspace(geometry: &Geometry, infos: &[TriInfo], tri_list: &[usize], members: &[usize], vertex: usize) -> Vec3 {
+    let mut sum = Vec3::ZERO;
+    for &f in members {
+        if infos[f].flags & GROUP_WITH_ANY != 0 {
+            continue;
+        }
+
+        let i = (0..3).find(|i| tri_list[f * 3 + i] == vertex).unwrap();
+        let n = geometry.normal(tri_list[f * 3 + i]);
+        let [p0, p1, p2] = [(i + 2) % 3, i, (i + 1) % 3].map(|j| geometry.position(tri_list[f * 3 + j]));
+        let (v1, v2) = (project(p0 - p1, n), project(p2 - p1, n));
+        let angle = (v1.dot(v2).clamp(-1.0, 1.0) as f64).acos() as f32;
+        sum += project(infos[f].os, n) * angle;
+    }
+    normalize(sum)
+}
+
+/// Helper function for giving the corners of triangles with corners at the
+/// same position the tangent of the first corner of another triangle they
+/// were welded with.
+fn degen_epilogue(tri_list: &[usize], faces: &[usize], good_count: usize, tangents: &mut [Vec4]) {
+    let mut firsts = HashMap::new();
+    for (j, corner) in tri_list[..good_count * 3].iter().enumerate() {
+        firsts.entry(*corner).or_insert(j);
+    }
+
+    for t in good_count..faces.len() {
+        for i in 0..3 {
+            if let Some(j) = firsts.get(&tri_list[t * 3 + i]) {
+                tangents[faces[t] * 3 + i] = tangents[faces[j / 3] * 3 + j % 3];
+            }
+        }
+    }
+}
+
+/// Helper function for projecting a vector onto the plane of a normal,
+/// normalized.
+fn project(v: Vec3, n: Vec3) -> Vec3 {
+    normalize(v - n * n.dot(v))
+}
+
+/// Helper function for normalizing vectors that are not zero.
+fn normalize(v: Vec3) -> Vec3 {
+    match not_zero(v.x) || not_zero(v.y) || not_zero(v.z) {
+        true => v * (1.0 / v.length()),
+        false => v
+    }
+}
+
+/// Helper function for telling whether a float is further from zero than
+/// the smallest normal float.
+fn not_zero(value: f32) -> bool {
+    value.abs() > f32::MIN_POSITIVE
+}
@@ -0,0 +1,386 @@
+//! Generators of primitive shapes, centered on the origin with Y up.
+//!
+//! Triangles wind counter-clockwise seen from outside. UVs start at the
+//! top left of every surface, as textures are uploaded top row first,
+//! and tangents point along U, with the handedness of the bitangent in w.
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use glam::{Vec2, Vec3, Vec4};
+use super::MeshData;
+
+/// Quad of the given size in the XY plane, facing +Z.
+pub fn quad(width: f32, height: f32) -> MeshData {
+    let mut data = MeshData::default();
+    grid(&mut data, Vec3::ZERO, Vec3::X * width, -Vec3::Y * height, 1, 1);
+    data
+}
+
+/// Plane of the given size in the XZ plane, facing +Y, made of
+/// subdivisions_x by subdivisions_z quads.
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> MeshData {
+    let mut data = MeshData::default();
+    grid(&mut data, Vec3::ZERO, Vec3::X * width, Vec3::Z * depth, subdivisions_x.max(1), subdivisions_z.max(1));
+    data
+}
+
+/// Cube with edges of the given size, each face mapped to the whole texture.
+pub fn cube(size: f32) -> MeshData {
+    let mut data = MeshData::default();
+    // Normal and right of every face, up being normal x right.
+    let faces = vec![
+        (Vec3::Z, Vec3::X),
+        (-Vec3::Z, -Vec3::X),
+        (Vec3::X, -Vec3::Z),
+        (-Vec3::X, Vec3::Z),
+        (Vec3::Y, Vec3::X),
+        (-Vec3::Y, Vec3::X),
+    ];
+
+    for (normal, right) in faces {
+        let down = right.cross(normal);
+        grid(&mut data, normal * size * 0.5, right * size, down * size, 1, 1);
+    }
+    data
+}
+
+/// Sphere made of segments around the Y axis and rings from pole to pole.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let rings = rings.max(2);
+    let profile: Vec<ProfilePoint> = (0..=rings)
+        .map(|ring| {
+            let theta = ring as f32 / rings as f32 * PI;
+            ProfilePoint::arc(theta, radius, 0.0, ring == 0 || ring == rings)
+        })
+        .collect();
+
+    lathe(&profile, segments.max(3))
+}
+
+/// Sphere made by subdividing an icosahedron, with evenly sized triangles.
+///
+/// UVs map longitude and latitude like uv_sphere(), with vertices split
+/// along the seam.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3> = vec![
+        Vec3::new(-1.0, t, 0.0), Vec3::new(1.0, t, 0.0), Vec3::new(-1.0, -t, 0.0), Vec3::new(1.0, -t, 0.0),
+        Vec3::new(0.0, -1.0, t), Vec3::new(0.0, 1.0, t), Vec3::new(0.0, -1.0, -t), Vec3::new(0.0, 1.0, -t),
+        Vec3::new(t, 0.0, -1.0), Vec3::new(t, 0.0, 1.0), Vec3::new(-t, 0.0, -1.0), Vec3::new(-t, 0.0, 1.0),
+    ].into_iter().map(Vec3::normalize).collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+            points.push((points[a as usize] + points[b as usize]).normalize());
+            points.len() as u32 - 1
+        });
+
+        triangles = triangles.into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Every triangle gets vertices of its own, so UVs can wrap around
+    // the seam and poles can take the longitude of their triangle.
+    let mut data = MeshData::default();
+    for triangle in triangles {
+        let corners = triangle.map(|index| points[index as usize]);
+        let mut us = corners.map(|point| {
+            let u = point.x.atan2(point.z) / (2.0 * PI);
+            if u < 0.0 { u + 1.0 } else { u }
+        });
+        let poles = corners.map(|point| point.x.abs() < 1e-6 && point.z.abs() < 1e-6);
+
+        let others: Vec<f32> = (0..3).filter(|i| !poles[*i]).map(|i| us[i]).collect();
+        let max = others.iter().cloned().fold(0.0, f32::max);
+        for (u, pole) in us.iter_mut().zip(poles) {
+            if pole {
+                continue;
+            }
+            if max - *u > 0.5 {
+                *u += 1.0;
+            }
+        }
+        let others: Vec<f32> = (0..3).filter(|i| !poles[*i]).map(|i| us[i]).collect();
+        for (u, pole) in us.iter_mut().zip(poles) {
+            if pole {
+                *u = others.iter().sum::<f32>() / others.len() as f32;
+            }
+        }
+
+        for (point, u) in corners.iter().zip(us) {
+            let phi = u * 2.0 * PI;
+            data.indices.push(data.positions.len() as u32);
+            data.positions.push(*point * radius);
+            data.normals.push(*point);
+            data.tangents.push(Vec4::new(phi.cos(), 0.0, -phi.sin(), -1.0));
+            data.uvs.push(Vec2::new(u, point.y.clamp(-1.0, 1.0).acos() / PI));
+        }
+    }
+
+    data
+}
+
+/// Cylinder around the Y axis, with caps.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let (top, bottom) = (height * 0.5, -height * 0.5);
+    let profile = vec![
+        ProfilePoint::new(0.0, top, Vec2::Y),
+        ProfilePoint::new(radius, top, Vec2::Y),
+        ProfilePoint::new(radius, top, Vec2::X),
+        ProfilePoint::new(radius, bottom, Vec2::X),
+        ProfilePoint::new(radius, bottom, -Vec2::Y),
+        ProfilePoint::new(0.0, bottom, -Vec2::Y),
+    ];
+
+    lathe(&profile, segments.max(3))
+}
+
+/// Cone around the Y axis pointing up, with a base.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    let (top, bottom) = (height * 0.5, -height * 0.5);
+    let side = Vec2::new(height, radius).normalize();
+    let profile = vec![
+        ProfilePoint::new(0.0, top, side),
+        ProfilePoint::new(radius, bottom, side),
+        ProfilePoint::new(radius, bottom, -Vec2::Y),
+        ProfilePoint::new(0.0, bottom, -Vec2::Y),
+    ];
+
+    lathe(&profile, segments.max(3))
+}
+
+/// Capsule around the Y axis: a cylinder of the given height between
+/// two half spheres, each made of rings.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let rings = rings.max(1);
+    let top = (0..=rings).map(|ring| {
+        let theta = ring as f32 / rings as f32 * PI * 0.5;
+        ProfilePoint::arc(theta, radius, height * 0.5, ring == 0)
+    });
+    let bottom = (0..=rings).map(|ring| {
+        let theta = (1.0 + ring as f32 / rings as f32) * PI * 0.5;
+        ProfilePoint::arc(theta, radius, -height * 0.5, ring == rings)
+    });
+
+    lathe(&top.chain(bottom).collect::<Vec<_>>(), segments.max(3))
+}
+
+/// Torus around the Y axis, with rings of the minor radius around a
+/// circle of the major radius.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> MeshData {
+    let minor_segments = minor_segments.max(3);
+    // Starts at the outer equator and goes down, like the outside of the
+    // other shapes.
+    let profile: Vec<ProfilePoint> = (0..=minor_segments)
+        .map(|segment| {
+            let alpha = turn(segment, minor_segments);
+            let normal = Vec2::new(alpha.cos(), -alpha.sin());
+            ProfilePoint::new(major_radius + minor_radius * normal.x, minor_radius * normal.y, normal)
+        })
+        .collect();
+
+    lathe(&profile, major_segments.max(3))
+}
+
+/// Helper function for adding a grid of quads around a center, spanning
+/// right and down. U follows right and V follows down.
+fn grid(data: &mut MeshData, center: Vec3, right: Vec3, down: Vec3, columns: u32, rows: u32) {
+    let normal = down.cross(right).normalize();
+    let tangent = right.normalize().extend(-1.0);
+    let first = data.positions.len() as u32;
+
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let uv = Vec2::new(column as f32 / columns as f32, row as f32 / rows as f32);
+            data.positions.push(center + right * (uv.x - 0.5) + down * (uv.y - 0.5));
+            data.normals.push(normal);
+            data.tangents.push(tangent);
+            data.uvs.push(uv);
+        }
+    }
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = first + row * (columns + 1) + column;
+            let (b, c) = (a + 1, a + columns + 1);
+            data.indices.extend([a, c, b, b, c, c + 1]);
+        }
+    }
+}
+
+/// A point of a profile revolved around the Y axis by lathe().
+struct ProfilePoint {
+    /// Distance from the Y axis.
+    radius: f32,
+    y: f32,
+    /// Normal, away from the axis in x and along it in y.
+    normal: Vec2,
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: Vec2) -> Self {
+        ProfilePoint {
+            radius,
+            y,
+            normal,
+        }
+    }
+
+    /// Point of a sphere centered at height y, at an angle theta from
+    /// the top. Poles lie exactly on the axis.
+    fn arc(theta: f32, radius: f32, y: f32, pole: bool) -> Self {
+        let normal = match pole {
+            true => Vec2::new(0.0, theta.cos().signum()),
+            false => Vec2::new(theta.sin(), theta.cos())
+        };
+        ProfilePoint::new(radius * normal.x, y + radius * normal.y, normal)
+    }
+}
+
+/// Helper function for the angle of a step around a full turn. The last
+/// step lands exactly on the first, so the seam of a revolved shape closes.
+fn turn(step: u32, steps: u32) -> f32 {
+    (step % steps) as f32 / steps as f32 * 2.0 * PI
+}
+
+/// Helper function for revolving a profile, going down the outside of the
+/// shape, around the Y axis. U goes around the axis and V along the profile,
+/// by distance.
+///
+/// Points on the axis get a vertex per segment, halfway between its sides.
+fn lathe(profile: &[ProfilePoint], segments: u32) -> MeshData {
+    let mut vs = vec![0.0];
+    for pair in profile.windows(2) {
+        let step = Vec2::new(pair[1].radius - pair[0].radius, pair[1].y - pair[0].y).length();
+        vs.push(vs.last().unwrap() + step);
+    }
+    let length = vs.last().unwrap().max(f32::EPSILON);
+
+    let mut data = MeshData::default();
+    for (point, v) in profile.iter().zip(&vs) {
+        let on_axis = point.radius == 0.0;
+        for segment in 0..=segments {
+            let u = match on_axis {
+                true => (segment as f32 + 0.5) / segments as f32,
+                false => segment as f32 / segments as f32
+            };
+            let (sin, cos) = match on_axis {
+                true => (u * 2.0 * PI).sin_cos(),
+                false => turn(segment, segments).sin_cos()
+            };
+
+            data.positions.push(Vec3::new(point.radius * sin, point.y, point.radius * cos));
+            data.normals.push(Vec3::new(point.normal.x * sin, point.normal.y, point.normal.x * cos).normalize());
+            data.tangents.push(Vec4::new(cos, 0.0, -sin, -1.0));
+            data.uvs.push(Vec2::new(u, v / length));
+        }
+    }
+
+    for (row, pair) in profile.windows(2).enumerate() {
+        // Duplicated points only split the normals, e.g. at the rim of a cap.
+        if pair[0].radius == pair[1].radius && pair[0].y == pair[1].y {
+            continue;
+        }
+
+        for segment in 0..segments {
+            let a = row as u32 * (segments + 1) + segment;
+            let (b, c) = (a + 1, a + segments + 1);
+            let d = c + 1;
+            if pair[0].radius == 0.0 {
+                data.indices.extend([a, c, d]);
+            } else if pair[1].radius == 0.0 {
+                data.indices.extend([a, c, b]);
+            } else {
+                data.indices.extend([a, c, b, b, c, d]);
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the winding, normals, tangents and UVs of every triangle.
+    fn check(name: &str, data: &MeshData) {
+        let count = data.positions.len();
+        assert!(count > 0, "{}", name);
+        assert_eq!((data.normals.len(), data.tangents.len(), data.uvs.len()), (count, count, count), "{}", name);
+        assert!(data.indices.len().is_multiple_of(3) && data.indices.iter().all(|i| (*i as usize) < count), "{}", name);
+
+        for (normal, tangent) in data.normals.iter().zip(&data.tangents) {
+            assert!((normal.length() - 1.0).abs() < 1e-4, "{}", name);
+            assert!((tangent.truncate().length() - 1.0).abs() < 1e-4, "{}", name);
+            assert!(normal.dot(tangent.truncate()).abs() < 1e-4, "{}", name);
+        }
+
+        // Seams close: vertices in the same place are exactly in the same place.
+        for (i, a) in data.positions.iter().enumerate() {
+            for b in &data.positions[i + 1..] {
+                assert!(a == b || a.distance(*b) > 1e-4, "{} has a seam between {} and {}", name, a, b);
+            }
+        }
+
+        for triangle in data.indices.chunks(3) {
+            let [i0, i1, i2] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (e1, e2) = (data.positions[i1] - data.positions[i0], data.positions[i2] - data.positions[i0]);
+            let face = e1.cross(e2);
+            assert!(face.length() > 1e-7, "{} has a degenerate triangle", name);
+            let normal = data.normals[i0] + data.normals[i1] + data.normals[i2];
+            assert!(face.dot(normal) > 0.0, "{} winds the wrong way", name);
+
+            let (d1, d2) = (data.uvs[i1] - data.uvs[i0], data.uvs[i2] - data.uvs[i0]);
+            let r = d1.x * d2.y - d2.x * d1.y;
+            assert!(r.abs() > 1e-9, "{} has a triangle without UV area", name);
+            let tangent = (e1 * d2.y - e2 * d1.y) / r;
+            let bitangent = (e2 * d1.x - e1 * d2.x) / r;
+            for i in [i0, i1, i2] {
+                let t = data.tangents[i];
+                assert!(t.truncate().dot(tangent) > 0.0, "{} has a tangent against U", name);
+                assert_eq!(data.normals[i].cross(t.truncate()).dot(bitangent).signum(), t.w, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_are_wound_and_mapped_consistently() {
+        let shapes = vec![
+            ("quad", quad(2.0, 1.0)),
+            ("plane", plane(4.0, 2.0, 4, 3)),
+            ("cube", cube(2.0)),
+            ("uv_sphere", uv_sphere(1.5, 16, 8)),
+            ("icosphere", icosphere(1.5, 2)),
+            ("cylinder", cylinder(1.0, 2.0, 12)),
+            ("cone", cone(1.0, 2.0, 12)),
+            ("capsule", capsule(0.5, 1.0, 12, 4)),
+            ("torus", torus(2.0, 0.5, 16, 8)),
+        ];
+        for (name, data) in &shapes {
+            check(name, data);
+        }
+
+        let aabb = |data: &MeshData| data.aabb().unwrap();
+        assert_eq!(aabb(&shapes[0].1).max, Vec3::new(1.0, 0.5, 0.0));
+        assert_eq!(aabb(&shapes[2].1).min, Vec3::splat(-1.0));
+        for (name, radius) in [("uv_sphere", 1.5), ("icosphere", 1.5)].iter() {
+            let data = &shapes.iter().find(|(n, _)| n == name).unwrap().1;
+            assert!(data.positions.iter().all(|p| (p.length() - radius).abs() < 1e-4), "{}", name);
+        }
+        assert!((aabb(&shapes[7].1).max.y - 1.0).abs() < 1e-5);
+        assert!((aabb(&shapes[8].1).max.x - 2.5).abs() < 1e-5);
+        assert_eq!(icosphere(1.0, 1).indices.len(), 80 * 3);
+    }
+}
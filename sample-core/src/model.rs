@@ -4,7 +4,7 @@ use crate::{
     component::MeshComponent,
     shader::Shader
 };
-use std::rc::Rc;
+use std::sync::Arc;
 use glam::Vec3;
 
 #[allow(dead_code)]
@@ -16,7 +16,7 @@ pub struct Model {
 }
 
 impl Model {
-    pub fn from_mesh(mesh: Rc<Mesh>, shader: Shader) -> Model {
+    pub fn from_mesh(mesh: Arc<Mesh>, shader: Shader) -> Model {
         let mut meshes = Vec::new();
         meshes.push(MeshComponent::new(mesh, shader));
         Model {
@@ -0,0 +1,41 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use crate::ecs::{ECS, EntityId};
+
+/// Points in the lifecycle of a Component that can be observed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    /// After a Component is added to an Entity which had none of its type.
+    Add,
+    /// Before a Component is overwritten by adding another of its type.
+    Replace,
+    /// Before a Component is removed, including when its Entity is despawned.
+    Remove,
+}
+
+/// A function called with the Entity whose Component reached a Lifecycle point.
+pub type Observer = fn(&mut ECS, EntityId);
+
+/// Observers of every Component type, by Lifecycle point.
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: HashMap<(TypeId, Lifecycle), Vec<Observer>>,
+}
+
+impl Observers {
+    pub(crate) fn add(&mut self, data_type: TypeId, lifecycle: Lifecycle, observer: Observer) {
+        self.observers
+            .entry((data_type, lifecycle))
+            .or_default()
+            .push(observer);
+    }
+
+    /// Observers of a Component type at a Lifecycle point, in the order
+    /// they were added.
+    pub(crate) fn get(&self, data_type: TypeId, lifecycle: Lifecycle) -> Vec<Observer> {
+        match self.observers.get(&(data_type, lifecycle)) {
+            Some(observers) => observers.clone(),
+            None => Vec::new()
+        }
+    }
+}
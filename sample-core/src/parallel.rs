@@ -0,0 +1,294 @@
+use std::any::TypeId;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use crate::change::{Mut, Ticks};
+use crate::commands::Commands;
+use crate::component::Component;
+use crate::ecs::{EntityId, EntitySlot};
+use crate::query::{Access, Query, QueryData, QueryFilter, StorageSource, Storages};
+use crate::storage::ComponentStorage;
+use crate::time::Time;
+
+/// A system run on the thread pool, at the same time as other parallel
+/// systems whose declared access does not conflict with its own.
+///
+/// Parallel systems only reach the Components they declared with
+/// SystemBuilder::reads() and SystemBuilder::writes(). GL calls have to
+/// stay on the main thread and take the MainThread token, which only
+/// systems added with ECS::add_system() can get.
+pub type ParallelSystem = fn(&mut SystemView);
+
+/// The part of the ECS a parallel system may use.
+pub struct SystemView<'a> {
+    entities: &'a [EntitySlot],
+    /// Storages of the Component types the system writes, moved out of the ECS.
+    writable: Storages,
+    /// Storages no system of the batch writes, shared with the others.
+    readable: &'a Storages,
+    access: &'a Access,
+    ticks: Ticks,
+    time: Time,
+    commands: Commands,
+}
+
+impl<'a> SystemView<'a> {
+    /// Iterates over all Entities having the Components in Q.
+    ///
+    /// Panics if Q accesses Components the system did not declare.
+    pub fn query<'w, Q: QueryData<'w>>(&'w mut self) -> Query<'w, Q> {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// Iterates over all Entities having the Components in Q and passing
+    /// the filter F.
+    ///
+    /// Panics if Q or F access Components the system did not declare.
+    pub fn query_filtered<'w, Q: QueryData<'w>, F: QueryFilter>(&'w mut self) -> Query<'w, Q, F> {
+        if !self.access.covers(&Query::<Q, F>::access()) {
+            panic!("Query accesses Components the system did not declare");
+        }
+
+        let source = StorageSource::new(&mut self.writable, Some(self.readable));
+        Query::new(self.entities, source, self.ticks)
+    }
+
+    /// Gets a Component of an Entity.
+    ///
+    /// Panics if the system did not declare reading or writing type C.
+    pub fn get_component<C: Component + 'static>(&self, id: EntityId) -> Option<&C> {
+        let mut access = Access::new();
+        access.read::<C>();
+        if !self.access.covers(&access) {
+            panic!("Component was read without being declared");
+        }
+
+        let data_type = TypeId::of::<C>();
+        let storage = match self.writable.get(&data_type) {
+            Some(storage) => storage,
+            None => self.readable.get(&data_type)?
+        };
+
+        storage.as_any()
+            .downcast_ref::<ComponentStorage<C>>()?
+            .get(id)
+    }
+
+    /// Gets a mutable Component of an Entity.
+    ///
+    /// Panics if the system did not declare writing type C.
+    pub fn get_component_mut<C: Component + 'static>(&mut self, id: EntityId) -> Option<Mut<'_, C>> {
+        let mut access = Access::new();
+        access.write::<C>();
+        if !self.access.covers(&access) {
+            panic!("Component was written without being declared");
+        }
+
+        let ticks = self.ticks;
+        self.writable
+            .get_mut(&TypeId::of::<C>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<C>>()?
+            .get_mut(id, ticks)
+    }
+
+    /// Frame timing of the ECS.
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    /// Queue of structural changes, applied on the main thread after
+    /// the current stage.
+    pub fn commands(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+/// A parallel system ready to run in a batch.
+pub(crate) struct Job<'s> {
+    pub(crate) system: ParallelSystem,
+    pub(crate) access: &'s Access,
+    /// Change tick of the last run, set to the tick of this run.
+    pub(crate) last_run: &'s mut u64,
+}
+
+/// Runs a batch of parallel systems without conflicting access on the
+/// given thread pool, or the global one, and returns the commands each
+/// one queued.
+///
+/// The storages each system writes are moved out of components for the
+/// duration of the batch, so every system owns what it writes, and the
+/// rest is shared for reading.
+pub(crate) fn run_batch(components: &mut Storages, entities: &[EntitySlot], pool: Option<&ThreadPool>, time: Time, this_run: u64, jobs: Vec<Job>) -> Vec<Commands> {
+    let writables: Vec<Storages> = jobs.iter()
+        .map(|job| job.access.writes()
+            .iter()
+            .filter_map(|data_type| components.remove_entry(data_type))
+            .collect())
+        .collect();
+
+    let readable: &Storages = components;
+    let mut views: Vec<(ParallelSystem, SystemView)> = jobs.into_iter()
+        .zip(writables)
+        .map(|(job, writable)| {
+            let ticks = Ticks {
+                last_run: *job.last_run,
+                this_run,
+            };
+            *job.last_run = this_run;
+
+            (job.system, SystemView {
+                entities,
+                writable,
+                readable,
+                access: job.access,
+                ticks,
+                time,
+                commands: Commands::new(),
+            })
+        })
+        .collect();
+
+    if views.len() == 1 {
+        let (system, view) = &mut views[0];
+        system(view);
+    } else {
+        let run = |views: &mut Vec<(ParallelSystem, SystemView)>| {
+            views.par_iter_mut().for_each(|(system, view)| system(view));
+        };
+        match pool {
+            Some(pool) => pool.install(|| run(&mut views)),
+            None => run(&mut views)
+        }
+    }
+
+    let done: Vec<(Storages, Commands)> = views.into_iter()
+        .map(|(_, view)| (view.writable, view.commands))
+        .collect();
+
+    let mut commands = Vec::with_capacity(done.len());
+    for (writable, queued) in done {
+        components.extend(writable);
+        commands.push(queued);
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{ECS, Entity};
+    use crate::schedule::{Schedule, Stage};
+
+    struct Position(f32);
+    struct Velocity(f32);
+    struct Health(u32);
+
+    impl Component for Position {
+        const PARALLEL: bool = true;
+
+        fn create(_entity: &mut Entity) -> Self {
+            Position(0.0)
+        }
+
+        fn update(&mut self, _time: &Time) {
+            self.0 += 1.0;
+        }
+    }
+
+    impl Component for Velocity {
+        fn create(_entity: &mut Entity) -> Self {
+            Velocity(2.0)
+        }
+    }
+
+    impl Component for Health {
+        fn create(_entity: &mut Entity) -> Self {
+            Health(10)
+        }
+    }
+
+    fn movement(view: &mut SystemView) {
+        for (mut position, velocity) in view.query::<(&mut Position, &Velocity)>() {
+            position.0 += velocity.0;
+        }
+    }
+
+    fn damage(view: &mut SystemView) {
+        for mut health in view.query::<&mut Health>() {
+            health.0 -= 5;
+        }
+    }
+
+    fn check_health(view: &mut SystemView) {
+        for health in view.query::<&Health>() {
+            assert_eq!(health.0, 5);
+        }
+    }
+
+    #[test]
+    fn systems_without_conflicts_share_a_batch() {
+        let mut ecs = ECS::new();
+        for i in 0..100 {
+            let mut entity = ecs.new_entity(&i.to_string());
+            entity.add_component::<Position>(&|_| {});
+            entity.add_component::<Velocity>(&|_| {});
+            entity.add_component::<Health>(&|_| {});
+        }
+
+        let mut schedule = Schedule::new();
+        schedule.add_parallel_system(Stage::Update, movement)
+            .writes::<Position>()
+            .reads::<Velocity>();
+        schedule.add_parallel_system(Stage::Update, damage)
+            .writes::<Health>();
+        schedule.add_parallel_system(Stage::Update, check_health)
+            .reads::<Health>();
+        assert_eq!(schedule.batches(Stage::Update), [vec![0, 1], vec![2]]);
+
+        ecs.set_threads(2);
+        schedule.run(&[Stage::Update], &mut ecs);
+        ecs.update();
+
+        let id = ecs.entity_id("7").unwrap();
+        assert_eq!(ecs.get_component::<Position>(id).unwrap().0, 3.0);
+        assert_eq!(ecs.get_component::<Health>(id).unwrap().0, 5);
+    }
+
+    #[test]
+    fn access_conflicts_only_on_writes() {
+        let mut movement = Access::new();
+        movement.write::<Position>();
+        movement.read::<Velocity>();
+        let mut steering = Access::new();
+        steering.write::<Velocity>();
+        let mut render = Access::new();
+        render.read::<Position>();
+        render.read::<Health>();
+        let mut damage = Access::new();
+        damage.write::<Health>();
+
+        assert!(movement.conflicts_with(&steering));
+        assert!(render.conflicts_with(&movement));
+        assert!(!movement.conflicts_with(&damage));
+        assert!(!steering.conflicts_with(&render));
+
+        let mut view = Access::new();
+        view.read::<Position>();
+        assert!(movement.covers(&view));
+        assert!(!render.covers(&movement));
+    }
+
+    #[test]
+    #[should_panic(expected = "did not declare")]
+    fn undeclared_access_panics() {
+        let mut ecs = ECS::new();
+        let id = ecs.new_entity("a").id();
+        ecs.add_component::<Position>(id, &|_| {});
+        ecs.add_parallel_system(Stage::Update, |view| {
+            view.query::<&mut Position>().count();
+        }).reads::<Position>();
+
+        ecs.update();
+    }
+}
@@ -0,0 +1,619 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use crate::assets::Assets;
+use crate::component::Component;
+use crate::ecs::{ECS, Entity, EntityId};
+use crate::scene::{read_scene, SceneEntity, Value};
+use crate::schedule::Stage;
+
+/// Template of an Entity and its descendants, read from a scene file
+/// whose first Entity is the root.
+struct Prefab {
+    path: String,
+    data: RwLock<PrefabData>,
+}
+
+struct PrefabData {
+    entities: Vec<SceneEntity>,
+    /// Increased by every reload.
+    version: u64,
+}
+
+/// Shared handle to a prefab, spawned with ECS::spawn_prefab().
+///
+/// Loaded with PrefabHandle::load(), or once per path with
+/// Assets::load_prefab().
+#[derive(Clone)]
+pub struct PrefabHandle(Arc<Prefab>);
+
+impl PrefabHandle {
+    /// Reads a prefab from a .ron or .json scene file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let entities = read_prefab(path)?;
+        Ok(PrefabHandle(Arc::new(Prefab {
+            path: path.to_string(),
+            data: RwLock::new(PrefabData {
+                entities,
+                version: 1,
+            }),
+        })))
+    }
+
+    /// Path of the file the prefab is read from.
+    pub fn path(&self) -> &str {
+        &self.0.path
+    }
+
+    /// Version of the template, increased by every reload.
+    pub fn version(&self) -> u64 {
+        self.0.data.read().unwrap().version
+    }
+
+    /// Reads the file again. Spawned instances are updated by the
+    /// prefab_sync system in PreUpdate.
+    ///
+    /// On error, the prefab keeps its old template.
+    pub fn reload(&self) -> Result<(), String> {
+        let entities = read_prefab(&self.0.path)?;
+        let mut data = self.0.data.write().unwrap();
+        data.entities = entities;
+        data.version += 1;
+        Ok(())
+    }
+
+    /// The template along with its version.
+    pub(crate) fn template(&self) -> (Vec<SceneEntity>, u64) {
+        let data = self.0.data.read().unwrap();
+        (data.entities.clone(), data.version)
+    }
+}
+
+/// Helper function for reading a prefab file, which needs a root Entity.
+fn read_prefab(path: &str) -> Result<Vec<SceneEntity>, String> {
+    let entities = read_scene(Path::new(path))?;
+    if entities.is_empty() {
+        return Err(format!("Prefab {} has no Entities", path));
+    }
+
+    Ok(entities)
+}
+
+/// A value replacing part of a Component of one Entity of an instance.
+#[derive(Clone)]
+struct Override {
+    /// Name of the Entity in the template.
+    entity: String,
+    component: String,
+    /// JSON pointer to the field, empty for the whole Component.
+    pointer: String,
+    value: Value,
+}
+
+/// Added to the root Entity of every spawned prefab.
+///
+/// Keeps the overrides of the instance, so they are kept when the
+/// prefab is reloaded.
+pub struct PrefabInstance {
+    prefab: Option<PrefabHandle>,
+    /// Version of the template the instance was last synced with.
+    version: u64,
+    /// Version of the template the instance last failed to sync with,
+    /// which is not tried again.
+    failed_version: u64,
+    template: Vec<SceneEntity>,
+    /// Entities of the instance, by their name in the template.
+    entities: HashMap<String, EntityId>,
+    overrides: Vec<Override>,
+}
+
+impl PrefabInstance {
+    /// The prefab the instance was spawned from.
+    pub fn prefab(&self) -> Option<&PrefabHandle> {
+        self.prefab.as_ref()
+    }
+
+    /// Id of an Entity of the instance, by its name in the template.
+    pub fn entity(&self, name: &str) -> Option<EntityId> {
+        self.entities.get(name).copied()
+    }
+}
+
+impl Component for PrefabInstance {
+    fn create(_entity: &mut Entity) -> Self {
+        PrefabInstance {
+            prefab: None,
+            version: 0,
+            failed_version: 0,
+            template: Vec::new(),
+            entities: HashMap::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    fn register(ecs: &mut ECS) {
+        ecs.add_system(Stage::PreUpdate, sync_prefabs)
+            .label("prefab_sync");
+    }
+}
+
+/// Event sent when an instance could not be updated to the current
+/// version of its prefab.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefabSyncError {
+    /// Root of the instance.
+    pub root: EntityId,
+    pub error: String,
+}
+
+/// Event sent when an instance drops an override, as the field it sets
+/// is no longer in the template of its prefab.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefabOverrideDropped {
+    /// Root of the instance.
+    pub root: EntityId,
+    /// Name of the Entity in the template.
+    pub entity: String,
+    pub component: String,
+    /// JSON pointer to the field.
+    pub pointer: String,
+}
+
+/// Updates the instances of reloaded prefabs.
+///
+/// Sends a PrefabSyncError for every instance which fails to update, once
+/// per version of its prefab, and a PrefabOverrideDropped for every
+/// override which no longer applies.
+pub fn sync_prefabs(ecs: &mut ECS) {
+    for root in ecs.entities_with::<PrefabInstance>() {
+        if let Err(error) = ecs.with_assets(|ecs, assets| ecs.sync_prefab(root, assets)) {
+            ecs.send_event(PrefabSyncError { root, error });
+        }
+    }
+}
+
+/// Turns a dot separated path, e.g. `local.translation`, into a JSON pointer.
+fn to_pointer(path: &str) -> String {
+    path.split('.')
+        .filter(|field| !field.is_empty())
+        .map(|field| format!("/{}", field.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Value of a Component of an instance: its value in the template, with
+/// the overrides applied in the order they were set, along with the
+/// overrides of fields the value does not have.
+///
+/// None if the template has no such Component.
+fn instance_value<'o>(template: &[SceneEntity], overrides: &'o [Override], entity: &str, component: &str) -> Option<(Value, Vec<&'o Override>)> {
+    let mut value = template.iter()
+        .find(|e| e.name == entity)
+        .and_then(|e| e.components.get(component))?
+        .clone();
+
+    let mut missing = Vec::new();
+    for o in overrides.iter().filter(|o| o.entity == entity && o.component == component) {
+        match value.pointer_mut(&o.pointer) {
+            Some(field) => *field = o.value.clone(),
+            None => missing.push(o)
+        }
+    }
+
+    Some((value, missing))
+}
+
+impl ECS {
+    /// Spawns an instance of a prefab. Returns the id of its root.
+    ///
+    /// Assets are resolved through the Assets resource, if there is one.
+    /// On error, nothing is spawned.
+    pub fn spawn_prefab(&mut self, prefab: &PrefabHandle) -> Result<EntityId, String> {
+        let (template, version) = prefab.template();
+        let ids = self.with_assets(|ecs, assets| ecs.spawn_scene(&template, assets))
+            .map_err(|e| format!("{}: {}", prefab.path(), e))?;
+
+        let root = ids[0];
+        let instance = Cell::new(Some(PrefabInstance {
+            prefab: Some(prefab.clone()),
+            version,
+            failed_version: 0,
+            entities: template.iter().map(|e| e.name.clone()).zip(ids).collect(),
+            template,
+            overrides: Vec::new(),
+        }));
+        self.add_component::<PrefabInstance>(root, &|c| {
+            if let Some(instance) = instance.take() {
+                *c = instance;
+            }
+        });
+
+        Ok(root)
+    }
+
+    /// Overrides a field of a Component of an Entity spawned by a prefab,
+    /// e.g. `ecs.set_prefab_override(barrel, "Transform", "local.scale", value)`.
+    ///
+    /// An empty path overrides the whole Component. Overridden fields keep
+    /// their value when the prefab is reloaded.
+    pub fn set_prefab_override(&mut self, id: EntityId, component: &str, path: &str, value: Value) -> Result<(), String> {
+        let (root, entity) = self.prefab_root(id)
+            .ok_or_else(|| format!("Entity {} is not part of a prefab instance", id.index()))?;
+        let pointer = to_pointer(path);
+
+        let mut overrides = self.get_component::<PrefabInstance>(root).unwrap().overrides.clone();
+        overrides.retain(|o| o.entity != entity || o.component != component || !(o.pointer == pointer || pointer.is_empty()));
+        overrides.push(Override {
+            entity: entity.clone(),
+            component: component.to_string(),
+            pointer,
+            value,
+        });
+
+        self.apply_overrides(root, id, &entity, component, overrides)
+    }
+
+    /// Removes an override set with set_prefab_override(), restoring the
+    /// value of the prefab.
+    pub fn remove_prefab_override(&mut self, id: EntityId, component: &str, path: &str) -> Result<(), String> {
+        let (root, entity) = self.prefab_root(id)
+            .ok_or_else(|| format!("Entity {} is not part of a prefab instance", id.index()))?;
+        let pointer = to_pointer(path);
+
+        let mut overrides = self.get_component::<PrefabInstance>(root).unwrap().overrides.clone();
+        overrides.retain(|o| o.entity != entity || o.component != component || o.pointer != pointer);
+
+        self.apply_overrides(root, id, &entity, component, overrides)
+    }
+
+    /// Helper function for finding the root of the prefab instance an
+    /// Entity belongs to, and the name of the Entity in the template.
+    fn prefab_root(&self, id: EntityId) -> Option<(EntityId, String)> {
+        let mut ancestor = Some(id);
+        while let Some(root) = ancestor {
+            if let Some(instance) = self.get_component::<PrefabInstance>(root) {
+                if let Some((name, _)) = instance.entities.iter().find(|(_, e)| **e == id) {
+                    return Some((root, name.clone()));
+                }
+            }
+            ancestor = self.parent(root);
+        }
+
+        None
+    }
+
+    /// Helper function for reloading a Component of an instance with new
+    /// overrides, which are only kept if they apply.
+    fn apply_overrides(&mut self, root: EntityId, id: EntityId, entity: &str, component: &str, overrides: Vec<Override>) -> Result<(), String> {
+        let instance = self.get_component::<PrefabInstance>(root).unwrap();
+        let value = match instance_value(&instance.template, &overrides, entity, component) {
+            Some((value, missing)) => match missing.first() {
+                Some(o) => return Err(format!("{} of {} has no field {}", component, entity, o.pointer)),
+                None => value
+            },
+            None => return Err(format!("{} has no {} in the prefab", entity, component))
+        };
+        let entities = instance.entities.clone();
+
+        self.with_assets(|ecs, assets| ecs.load_scene_component(id, component, value, entities, assets))?;
+        self.get_component_mut::<PrefabInstance>(root).unwrap().overrides = overrides;
+        Ok(())
+    }
+
+    /// Updates an instance to the current version of its prefab.
+    ///
+    /// The instance only takes the new version once all Components loaded.
+    /// On error, it waits for the next version instead.
+    fn sync_prefab(&mut self, root: EntityId, assets: &mut Assets) -> Result<(), String> {
+        let instance = self.get_component::<PrefabInstance>(root).unwrap();
+        let prefab = match &instance.prefab {
+            Some(prefab) if prefab.version() != instance.version && prefab.version() != instance.failed_version => prefab.clone(),
+            _ => return Ok(())
+        };
+        let (template, version) = prefab.template();
+
+        let result = self.update_instance(root, &template, assets);
+        let mut instance = self.get_component_mut::<PrefabInstance>(root).unwrap();
+        let dropped = match result {
+            Ok(dropped) => dropped,
+            Err(error) => {
+                instance.failed_version = version;
+                return Err(error);
+            }
+        };
+
+        instance.version = version;
+        instance.template = template;
+        instance.overrides.retain(|o| !dropped.iter().any(|d| d.entity == o.entity && d.component == o.component && d.pointer == o.pointer));
+        for o in dropped {
+            self.send_event(PrefabOverrideDropped {
+                root,
+                entity: o.entity,
+                component: o.component,
+                pointer: o.pointer,
+            });
+        }
+        Ok(())
+    }
+
+    /// Helper function for updating the Entities and Components of an
+    /// instance to a template. Returns the overrides which no longer apply.
+    ///
+    /// Entities are matched by name, except for the root. Only Components
+    /// whose value changed in the template are loaded again, along with
+    /// the Transform of Entities whose Parent changed, as set_parent()
+    /// changes the local transform.
+    fn update_instance(&mut self, root: EntityId, template: &[SceneEntity], assets: &mut Assets) -> Result<Vec<Override>, String> {
+        let instance = self.get_component::<PrefabInstance>(root).unwrap();
+        let old = instance.template.clone();
+        let old_entities = instance.entities.clone();
+        let overrides = instance.overrides.clone();
+
+        let mut entities = HashMap::new();
+        entities.insert(template[0].name.clone(), root);
+        for (name, id) in old_entities {
+            if id == root {
+                continue;
+            }
+            if template.iter().any(|e| e.name == name) {
+                entities.insert(name, id);
+            } else {
+                self.despawn(id);
+            }
+        }
+        for entity in &template[1..] {
+            if !entities.contains_key(&entity.name) {
+                let id = self.new_entity(&entity.name).id();
+                entities.insert(entity.name.clone(), id);
+            }
+        }
+
+        // Entities are kept track of right away, so a failed sync does
+        // not spawn them again.
+        self.get_component_mut::<PrefabInstance>(root).unwrap().entities = entities.clone();
+
+        // Overrides of Entities and Components gone from the template.
+        let mut dropped: Vec<Override> = overrides.iter()
+            .filter(|o| instance_value(template, &[], &o.entity, &o.component).is_none())
+            .cloned()
+            .collect();
+
+        let types = self.scene_type_names();
+        for (index, entity) in template.iter().enumerate() {
+            let id = entities[&entity.name];
+            let was = match index {
+                0 => old.first(),
+                _ => old.iter().find(|e| e.name == entity.name)
+            };
+            let changed = |type_name: &str| match was {
+                Some(was) => was.components.get(type_name) != entity.components.get(type_name),
+                None => true
+            };
+
+            for type_name in &types {
+                let reload = changed(type_name) || (type_name == "Transform" && changed("Parent"));
+                if !reload {
+                    continue;
+                }
+
+                match instance_value(template, &overrides, &entity.name, type_name) {
+                    Some((value, missing)) => {
+                        dropped.extend(missing.into_iter().cloned());
+                        self.load_scene_component(id, type_name, value, entities.clone(), assets)
+                            .map_err(|e| format!("{} of {}: {}", type_name, entity.name, e))?
+                    },
+                    None => self.remove_scene_component(id, type_name)
+                }
+            }
+        }
+
+        Ok(dropped)
+    }
+
+    /// Helper function for running f with the Assets resource, or with
+    /// empty Assets if there is none.
+    fn with_assets<R>(&mut self, f: impl FnOnce(&mut ECS, &mut Assets) -> R) -> R {
+        if self.contains_resource::<Assets>() {
+            self.resource_scope(f).unwrap()
+        } else {
+            f(self, &mut Assets::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use super::*;
+    use crate::event::EventReader;
+    use crate::hierarchy::TransformComponent;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    impl Component for Health {
+        fn create(_entity: &mut Entity) -> Self {
+            Health(100)
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    struct Gun {
+        damage: u32,
+        range: u32,
+    }
+
+    impl Component for Gun {
+        fn create(_entity: &mut Entity) -> Self {
+            Gun::default()
+        }
+    }
+
+    fn prefab_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("sample-core-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn write_turret(path: &str, health: u32, barrel_height: f32, muzzle: bool) {
+        let transform = |y: f32| json!({ "local": {
+            "translation": [0.0, y, 0.0],
+            "rotation": [0.0, 0.0, 0.0, 1.0],
+            "scale": [1.0, 1.0, 1.0],
+        } });
+
+        let mut entities = vec![
+            json!({ "name": "Turret", "components": { "Health": health, "Tags": ["Enemy"] } }),
+            json!({ "name": "Barrel", "components": { "Parent": "Turret", "Transform": transform(barrel_height) } }),
+        ];
+        if muzzle {
+            entities.push(json!({ "name": "Muzzle", "components": { "Parent": "Barrel", "Transform": transform(0.5) } }));
+        }
+        std::fs::write(path, json!({ "entities": entities }).to_string()).unwrap();
+    }
+
+    fn translation(ecs: &ECS, id: EntityId) -> Vec3 {
+        ecs.get_component::<TransformComponent>(id).unwrap().local.translation
+    }
+
+    #[test]
+    fn reloads_reach_instances_except_for_overrides() {
+        let path = prefab_path("turret.json");
+        write_turret(&path, 100, 1.0, false);
+
+        let mut ecs = ECS::new();
+        ecs.register_scene_component::<Health>("Health");
+        let mut assets = Assets::new();
+        let turret = assets.load_prefab(&path).unwrap();
+        let a = ecs.spawn_prefab(&turret).unwrap();
+        let b = ecs.spawn_prefab(&assets.load_prefab(&path).unwrap()).unwrap();
+        assert_eq!(ecs.entity_name(b), Some("Turret (1)"));
+        assert_eq!(ecs.children(b).len(), 1);
+
+        ecs.set_prefab_override(a, "Health", "", Value::from(50)).unwrap();
+        assert_eq!(ecs.get_component::<Health>(a), Some(&Health(50)));
+
+        write_turret(&path, 200, 2.0, true);
+        turret.reload().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        ecs.update();
+
+        assert_eq!(ecs.get_component::<Health>(a), Some(&Health(50)));
+        assert_eq!(ecs.get_component::<Health>(b), Some(&Health(200)));
+        for root in [a, b] {
+            let instance = ecs.get_component::<PrefabInstance>(root).unwrap();
+            let (barrel, muzzle) = (instance.entity("Barrel").unwrap(), instance.entity("Muzzle").unwrap());
+            assert_eq!(ecs.children(root), &[barrel]);
+            assert_eq!(ecs.parent(muzzle), Some(barrel));
+            assert_eq!(translation(&ecs, barrel), Vec3::new(0.0, 2.0, 0.0));
+            assert_eq!(translation(&ecs, muzzle), Vec3::new(0.0, 0.5, 0.0));
+        }
+    }
+
+    #[test]
+    fn removing_an_override_restores_the_prefab_value() {
+        let path = prefab_path("barrel.json");
+        write_turret(&path, 100, 1.0, false);
+        let turret = PrefabHandle::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut ecs = ECS::new();
+        ecs.register_scene_component::<Health>("Health");
+        let root = ecs.spawn_prefab(&turret).unwrap();
+        let barrel = ecs.children(root)[0];
+
+        ecs.set_prefab_override(barrel, "Transform", "local.translation", json!([5.0, 0.0, 0.0])).unwrap();
+        assert_eq!(translation(&ecs, barrel), Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(
+            ecs.set_prefab_override(barrel, "Transform", "local.size", json!(1.0)),
+            Err(String::from("Transform of Barrel has no field /local/size"))
+        );
+        let loose = ecs.new_entity("Loose").id();
+        assert!(ecs.set_prefab_override(loose, "Health", "", json!(1)).is_err());
+
+        ecs.remove_prefab_override(barrel, "Transform", "local.translation").unwrap();
+        assert_eq!(translation(&ecs, barrel), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn failed_syncs_are_sent_as_events_and_retried() {
+        let path = prefab_path("broken.json");
+        write_turret(&path, 100, 1.0, false);
+        let turret = PrefabHandle::load(&path).unwrap();
+
+        let mut ecs = ECS::new();
+        ecs.register_scene_component::<Health>("Health");
+        let root = ecs.spawn_prefab(&turret).unwrap();
+        let mut reader = EventReader::<PrefabSyncError>::new();
+
+        // The new Muzzle loads, but the Health of the root does not.
+        write_turret(&path, 100, 2.0, true);
+        let text = std::fs::read_to_string(&path).unwrap().replace("100", "\"full\"");
+        std::fs::write(&path, text).unwrap();
+        turret.reload().unwrap();
+        ecs.update();
+
+        let errors: Vec<PrefabSyncError> = ecs.read_events(&mut reader).cloned().collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].root, root);
+
+        // The same version is not tried again.
+        ecs.update();
+        assert_eq!(ecs.read_events(&mut reader).count(), 0);
+        let instance = ecs.get_component::<PrefabInstance>(root).unwrap();
+        assert_eq!(instance.version, 1);
+        let muzzle = instance.entity("Muzzle").unwrap();
+
+        write_turret(&path, 150, 2.0, true);
+        turret.reload().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        ecs.update();
+
+        assert_eq!(ecs.read_events(&mut reader).count(), 0);
+        assert_eq!(ecs.get_component::<PrefabInstance>(root).unwrap().version, turret.version());
+        assert_eq!(ecs.get_component::<Health>(root), Some(&Health(150)));
+        assert_eq!(ecs.get_component::<PrefabInstance>(root).unwrap().entity("Muzzle"), Some(muzzle));
+        assert_eq!(ecs.entities_with::<TransformComponent>().len(), 2);
+    }
+
+    #[test]
+    fn reloads_keep_unchanged_components_and_drop_stale_overrides() {
+        let path = prefab_path("gun.json");
+        let write_gun = |gun: Value| {
+            let entities = json!([{ "name": "Turret", "components": { "Gun": gun, "Health": 100 } }]);
+            std::fs::write(&path, json!({ "entities": entities }).to_string()).unwrap();
+        };
+        write_gun(json!({ "damage": 5, "range": 10 }));
+        let turret = PrefabHandle::load(&path).unwrap();
+
+        let mut ecs = ECS::new();
+        ecs.register_scene_component::<Gun>("Gun");
+        ecs.register_scene_component::<Health>("Health");
+        let root = ecs.spawn_prefab(&turret).unwrap();
+        ecs.set_prefab_override(root, "Gun", "range", json!(20)).unwrap();
+        ecs.set_prefab_override(root, "Gun", "damage", json!(6)).unwrap();
+        ecs.get_component_mut::<Health>(root).unwrap().0 = 7;
+        let mut reader = EventReader::<PrefabOverrideDropped>::new();
+
+        // The range is gone from the template, so its override is dropped.
+        write_gun(json!({ "damage": 8 }));
+        turret.reload().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        ecs.update();
+
+        assert_eq!(ecs.get_component::<Gun>(root), Some(&Gun { damage: 6, range: 0 }));
+        assert_eq!(ecs.get_component::<Health>(root), Some(&Health(7)));
+        let dropped: Vec<PrefabOverrideDropped> = ecs.read_events(&mut reader).cloned().collect();
+        assert_eq!(dropped, vec![PrefabOverrideDropped {
+            root,
+            entity: String::from("Turret"),
+            component: String::from("Gun"),
+            pointer: String::from("/range"),
+        }]);
+        assert_eq!(ecs.get_component::<PrefabInstance>(root).unwrap().overrides.len(), 1);
+    }
+}
@@ -0,0 +1,501 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use crate::change::{Mut, Ticks};
+use crate::component::Component;
+use crate::ecs::{EntityId, EntitySlot};
+use crate::storage::{AnyStorage, ComponentStorage, RawStorage};
+
+/// Lists of all Components in the ECS, by TypeId.
+pub(crate) type Storages = HashMap<TypeId, Box<dyn AnyStorage>>;
+
+/// Components a Query or system reads and writes.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+    /// Creates an empty Access.
+    pub fn new() -> Self {
+        Access {
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Marks a Component type as read.
+    pub fn read<C: 'static>(&mut self) {
+        self.reads.push(TypeId::of::<C>());
+    }
+
+    /// Marks a Component type as written.
+    pub fn write<C: 'static>(&mut self) {
+        self.writes.push(TypeId::of::<C>());
+    }
+
+    /// Checks if a Component type is written more than once,
+    /// or both written and read.
+    pub fn has_self_conflict(&self) -> bool {
+        self.writes.iter().enumerate().any(|(i, write)| {
+            self.writes[i + 1..].contains(write) || self.reads.contains(write)
+        })
+    }
+
+    /// Checks if one of the two writes a Component type the other
+    /// reads or writes, so they cannot run at the same time.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.writes.iter().any(|write| other.reads.contains(write) || other.writes.contains(write))
+            || other.writes.iter().any(|write| self.reads.contains(write))
+    }
+
+    /// Checks if everything other reads or writes is also read or written here.
+    pub fn covers(&self, other: &Access) -> bool {
+        other.writes.iter().all(|write| self.writes.contains(write))
+            && other.reads.iter().all(|read| self.reads.contains(read) || self.writes.contains(read))
+    }
+
+    /// Component types written.
+    pub(crate) fn writes(&self) -> &[TypeId] {
+        &self.writes
+    }
+}
+
+/// Owners of the Components in a storage, which a Query can walk
+/// instead of every Entity.
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct Driver {
+    owners: *const EntityId,
+    len: usize,
+}
+
+impl Driver {
+    fn new<C>(storage: &RawStorage<C>) -> Self {
+        Driver {
+            owners: storage.owners(),
+            len: storage.len(),
+        }
+    }
+
+    /// Picks the Driver with fewer owners.
+    fn shortest(a: Option<Driver>, b: Option<Driver>) -> Option<Driver> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if b.len < a.len { b } else { a }),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+}
+
+/// Storages a Query fetches from: ones it may write to, and ones
+/// it may only read from.
+#[doc(hidden)]
+pub struct StorageSource<'a> {
+    writable: &'a mut Storages,
+    readable: Option<&'a Storages>,
+}
+
+impl<'a> StorageSource<'a> {
+    pub(crate) fn new(writable: &'a mut Storages, readable: Option<&'a Storages>) -> Self {
+        StorageSource {
+            writable,
+            readable,
+        }
+    }
+
+    /// Raw parts of the storage of a Component type, for writing.
+    fn raw<C: 'static>(&mut self) -> Option<RawStorage<C>> {
+        let storage = self.writable
+            .get_mut(&TypeId::of::<C>())?
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<C>>()?;
+
+        Some(storage.raw())
+    }
+
+    /// Raw parts of the storage of a Component type, only for reading.
+    fn raw_read<C: 'static>(&self) -> Option<RawStorage<C>> {
+        let data_type = TypeId::of::<C>();
+        let storage = match self.writable.get(&data_type) {
+            Some(storage) => storage,
+            None => self.readable?.get(&data_type)?
+        };
+
+        Some(storage.as_any().downcast_ref::<ComponentStorage<C>>()?.raw_read())
+    }
+}
+
+/// Data that can be fetched for every Entity matching a Query.
+///
+/// Implemented for `&C`, `&mut C`, `EntityId` and tuples of those.
+/// `&mut C` yields a Mut, which flags the Component as changed when written.
+///
+/// # Safety
+/// access() has to register every Component fetch() reads or writes, as
+/// Queries rely on it to never hand out aliasing references.
+pub unsafe trait QueryData<'w> {
+    /// What the Query yields for each Entity.
+    type Item;
+    #[doc(hidden)]
+    type State;
+
+    /// Registers the Components this data reads and writes.
+    fn access(access: &mut Access);
+
+    #[doc(hidden)]
+    fn state(source: &mut StorageSource, ticks: Ticks) -> Option<Self::State>;
+
+    /// The storage with the fewest Entities among the fetched Components.
+    #[doc(hidden)]
+    fn driver(state: &Self::State) -> Option<Driver>;
+
+    /// Fetches the data of one Entity.
+    ///
+    /// # Safety
+    /// The Access of the data must not conflict with itself, and every
+    /// Entity may only be fetched once while the Items are alive.
+    #[doc(hidden)]
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item>;
+}
+
+/// Filter on the Components an Entity has, without fetching them.
+///
+/// # Safety
+/// access() has to register every Component matches() reads.
+pub unsafe trait QueryFilter {
+    #[doc(hidden)]
+    type State;
+
+    /// Registers the Components this filter reads.
+    fn access(access: &mut Access);
+
+    #[doc(hidden)]
+    fn state(source: &mut StorageSource, ticks: Ticks) -> Self::State;
+
+    /// Checks if an Entity passes the filter.
+    ///
+    /// # Safety
+    /// The storages the state was created from must still be valid.
+    #[doc(hidden)]
+    unsafe fn matches(state: &Self::State, id: EntityId) -> bool;
+}
+
+/// Only matches Entities with a Component of type C.
+pub struct With<C>(PhantomData<C>);
+
+/// Only matches Entities without a Component of type C.
+pub struct Without<C>(PhantomData<C>);
+
+unsafe impl<C: Component + 'static> QueryFilter for With<C> {
+    type State = Option<RawStorage<C>>;
+
+    fn access(access: &mut Access) {
+        access.read::<C>();
+    }
+
+    fn state(source: &mut StorageSource, _ticks: Ticks) -> Self::State {
+        source.raw_read::<C>()
+    }
+
+    unsafe fn matches(state: &Self::State, id: EntityId) -> bool {
+        match state {
+            Some(storage) => storage.dense_index(id).is_some(),
+            None => false
+        }
+    }
+}
+
+unsafe impl<C: Component + 'static> QueryFilter for Without<C> {
+    type State = Option<RawStorage<C>>;
+
+    fn access(access: &mut Access) {
+        access.read::<C>();
+    }
+
+    fn state(source: &mut StorageSource, _ticks: Ticks) -> Self::State {
+        source.raw_read::<C>()
+    }
+
+    unsafe fn matches(state: &Self::State, id: EntityId) -> bool {
+        match state {
+            Some(storage) => storage.dense_index(id).is_none(),
+            None => true
+        }
+    }
+}
+
+/// Only matches Entities whose Component of type C was added since
+/// the system last ran.
+pub struct Added<C>(PhantomData<C>);
+
+/// Only matches Entities whose Component of type C was added or changed
+/// since the system last ran.
+pub struct Changed<C>(PhantomData<C>);
+
+unsafe impl<C: Component + 'static> QueryFilter for Added<C> {
+    type State = (Option<RawStorage<C>>, u64);
+
+    fn access(access: &mut Access) {
+        access.read::<C>();
+    }
+
+    fn state(source: &mut StorageSource, ticks: Ticks) -> Self::State {
+        (source.raw_read::<C>(), ticks.last_run)
+    }
+
+    unsafe fn matches(state: &Self::State, id: EntityId) -> bool {
+        let (storage, last_run) = state;
+        match storage.and_then(|storage| Some((storage, storage.dense_index(id)?))) {
+            Some((storage, index)) => (*storage.ticks(index)).is_added(*last_run),
+            None => false
+        }
+    }
+}
+
+unsafe impl<C: Component + 'static> QueryFilter for Changed<C> {
+    type State = (Option<RawStorage<C>>, u64);
+
+    fn access(access: &mut Access) {
+        access.read::<C>();
+    }
+
+    fn state(source: &mut StorageSource, ticks: Ticks) -> Self::State {
+        (source.raw_read::<C>(), ticks.last_run)
+    }
+
+    unsafe fn matches(state: &Self::State, id: EntityId) -> bool {
+        let (storage, last_run) = state;
+        match storage.and_then(|storage| Some((storage, storage.dense_index(id)?))) {
+            Some((storage, index)) => (*storage.ticks(index)).is_changed(*last_run),
+            None => false
+        }
+    }
+}
+
+unsafe impl QueryFilter for () {
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn state(_source: &mut StorageSource, _ticks: Ticks) {}
+
+    unsafe fn matches(_state: &(), _id: EntityId) -> bool {
+        true
+    }
+}
+
+unsafe impl<'w> QueryData<'w> for EntityId {
+    type Item = EntityId;
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn state(_source: &mut StorageSource, _ticks: Ticks) -> Option<()> {
+        Some(())
+    }
+
+    fn driver(_state: &()) -> Option<Driver> {
+        None
+    }
+
+    unsafe fn fetch(_state: &(), id: EntityId) -> Option<EntityId> {
+        Some(id)
+    }
+}
+
+unsafe impl<'w, C: Component + 'static> QueryData<'w> for &'w C {
+    type Item = &'w C;
+    type State = RawStorage<C>;
+
+    fn access(access: &mut Access) {
+        access.read::<C>();
+    }
+
+    fn state(source: &mut StorageSource, _ticks: Ticks) -> Option<RawStorage<C>> {
+        source.raw_read::<C>()
+    }
+
+    fn driver(state: &RawStorage<C>) -> Option<Driver> {
+        Some(Driver::new(state))
+    }
+
+    unsafe fn fetch(state: &RawStorage<C>, id: EntityId) -> Option<&'w C> {
+        let index = state.dense_index(id)?;
+        Some(&*state.component(index))
+    }
+}
+
+unsafe impl<'w, C: Component + 'static> QueryData<'w> for &'w mut C {
+    type Item = Mut<'w, C>;
+    type State = (RawStorage<C>, Ticks);
+
+    fn access(access: &mut Access) {
+        access.write::<C>();
+    }
+
+    fn state(source: &mut StorageSource, ticks: Ticks) -> Option<Self::State> {
+        Some((source.raw::<C>()?, ticks))
+    }
+
+    fn driver(state: &Self::State) -> Option<Driver> {
+        Some(Driver::new(&state.0))
+    }
+
+    unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Mut<'w, C>> {
+        let (storage, ticks) = state;
+        let index = storage.dense_index(id)?;
+        Some(Mut::new(&mut *storage.component(index), &mut *storage.ticks(index), *ticks))
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($name:ident),*) => {
+        unsafe impl<'w, $($name: QueryData<'w>),*> QueryData<'w> for ($($name,)*) {
+            type Item = ($($name::Item,)*);
+            type State = ($($name::State,)*);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)*
+            }
+
+            fn state(source: &mut StorageSource, ticks: Ticks) -> Option<Self::State> {
+                Some(($($name::state(source, ticks)?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            fn driver(state: &Self::State) -> Option<Driver> {
+                let ($($name,)*) = state;
+                let driver = None;
+                $(let driver = Driver::shortest(driver, $name::driver($name));)*
+                driver
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(state: &Self::State, id: EntityId) -> Option<Self::Item> {
+                let ($($name,)*) = state;
+                Some(($($name::fetch($name, id)?,)*))
+            }
+        }
+
+        unsafe impl<$($name: QueryFilter),*> QueryFilter for ($($name,)*) {
+            type State = ($($name::State,)*);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)*
+            }
+
+            fn state(source: &mut StorageSource, ticks: Ticks) -> Self::State {
+                ($($name::state(source, ticks),)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn matches(state: &Self::State, id: EntityId) -> bool {
+                let ($($name,)*) = state;
+                $($name::matches($name, id))&&*
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+impl_query_tuple!(A, B, C, D, E, F);
+impl_query_tuple!(A, B, C, D, E, F, G);
+impl_query_tuple!(A, B, C, D, E, F, G, H);
+
+/// Iterator over all Entities having the Components in Q and passing the filter F.
+///
+/// Walks the owners of the smallest fetched storage, or every Entity
+/// if Q fetches no Components. Created by ECS::query() and ECS::query_filtered().
+pub struct Query<'w, Q: QueryData<'w>, F: QueryFilter = ()> {
+    entities: &'w [EntitySlot],
+    state: Option<Q::State>,
+    filter: F::State,
+    next: usize,
+}
+
+impl<'w, Q: QueryData<'w>, F: QueryFilter> Query<'w, Q, F> {
+    /// Creates a Query over the given Entities and Components.
+    ///
+    /// Panics if Q writes a Component it also reads or writes elsewhere.
+    pub(crate) fn new(entities: &'w [EntitySlot], mut source: StorageSource<'w>, ticks: Ticks) -> Self {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        if access.has_self_conflict() {
+            panic!("Query accesses a Component mutably more than once");
+        }
+
+        Query {
+            entities,
+            state: Q::state(&mut source, ticks),
+            filter: F::state(&mut source, ticks),
+            next: 0,
+        }
+    }
+
+    /// Components the Query and its filter read and write.
+    pub fn access() -> Access {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+        access
+    }
+
+    /// Helper function for finding the next Entity to visit.
+    fn next_id(&mut self, driver: Option<Driver>) -> Option<EntityId> {
+        match driver {
+            Some(driver) => {
+                if self.next >= driver.len {
+                    return None;
+                }
+
+                // The owners stay valid for 'w, and next is less than their length.
+                let id = unsafe { *driver.owners.add(self.next) };
+                self.next += 1;
+                Some(id)
+            },
+            None => {
+                while self.next < self.entities.len() {
+                    let index = self.next;
+                    self.next += 1;
+
+                    let slot = &self.entities[index];
+                    if slot.data.is_some() {
+                        return Some(EntityId::new(index as u32, slot.generation));
+                    }
+                }
+
+                None
+            }
+        }
+    }
+}
+
+impl<'w, Q: QueryData<'w>, F: QueryFilter> Iterator for Query<'w, Q, F> {
+    type Item = Q::Item;
+
+    fn next(&mut self) -> Option<Q::Item> {
+        let driver = Q::driver(self.state.as_ref()?);
+        while let Some(id) = self.next_id(driver) {
+            // The storages stay valid for 'w, the Access was checked for
+            // conflicts on creation, and every Entity is visited only once,
+            // so no two Items alias.
+            unsafe {
+                if !F::matches(&self.filter, id) {
+                    continue;
+                }
+
+                if let Some(item) = Q::fetch(self.state.as_ref().unwrap(), id) {
+                    return Some(item);
+                }
+            }
+        }
+
+        None
+    }
+}
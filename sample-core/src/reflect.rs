@@ -0,0 +1,383 @@
+use std::any::Any;
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use serde_json::Map;
+use crate::component::Component;
+use crate::ecs::{ECS, EntityId};
+use crate::scene::SceneComponent;
+
+pub use sample_derive::Reflect;
+pub use serde_json::Value;
+
+/// A type whose fields can be listed, read and written at runtime, e.g.
+/// by an inspector or console commands.
+///
+/// Derived for structs with `#[derive(Reflect)]`. Plain values like
+/// numbers have no fields, and are only read and written as a whole.
+pub trait Reflect: Any {
+    /// Full name of the type, as given by std::any::type_name().
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Names of the fields, in declaration order.
+    fn field_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn field(&self, _name: &str) -> Option<&dyn Reflect> {
+        None
+    }
+
+    fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> {
+        None
+    }
+
+    /// Writes the whole value, with all of its fields.
+    fn to_value(&self) -> Value;
+
+    /// Overwrites the value, or the fields present in it.
+    fn set_value(&mut self, value: Value) -> Result<(), String>;
+
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl dyn Reflect {
+    /// Gets a nested field by a dot separated path, e.g. `local.translation.x`.
+    ///
+    /// An empty path is the value itself.
+    pub fn path(&self, path: &str) -> Option<&dyn Reflect> {
+        path.split('.')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |value, name| value.field(name))
+    }
+
+    /// Gets a mutable nested field by a dot separated path.
+    pub fn path_mut(&mut self, path: &str) -> Option<&mut dyn Reflect> {
+        path.split('.')
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |value, name| value.field_mut(name))
+    }
+
+    /// Gets a nested field of type T.
+    pub fn get<T: Reflect>(&self, path: &str) -> Option<&T> {
+        self.path(path)?.as_any().downcast_ref::<T>()
+    }
+
+    /// Overwrites a nested field of type T.
+    pub fn set<T: Reflect>(&mut self, path: &str, value: T) -> Result<(), String> {
+        let type_name = self.type_name();
+        let field = self.path_mut(path)
+            .ok_or_else(|| format!("{} has no field {}", type_name, path))?;
+        let field_type = field.type_name();
+        match field.as_any_mut().downcast_mut::<T>() {
+            Some(field) => {
+                *field = value;
+                Ok(())
+            }
+            None => Err(format!("{} of {} is a {}", path, type_name, field_type))
+        }
+    }
+
+    /// Overwrites a nested field from a value, e.g. one typed into a console.
+    pub fn set_path_value(&mut self, path: &str, value: Value) -> Result<(), String> {
+        let type_name = self.type_name();
+        self.path_mut(path)
+            .ok_or_else(|| format!("{} has no field {}", type_name, path))?
+            .set_value(value)
+            .map_err(|e| format!("{} of {}: {}", path, type_name, e))
+    }
+}
+
+/// to_value() of derived structs, an object of the values of the fields.
+pub fn struct_to_value(reflect: &dyn Reflect) -> Value {
+    let fields = reflect.field_names()
+        .into_iter()
+        .map(|name| {
+            let value = reflect.field(&name).unwrap().to_value();
+            (name, value)
+        })
+        .collect::<Map<String, Value>>();
+
+    Value::Object(fields)
+}
+
+/// set_value() of derived structs, setting the fields of an object.
+pub fn struct_set_value(reflect: &mut dyn Reflect, value: Value) -> Result<(), String> {
+    let type_name = reflect.type_name();
+    let fields = match value {
+        Value::Object(fields) => fields,
+        _ => return Err(format!("{} is set from an object", type_name))
+    };
+
+    for (name, value) in fields {
+        reflect.field_mut(&name)
+            .ok_or_else(|| format!("{} has no field {}", type_name, name))?
+            .set_value(value)
+            .map_err(|e| format!("{}: {}", name, e))?;
+    }
+
+    Ok(())
+}
+
+/// Implements Reflect for types without fields, written through serde.
+macro_rules! impl_reflect_value {
+    ($($type:ty),*) => {
+        $(
+            impl Reflect for $type {
+                fn to_value(&self) -> Value {
+                    serde_json::to_value(self).unwrap()
+                }
+
+                fn set_value(&mut self, value: Value) -> Result<(), String> {
+                    *self = serde_json::from_value(value).map_err(|e| e.to_string())?;
+                    Ok(())
+                }
+
+                fn as_any(&self) -> &dyn Any {
+                    self
+                }
+
+                fn as_any_mut(&mut self) -> &mut dyn Any {
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_reflect_value!(bool, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64, String, Quat, Mat4);
+
+/// Implements Reflect for vectors, with a field per axis.
+macro_rules! impl_reflect_vector {
+    ($type:ty, $($axis:ident),*) => {
+        impl Reflect for $type {
+            fn field_names(&self) -> Vec<String> {
+                vec![$(String::from(stringify!($axis))),*]
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn Reflect> {
+                match name {
+                    $(stringify!($axis) => Some(&self.$axis),)*
+                    _ => None
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+                match name {
+                    $(stringify!($axis) => Some(&mut self.$axis),)*
+                    _ => None
+                }
+            }
+
+            fn to_value(&self) -> Value {
+                serde_json::to_value(self).unwrap()
+            }
+
+            /// Set from an array of every axis, or an object of some.
+            fn set_value(&mut self, value: Value) -> Result<(), String> {
+                match value {
+                    Value::Object(_) => struct_set_value(self, value),
+                    value => {
+                        *self = serde_json::from_value(value).map_err(|e| e.to_string())?;
+                        Ok(())
+                    }
+                }
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+        }
+    };
+}
+
+impl_reflect_vector!(Vec2, x, y);
+impl_reflect_vector!(Vec3, x, y, z);
+impl_reflect_vector!(Vec4, x, y, z, w);
+
+/// Items are fields named by their index. Setting a value keeps the length.
+impl<T: Reflect> Reflect for Vec<T> {
+    fn field_names(&self) -> Vec<String> {
+        (0..self.len()).map(|index| index.to_string()).collect()
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        let item = self.get(name.parse::<usize>().ok()?)?;
+        Some(item)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        let item = self.get_mut(name.parse::<usize>().ok()?)?;
+        Some(item)
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(|item| item.to_value()).collect())
+    }
+
+    fn set_value(&mut self, value: Value) -> Result<(), String> {
+        let items = match value {
+            Value::Array(items) if items.len() == self.len() => items,
+            _ => return Err(format!("Expected an array of {} items", self.len()))
+        };
+
+        for (index, (item, value)) in self.iter_mut().zip(items).enumerate() {
+            item.set_value(value).map_err(|e| format!("{}: {}", index, e))?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Reads and writes the Components of one registered type.
+#[derive(Clone, Copy)]
+pub(crate) struct ReflectType {
+    get: fn(&ECS, EntityId) -> Option<&dyn Reflect>,
+    get_mut: fn(&mut ECS, EntityId) -> Option<&mut dyn Reflect>,
+}
+
+fn get_component<C: Component + Reflect>(ecs: &ECS, id: EntityId) -> Option<&dyn Reflect> {
+    let comp = ecs.get_component::<C>(id)?;
+    Some(comp)
+}
+
+/// Flags the Component as changed, as it may be written to.
+fn get_component_mut<C: Component + Reflect>(ecs: &mut ECS, id: EntityId) -> Option<&mut dyn Reflect> {
+    let comp = ecs.get_component_mut::<C>(id)?.into_inner();
+    Some(comp)
+}
+
+impl ECS {
+    /// Makes Components of type C reflectable under the given name,
+    /// e.g. `ecs.register_reflect::<Health>("Health")`.
+    ///
+    /// Types saved in scenes are registered under the same name, see
+    /// register_component().
+    pub fn register_reflect<C: Component + Reflect>(&mut self, name: &str) {
+        self.register_type::<C>(name).reflect = Some(ReflectType {
+            get: get_component::<C>,
+            get_mut: get_component_mut::<C>,
+        });
+    }
+
+    /// Saves Components of type C in scenes and makes them reflectable,
+    /// both under the given name.
+    pub fn register_component<C: SceneComponent + Reflect>(&mut self, name: &str) {
+        self.register_scene_component::<C>(name);
+        self.register_reflect::<C>(name);
+    }
+
+    /// Helper function for finding a reflectable type by its name.
+    fn reflect_type(&self, name: &str) -> Option<ReflectType> {
+        self.registered_type(name)?.reflect
+    }
+
+    /// Names of the reflectable Components of an Entity.
+    pub fn reflected_components(&self, id: EntityId) -> Vec<String> {
+        self.registered_types()
+            .iter()
+            .filter(|registered| registered.reflect.is_some_and(|reflect_type| (reflect_type.get)(self, id).is_some()))
+            .map(|registered| registered.name.clone())
+            .collect()
+    }
+
+    /// Gets a Component of an Entity by the name its type was registered under.
+    pub fn reflect(&self, id: EntityId, name: &str) -> Option<&dyn Reflect> {
+        (self.reflect_type(name)?.get)(self, id)
+    }
+
+    /// Gets a mutable Component of an Entity by the name its type was
+    /// registered under, flagging it as changed.
+    pub fn reflect_mut(&mut self, id: EntityId, name: &str) -> Option<&mut dyn Reflect> {
+        (self.reflect_type(name)?.get_mut)(self, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::Transform;
+    use crate::component::{ScriptComponent, MeshComponent};
+    use crate::hierarchy::TransformComponent;
+    use crate::scriptable::Scriptable;
+
+    #[derive(Reflect)]
+    struct Turret {
+        range: f32,
+        targets: Vec<u32>,
+        #[reflect(ignore)]
+        _cooldown: Option<f32>,
+    }
+
+    impl Scriptable for Turret {
+        fn create() -> Self {
+            Turret {
+                range: 10.0,
+                targets: vec![1, 2],
+                _cooldown: None,
+            }
+        }
+    }
+
+    #[test]
+    fn fields_are_listed_and_set_by_path() {
+        let mut ecs = ECS::new();
+        ecs.register_reflect::<ScriptComponent<Turret>>("Turret");
+        let id = ecs.new_entity("Turret").id();
+        ecs.add_component::<TransformComponent>(id, &|_| {});
+        ecs.add_component::<ScriptComponent<Turret>>(id, &|_| {});
+        ecs.add_tag(id, "Enemy");
+        assert_eq!(ecs.reflected_components(id), ["Tags", "Transform", "Turret"]);
+        // Registered for reflection only, so not saved in scenes.
+        assert_eq!(ecs.scene_type_names(), ["Tags", "Parent", "Transform", "Mesh"]);
+
+        let transform = ecs.reflect(id, "Transform").unwrap();
+        assert_eq!(transform.field_names(), ["local"]);
+        let local = transform.path("local").unwrap();
+        assert_eq!(local.type_name(), "sample_core::Transform");
+        assert_eq!(local.field_names(), ["translation", "rotation", "scale"]);
+        assert_eq!(local.path("scale.y").unwrap().to_value(), json!(1.0));
+
+        let transform = ecs.reflect_mut(id, "Transform").unwrap();
+        transform.set("local.translation.x", 2.0f32).unwrap();
+        transform.set_path_value("local.scale", json!([3.0, 3.0, 3.0])).unwrap();
+        assert!(transform.set("local.scale", 1.0f32).unwrap_err().starts_with("local.scale of sample_core::hierarchy::TransformComponent is a "));
+        assert!(transform.set_path_value("local.size", json!(1.0)).is_err());
+        let local = ecs.get_component::<TransformComponent>(id).unwrap().local;
+        assert_eq!(local.translation.x, 2.0);
+        assert_eq!(local.scale, Vec3::splat(3.0));
+
+        let script = ecs.reflect_mut(id, "Turret").unwrap();
+        assert_eq!(script.type_name(), std::any::type_name::<ScriptComponent<Turret>>());
+        assert!(script.set("enabled", 1.0f32).unwrap_err().ends_with("ScriptComponent<sample_core::reflect::tests::Turret> is a bool"));
+        assert_eq!(script.field_names(), ["enabled", "script"]);
+        assert_eq!(script.path("script").unwrap().field_names(), ["range", "targets"]);
+        assert_eq!(script.get::<u32>("script.targets.1"), Some(&2));
+        script.set_path_value("script", json!({ "range": 4.5, "targets": [7, 8] })).unwrap();
+        assert_eq!(script.to_value(), json!({ "enabled": true, "script": { "range": 4.5, "targets": [7, 8] } }));
+        assert!(script.set_path_value("script.targets", json!([1])).is_err());
+    }
+
+    #[test]
+    fn mesh_components_reflect_their_transforms() {
+        let transform = Transform::new();
+        let value = (&transform as &dyn Reflect).to_value();
+        assert_eq!(value["scale"], json!([1.0, 1.0, 1.0]));
+
+        let mesh = MeshComponent::create(&mut ECS::new().new_entity("Mesh"));
+        assert_eq!((&mesh as &dyn Reflect).field_names(), ["transforms"]);
+    }
+}
@@ -0,0 +1,88 @@
+use std::any::TypeId;
+use crate::ecs::ECS;
+use crate::reflect::ReflectType;
+use crate::scene::SceneType;
+
+/// A Component type registered under a name, along with the functions
+/// saving it in scenes and reflecting it, if registered for them.
+#[derive(Clone)]
+pub(crate) struct RegisteredType {
+    pub(crate) name: String,
+    data_type: TypeId,
+    pub(crate) scene: Option<SceneType>,
+    pub(crate) reflect: Option<ReflectType>,
+}
+
+/// Component types by the names scenes and reflection refer to them by.
+///
+/// Stored as a resource. Types keep the place they were first registered
+/// at, which is the order scenes load them in.
+#[derive(Default)]
+pub(crate) struct TypeRegistry {
+    types: Vec<RegisteredType>,
+}
+
+impl TypeRegistry {
+    /// Registered types, in the order they were first registered.
+    pub(crate) fn types(&self) -> &[RegisteredType] {
+        &self.types
+    }
+
+    /// Gets a registered type by its name.
+    pub(crate) fn get(&self, name: &str) -> Option<&RegisteredType> {
+        self.types.iter().find(|registered| registered.name == name)
+    }
+
+    /// Gets the type registered under a name, adding it if missing.
+    ///
+    /// Registering another type under a taken name drops what the old
+    /// type was registered for.
+    fn entry<C: 'static>(&mut self, name: &str) -> &mut RegisteredType {
+        let data_type = TypeId::of::<C>();
+        let index = match self.types.iter().position(|registered| registered.name == name) {
+            Some(index) => index,
+            None => {
+                self.types.push(RegisteredType {
+                    name: name.to_string(),
+                    data_type,
+                    scene: None,
+                    reflect: None,
+                });
+                self.types.len() - 1
+            }
+        };
+
+        let registered = &mut self.types[index];
+        if registered.data_type != data_type {
+            registered.data_type = data_type;
+            registered.scene = None;
+            registered.reflect = None;
+        }
+        registered
+    }
+}
+
+impl ECS {
+    /// Helper function for getting the type registered under a name,
+    /// adding the registry and the type if missing.
+    pub(crate) fn register_type<C: 'static>(&mut self, name: &str) -> &mut RegisteredType {
+        if !self.contains_resource::<TypeRegistry>() {
+            self.insert_resource(TypeRegistry::default());
+        }
+
+        self.resource_mut::<TypeRegistry>().unwrap().entry::<C>(name)
+    }
+
+    /// Registered types, in the order they were first registered.
+    pub(crate) fn registered_types(&self) -> &[RegisteredType] {
+        match self.resource::<TypeRegistry>() {
+            Some(registry) => registry.types(),
+            None => &[]
+        }
+    }
+
+    /// Gets a registered type by its name.
+    pub(crate) fn registered_type(&self, name: &str) -> Option<&RegisteredType> {
+        self.resource::<TypeRegistry>()?.get(name)
+    }
+}
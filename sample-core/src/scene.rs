@@ -0,0 +1,416 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use crate::assets::Assets;
+use crate::component::Component;
+use crate::ecs::{ECS, EntityId};
+
+/// Format independent value of a Component in a scene file.
+pub use serde_json::Value;
+
+/// A Component which can be saved in scene files.
+///
+/// Implemented for every Component which is Serialize and Deserialize.
+/// Components referring to other Entities or to assets implement it
+/// themselves, using names instead.
+pub trait SceneComponent: Component + Sized + 'static {
+    /// Writes the Component to a value.
+    fn save(&self, ecs: &ECS) -> Result<Value, String>;
+
+    /// Adds the Component written to value to an Entity of a loading scene.
+    fn load(value: Value, loader: &mut SceneLoader, id: EntityId) -> Result<(), String>;
+}
+
+impl<C: Component + Serialize + DeserializeOwned + 'static> SceneComponent for C {
+    fn save(&self, _ecs: &ECS) -> Result<Value, String> {
+        serde_json::to_value(self).map_err(|e| e.to_string())
+    }
+
+    fn load(value: Value, loader: &mut SceneLoader, id: EntityId) -> Result<(), String> {
+        let comp: C = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        loader.insert(id, comp)
+    }
+}
+
+/// The ECS and Assets a scene is loaded into.
+pub struct SceneLoader<'a> {
+    ecs: &'a mut ECS,
+    assets: &'a mut Assets,
+    /// Loaded Entities, by their name in the scene file.
+    entities: HashMap<String, EntityId>,
+}
+
+impl SceneLoader<'_> {
+    /// The ECS the scene is loaded into.
+    pub fn ecs(&mut self) -> &mut ECS {
+        self.ecs
+    }
+
+    /// Assets the scene refers to.
+    pub fn assets(&mut self) -> &mut Assets {
+        self.assets
+    }
+
+    /// Id of an Entity of the scene, by its name in the scene file.
+    pub fn entity(&self, name: &str) -> Option<EntityId> {
+        self.entities.get(name).copied()
+    }
+
+    /// Adds a Component to an Entity, replacing the one made by create().
+    pub fn insert<C: Component + 'static>(&mut self, id: EntityId, comp: C) -> Result<(), String> {
+        let comp = Cell::new(Some(comp));
+        let added = self.ecs.add_component::<C>(id, &|c| {
+            if let Some(comp) = comp.take() {
+                *c = comp;
+            }
+        });
+
+        match added {
+            Some(_) => Ok(()),
+            None => Err(format!("Entity {} was deleted while loading", id.index()))
+        }
+    }
+
+    /// Despawns the Entities loaded so far, and passes the error on.
+    fn abort(&mut self, ids: &[EntityId], error: String) -> String {
+        for id in ids {
+            self.ecs.despawn(*id);
+        }
+
+        error
+    }
+}
+
+/// Saves and loads the Components of one registered type.
+#[derive(Clone)]
+pub(crate) struct SceneType {
+    save: fn(&ECS, EntityId) -> Option<Result<Value, String>>,
+    load: fn(Value, &mut SceneLoader, EntityId) -> Result<(), String>,
+    remove: fn(&mut ECS, EntityId),
+}
+
+/// Saves the Component of type C of an Entity, if it has one.
+fn save_component<C: SceneComponent>(ecs: &ECS, id: EntityId) -> Option<Result<Value, String>> {
+    ecs.get_component::<C>(id).map(|comp| comp.save(ecs))
+}
+
+/// Removes the Component of type C of an Entity, if it has one.
+fn remove_component<C: SceneComponent>(ecs: &mut ECS, id: EntityId) {
+    ecs.remove_component::<C>(id);
+}
+
+/// Contents of a scene file.
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    entities: Vec<SceneEntity>,
+}
+
+/// An Entity of a scene file.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SceneEntity {
+    pub(crate) name: String,
+    /// Values of the Components, by the name of their type.
+    #[serde(default)]
+    pub(crate) components: BTreeMap<String, Value>,
+}
+
+/// File formats of scenes, picked by the extension of the path.
+enum Format {
+    Ron,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Format, String> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Ok(Format::Ron),
+            Some("json") => Ok(Format::Json),
+            _ => Err(format!("Scene {} is neither .ron nor .json", path.display()))
+        }
+    }
+}
+
+/// Reads the Entities of a .ron or .json scene file.
+pub(crate) fn read_scene(path: &Path) -> Result<Vec<SceneEntity>, String> {
+    let format = Format::of(path)?;
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: SceneFile = match format {
+        Format::Ron => ron::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+        Format::Json => serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+    };
+
+    Ok(file.entities)
+}
+
+impl ECS {
+    /// Saves Components of type C in scenes, under the given name,
+    /// e.g. `ecs.register_scene_component::<Health>("Health")`.
+    ///
+    /// Components are loaded in the order their types were first
+    /// registered, for scenes or reflection.
+    pub fn register_scene_component<C: SceneComponent>(&mut self, name: &str) {
+        self.register_type::<C>(name).scene = Some(SceneType {
+            save: save_component::<C>,
+            load: C::load,
+            remove: remove_component::<C>,
+        });
+    }
+
+    /// Helper function for getting the types saved in scenes along with
+    /// their names, in loading order.
+    fn scene_types(&self) -> Vec<(String, SceneType)> {
+        self.registered_types()
+            .iter()
+            .filter_map(|registered| Some((registered.name.clone(), registered.scene.clone()?)))
+            .collect()
+    }
+
+    /// Names of the types saved in scenes, in the order they are loaded in.
+    pub(crate) fn scene_type_names(&self) -> Vec<String> {
+        self.scene_types()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Saves every Entity, with its name and the Components of registered
+    /// types, to a .ron or .json file.
+    pub fn save_scene<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let format = Format::of(path)?;
+        let types = self.scene_types();
+
+        let mut entities = Vec::new();
+        for id in self.entities() {
+            let mut components = BTreeMap::new();
+            for (name, scene_type) in &types {
+                if let Some(value) = (scene_type.save)(self, id) {
+                    let value = value.map_err(|e| format!("{} of {}: {}", name, self.entity_name(id).unwrap(), e))?;
+                    components.insert(name.clone(), value);
+                }
+            }
+
+            entities.push(SceneEntity {
+                name: self.entity_name(id).unwrap().to_string(),
+                components,
+            });
+        }
+
+        let file = SceneFile {
+            entities,
+        };
+        let text = match format {
+            Format::Ron => ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::new().compact_arrays(true))
+                .map_err(|e| e.to_string())?,
+            Format::Json => serde_json::to_string_pretty(&file)
+                .map_err(|e| e.to_string())?,
+        };
+
+        fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Loads the Entities of a .ron or .json scene file into the ECS,
+    /// resolving asset references through assets. Returns their ids.
+    ///
+    /// Entities are spawned first, so Components may refer to any Entity
+    /// of the scene. Names taken in the ECS get a suffix. On error, the
+    /// Entities loaded so far are despawned.
+    pub fn load_scene<P: AsRef<Path>>(&mut self, path: P, assets: &mut Assets) -> Result<Vec<EntityId>, String> {
+        let entities = read_scene(path.as_ref())?;
+        self.spawn_scene(&entities, assets)
+    }
+
+    /// Spawns the Entities of a scene, see load_scene().
+    pub(crate) fn spawn_scene(&mut self, entities: &[SceneEntity], assets: &mut Assets) -> Result<Vec<EntityId>, String> {
+        let types = self.scene_types();
+        for entity in entities {
+            if let Some(name) = entity.components.keys().find(|name| !types.iter().any(|(t, _)| t == *name)) {
+                return Err(format!("Unknown Component type {} in {}", name, entity.name));
+            }
+        }
+
+        let mut loader = SceneLoader {
+            ecs: self,
+            assets,
+            entities: HashMap::new(),
+        };
+        let mut ids = Vec::with_capacity(entities.len());
+        for entity in entities {
+            let id = loader.ecs.new_entity(&entity.name).id();
+            ids.push(id);
+            if loader.entities.insert(entity.name.clone(), id).is_some() {
+                return Err(loader.abort(&ids, format!("Entity {} appears twice", entity.name)));
+            }
+        }
+
+        for (entity, id) in entities.iter().zip(ids.clone()) {
+            for (name, scene_type) in &types {
+                if let Some(value) = entity.components.get(name) {
+                    if let Err(e) = (scene_type.load)(value.clone(), &mut loader, id) {
+                        let error = format!("{} of {}: {}", name, entity.name, e);
+                        return Err(loader.abort(&ids, error));
+                    }
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Loads one Component of a registered type onto an existing Entity.
+    ///
+    /// entities maps the names the value may refer to onto their ids.
+    pub(crate) fn load_scene_component(
+        &mut self,
+        id: EntityId,
+        type_name: &str,
+        value: Value,
+        entities: HashMap<String, EntityId>,
+        assets: &mut Assets,
+    ) -> Result<(), String> {
+        let scene_type = self.registered_type(type_name)
+            .and_then(|registered| registered.scene.clone())
+            .ok_or_else(|| format!("Unknown Component type {}", type_name))?;
+
+        let mut loader = SceneLoader {
+            ecs: self,
+            assets,
+            entities,
+        };
+        (scene_type.load)(value, &mut loader, id)
+    }
+
+    /// Removes the Component of a registered type from an Entity.
+    pub(crate) fn remove_scene_component(&mut self, id: EntityId, type_name: &str) {
+        if let Some(scene_type) = self.registered_type(type_name).and_then(|registered| registered.scene.clone()) {
+            (scene_type.remove)(self, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+    use super::*;
+    use std::sync::Arc;
+    use crate::component::MeshComponent;
+    use crate::ecs::Entity;
+    use crate::hierarchy::TransformComponent;
+    use crate::mesh::Mesh;
+    use crate::shader::Shader;
+    use crate::texture::Texture;
+    use crate::tag::Tags;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    impl Component for Health {
+        fn create(_entity: &mut Entity) -> Self {
+            Health(100)
+        }
+    }
+
+    fn scene_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("sample-core-{}-{}", std::process::id(), name))
+    }
+
+    fn level() -> ECS {
+        let mut ecs = ECS::new();
+        ecs.register_scene_component::<Health>("Health");
+
+        let ship = ecs.new_entity("Ship").id();
+        ecs.add_component::<TransformComponent>(ship, &|t| t.local.translation = Vec3::new(1.0, 2.0, 3.0));
+        ecs.add_component::<Health>(ship, &|h| h.0 = 40);
+        ecs.add_tag(ship, "Player");
+
+        let turret = ecs.new_entity("Turret").id();
+        ecs.add_component::<TransformComponent>(turret, &|t| t.local.scale = Vec3::splat(0.5));
+        ecs.set_parent(turret, ship);
+        ecs
+    }
+
+    #[test]
+    fn scenes_round_trip_through_ron_and_json() {
+        let ecs = level();
+        for name in ["level.ron", "level.json"] {
+            let path = scene_path(name);
+            ecs.save_scene(&path).unwrap();
+
+            let mut loaded = ECS::new();
+            loaded.register_scene_component::<Health>("Health");
+            loaded.new_entity("Ship");
+            let ids = loaded.load_scene(&path, &mut Assets::new()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(ids.len(), 2);
+            let (ship, turret) = (ids[0], ids[1]);
+            assert_eq!(loaded.entity_name(ship), Some("Ship (1)"));
+            assert_eq!(loaded.get_component::<Health>(ship), Some(&Health(40)));
+            assert_eq!(loaded.get_component::<Tags>(ship).unwrap().get(), &["Player"]);
+            assert_eq!(loaded.parent(turret), Some(ship));
+            assert_eq!(loaded.children(ship), &[turret]);
+
+            let local = loaded.get_component::<TransformComponent>(turret).unwrap().local;
+            assert_eq!(local, ecs.get_component::<TransformComponent>(ecs.entity_id("Turret").unwrap()).unwrap().local);
+            assert_eq!(loaded.world_matrix(turret), ecs.world_matrix(ecs.entity_id("Turret").unwrap()));
+        }
+    }
+
+    #[test]
+    fn meshes_keep_their_own_textures() {
+        let mut assets = Assets::new();
+        assets.add_mesh(Arc::new(Mesh::unloaded("Quad")));
+        assets.add_shader(Arc::new(Shader::unloaded("default")));
+        for path in ["grass.png", "stone.png"] {
+            assets.add_texture(Arc::new(Texture::unloaded(path)));
+        }
+
+        let mut ecs = ECS::new();
+        let id = ecs.new_entity("Ground").id();
+        let (mesh, shader) = (assets.get_mesh("Quad").unwrap(), assets.get_shader("default").unwrap());
+        ecs.add_component::<MeshComponent>(id, &|comp| {
+            for texture in [Some("grass.png"), None, Some("stone.png")] {
+                let texture = texture.map(|path| assets.get_texture(path).unwrap());
+                comp.add_mstm(mesh.clone(), shader.clone(), texture, None);
+            }
+        });
+
+        let path = scene_path("textures.ron");
+        ecs.save_scene(&path).unwrap();
+        let mut loaded = ECS::new();
+        let ids = loaded.load_scene(&path, &mut assets).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let textures: Vec<Option<&str>> = loaded.get_component::<MeshComponent>(ids[0]).unwrap()
+            .textures()
+            .iter()
+            .map(|texture| texture.as_ref().map(|texture| texture.path()))
+            .collect();
+        assert_eq!(textures, [Some("grass.png"), None, Some("stone.png")]);
+    }
+
+    #[test]
+    fn unknown_components_and_bad_references_are_errors() {
+        let path = scene_path("unknown.ron");
+        level().save_scene(&path).unwrap();
+        let mut ecs = ECS::new();
+        let error = ecs.load_scene(&path, &mut Assets::new()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error, "Unknown Component type Health in Ship");
+
+        let path = scene_path("orphan.json");
+        std::fs::write(&path, r#"{ "entities": [
+            { "name": "A" },
+            { "name": "B", "components": { "Parent": "C" } }
+        ] }"#).unwrap();
+        let error = ecs.load_scene(&path, &mut Assets::new()).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error, "Parent of B: Parent C is not in the scene");
+        assert!(ecs.entities().is_empty());
+
+        assert!(ecs.save_scene(scene_path("level.txt")).is_err());
+    }
+}
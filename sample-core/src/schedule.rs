@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use crate::component::Component;
+use crate::ecs::ECS;
+use crate::parallel::{Job, ParallelSystem};
+use crate::query::Access;
+
+/// Stages of an update, run in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    /// Run by ECS::fixed_update() instead of ECS::update().
+    FixedUpdate,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    /// All stages.
+    pub const ALL: [Stage; 5] = [
+        Stage::FixedUpdate,
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+
+    /// Stages run by ECS::update(), in order.
+    pub const FRAME: [Stage; 4] = [
+        Stage::PreUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A system run by the ECS every update, alone and on the main thread.
+pub type System = fn(&mut ECS);
+
+/// How a system is run.
+#[derive(Clone, Copy)]
+enum SystemKind {
+    Exclusive(System),
+    Parallel(ParallelSystem),
+}
+
+impl SystemKind {
+    /// Address of the function of the system.
+    fn address(self) -> usize {
+        match self {
+            SystemKind::Exclusive(system) => system as usize,
+            SystemKind::Parallel(system) => system as usize,
+        }
+    }
+}
+
+/// Identifies a system of the Schedule in snapshots, wherever it ends up
+/// when systems are added or sorted later.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct SystemKey {
+    stage: Stage,
+    label: Option<String>,
+    /// Address of the function of the system.
+    system: usize,
+    /// Number of systems of the stage added before it with the same
+    /// label and function.
+    nth: usize,
+}
+
+/// A system along with its ordering constraints.
+struct SystemEntry {
+    system: SystemKind,
+    /// Components a parallel system reads and writes.
+    access: Access,
+    label: Option<String>,
+    before: Vec<String>,
+    after: Vec<String>,
+    /// Change tick of the last run of the system.
+    last_run: u64,
+}
+
+/// Builder for the label and ordering of a system, returned by
+/// ECS::add_system().
+pub struct SystemBuilder<'a> {
+    entry: &'a mut SystemEntry,
+    dirty: &'a mut bool,
+}
+
+impl<'a> SystemBuilder<'a> {
+    /// Names the system, so others can be ordered relative to it.
+    pub fn label<S: Into<String>>(self, label: S) -> SystemBuilder<'a> {
+        self.entry.label = Some(label.into());
+        *self.dirty = true;
+        self
+    }
+
+    /// Runs the system before the systems with the label, in the same stage.
+    pub fn before<S: Into<String>>(self, label: S) -> SystemBuilder<'a> {
+        self.entry.before.push(label.into());
+        *self.dirty = true;
+        self
+    }
+
+    /// Runs the system after the systems with the label, in the same stage.
+    pub fn after<S: Into<String>>(self, label: S) -> SystemBuilder<'a> {
+        self.entry.after.push(label.into());
+        *self.dirty = true;
+        self
+    }
+
+    /// Declares that a parallel system reads Components of type C.
+    pub fn reads<C: Component + 'static>(self) -> SystemBuilder<'a> {
+        self.entry.access.read::<C>();
+        *self.dirty = true;
+        self
+    }
+
+    /// Declares that a parallel system writes Components of type C.
+    pub fn writes<C: Component + 'static>(self) -> SystemBuilder<'a> {
+        self.entry.access.write::<C>();
+        *self.dirty = true;
+        self
+    }
+}
+
+/// The systems of a single stage.
+#[derive(Default)]
+struct StageSystems {
+    systems: Vec<SystemEntry>,
+    /// Indices into systems, in the order they run.
+    order: Vec<usize>,
+    /// Systems which run at the same time, in the order they run.
+    batches: Vec<Vec<usize>>,
+    /// Whether order needs to be recomputed.
+    dirty: bool,
+}
+
+impl StageSystems {
+    /// Sorts the systems so that all before/after constraints hold.
+    ///
+    /// Systems without constraints between them keep the order they
+    /// were added in. Panics if the constraints form a cycle.
+    fn sort(&mut self) {
+        let count = self.systems.len();
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); count];
+        let mut incoming = vec![0; count];
+
+        let labelled = |label: &str| -> Vec<usize> {
+            self.systems.iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.label.as_deref() == Some(label))
+                .map(|(i, _)| i)
+                .collect()
+        };
+
+        for (i, entry) in self.systems.iter().enumerate() {
+            for label in &entry.before {
+                for j in labelled(label) {
+                    edges[i].push(j);
+                }
+            }
+            for label in &entry.after {
+                for j in labelled(label) {
+                    edges[j].push(i);
+                }
+            }
+        }
+
+        for targets in &edges {
+            for target in targets {
+                incoming[*target] += 1;
+            }
+        }
+
+        self.order.clear();
+        let mut done = vec![false; count];
+        while self.order.len() < count {
+            let next = (0..count).find(|i| !done[*i] && incoming[*i] == 0);
+            let next = match next {
+                Some(next) => next,
+                None => panic!("Systems have cyclic before/after constraints"),
+            };
+
+            done[next] = true;
+            self.order.push(next);
+            for target in &edges[next] {
+                incoming[*target] -= 1;
+            }
+        }
+
+        self.batch(&edges);
+        self.dirty = false;
+    }
+
+    /// Groups the sorted systems into batches.
+    ///
+    /// Consecutive parallel systems share a batch as long as their access
+    /// does not conflict and none is ordered relative to another.
+    /// Exclusive systems always run alone.
+    fn batch(&mut self, edges: &[Vec<usize>]) {
+        self.batches.clear();
+        let mut batch: Vec<usize> = Vec::new();
+        for &i in &self.order {
+            let joins = match self.systems[i].system {
+                SystemKind::Exclusive(_) => false,
+                SystemKind::Parallel(_) => batch.iter().all(|&j| {
+                    matches!(self.systems[j].system, SystemKind::Parallel(_))
+                        && !self.systems[i].access.conflicts_with(&self.systems[j].access)
+                        && !edges[i].contains(&j)
+                        && !edges[j].contains(&i)
+                }),
+            };
+
+            if !joins && !batch.is_empty() {
+                self.batches.push(std::mem::take(&mut batch));
+            }
+            batch.push(i);
+        }
+
+        if !batch.is_empty() {
+            self.batches.push(batch);
+        }
+    }
+
+    /// Runs a batch of systems.
+    fn run_batch(&mut self, batch: usize, ecs: &mut ECS) {
+        let batch = &self.batches[batch];
+        if let [i] = batch[..] {
+            if let SystemKind::Exclusive(system) = self.systems[i].system {
+                ecs.run_system(system, &mut self.systems[i].last_run);
+                return;
+            }
+        }
+
+        let jobs: Vec<Job> = self.systems.iter_mut()
+            .enumerate()
+            .filter(|(i, _)| batch.contains(i))
+            .filter_map(|(_, entry)| match entry.system {
+                SystemKind::Parallel(system) => Some(Job {
+                    system,
+                    access: &entry.access,
+                    last_run: &mut entry.last_run,
+                }),
+                SystemKind::Exclusive(_) => None
+            })
+            .collect();
+
+        ecs.run_parallel(jobs);
+    }
+}
+
+/// All systems of the ECS, grouped in stages.
+pub struct Schedule {
+    stages: Vec<StageSystems>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            stages: Stage::ALL.iter().map(|_| StageSystems::default()).collect(),
+        }
+    }
+}
+
+impl Schedule {
+    /// Creates an empty Schedule.
+    pub fn new() -> Self {
+        Schedule::default()
+    }
+
+    /// Adds a system to a stage.
+    pub fn add_system(&mut self, stage: Stage, system: System) -> SystemBuilder<'_> {
+        self.add_entry(stage, SystemKind::Exclusive(system))
+    }
+
+    /// Adds a parallel system to a stage.
+    pub fn add_parallel_system(&mut self, stage: Stage, system: ParallelSystem) -> SystemBuilder<'_> {
+        self.add_entry(stage, SystemKind::Parallel(system))
+    }
+
+    /// Helper function for adding any kind of system to a stage.
+    fn add_entry(&mut self, stage: Stage, system: SystemKind) -> SystemBuilder<'_> {
+        let stage = &mut self.stages[stage.index()];
+        stage.systems.push(SystemEntry {
+            system,
+            access: Access::new(),
+            label: None,
+            before: Vec::new(),
+            after: Vec::new(),
+            last_run: 0,
+        });
+        stage.dirty = true;
+
+        SystemBuilder {
+            entry: stage.systems.last_mut().unwrap(),
+            dirty: &mut stage.dirty,
+        }
+    }
+
+    /// Moves all systems of another Schedule into this one.
+    pub(crate) fn append(&mut self, other: Schedule) {
+        for (stage, other) in self.stages.iter_mut().zip(other.stages) {
+            if !other.systems.is_empty() {
+                stage.systems.extend(other.systems);
+                stage.dirty = true;
+            }
+        }
+    }
+
+    /// Systems of a stage which run at the same time, in the order the
+    /// batches run. Systems are numbered in the order they were added.
+    pub fn batches(&mut self, stage: Stage) -> &[Vec<usize>] {
+        let stage = &mut self.stages[stage.index()];
+        if stage.dirty {
+            stage.sort();
+        }
+
+        &stage.batches
+    }
+
+    /// Change ticks of the last run of every system.
+    pub(crate) fn ticks(&self) -> HashMap<SystemKey, u64> {
+        let entries = self.stages.iter()
+            .flat_map(|stage| stage.systems.iter());
+        self.keys()
+            .into_iter()
+            .zip(entries)
+            .map(|(key, entry)| (key, entry.last_run))
+            .collect()
+    }
+
+    /// Sets the change ticks of the last run of every system.
+    ///
+    /// Systems added after the ticks were taken are left as they are.
+    pub(crate) fn set_ticks(&mut self, ticks: &HashMap<SystemKey, u64>) {
+        let keys = self.keys();
+        let entries = self.stages.iter_mut()
+            .flat_map(|stage| stage.systems.iter_mut());
+        for (key, entry) in keys.iter().zip(entries) {
+            if let Some(tick) = ticks.get(key) {
+                entry.last_run = *tick;
+            }
+        }
+    }
+
+    /// Helper function for the keys of every system, stage by stage in
+    /// the order they were added.
+    fn keys(&self) -> Vec<SystemKey> {
+        let mut keys: Vec<SystemKey> = Vec::new();
+        for (stage, systems) in Stage::ALL.iter().zip(&self.stages) {
+            let added = keys.len();
+            for entry in &systems.systems {
+                let system = entry.system.address();
+                let nth = keys[added..].iter()
+                    .filter(|key| key.label == entry.label && key.system == system)
+                    .count();
+                keys.push(SystemKey {
+                    stage: *stage,
+                    label: entry.label.clone(),
+                    system,
+                    nth,
+                });
+            }
+        }
+
+        keys
+    }
+
+    /// Runs the given stages in order, applying the queued commands
+    /// of the ECS after each stage.
+    pub fn run(&mut self, stages: &[Stage], ecs: &mut ECS) {
+        for stage in stages {
+            let stage = &mut self.stages[stage.index()];
+            if stage.dirty {
+                stage.sort();
+            }
+
+            for batch in 0..stage.batches.len() {
+                stage.run_batch(batch, ecs);
+            }
+
+            ecs.apply_commands();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first(_ecs: &mut ECS) {}
+
+    fn second(_ecs: &mut ECS) {}
+
+    #[test]
+    fn ticks_follow_systems_added_later() {
+        let mut ecs = ECS::new();
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, first).label("first");
+        schedule.add_system(Stage::Update, second);
+        schedule.add_system(Stage::Update, second);
+        schedule.run(&[Stage::Update], &mut ecs);
+        let saved = schedule.ticks();
+
+        // Systems added in earlier stages or sorted in front of the saved
+        // ones do not shift their ticks.
+        schedule.add_system(Stage::PreUpdate, second);
+        schedule.add_system(Stage::Update, first).before("first");
+        schedule.run(&[Stage::PreUpdate, Stage::Update], &mut ecs);
+        let ran = schedule.ticks();
+        schedule.set_ticks(&saved);
+
+        let restored = schedule.ticks();
+        assert_eq!(restored.len(), 5);
+        for (key, tick) in &restored {
+            match saved.get(key) {
+                Some(saved) => assert_eq!(tick, saved),
+                None => assert_eq!(tick, &ran[key]),
+            }
+        }
+        let mut ticks: Vec<u64> = saved.values().copied().collect();
+        ticks.sort_unstable();
+        ticks.dedup();
+        assert_eq!(ticks.len(), 3);
+    }
+}
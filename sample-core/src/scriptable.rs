@@ -1,8 +1,32 @@
+use sdl2::event::Event;
 use crate::ecs::Entity;
+use crate::time::Time;
 
-pub trait Scriptable {
+/// Behaviour of an Entity, run by a ScriptComponent.
+///
+/// Scripts are Send and Sync, as Components may be used by systems
+/// running on other threads.
+pub trait Scriptable: Send + Sync {
     fn create() -> Self;
     fn on_create(&mut self, _entity: &mut Entity) {}
-    fn on_update(&mut self, _entity: &mut Entity) {}
-    fn on_destroy() {}
+    fn on_update(&mut self, _entity: &mut Entity, _time: &Time) {}
+
+    /// Called once for every fixed step of time that passed, with the
+    /// duration of the step.
+    fn on_fixed_update(&mut self, _entity: &mut Entity, _dt: f32) {}
+
+    /// Called after on_create(), and when the script is enabled again.
+    fn on_enable(&mut self, _entity: &mut Entity) {}
+
+    /// Called when the script is disabled.
+    fn on_disable(&mut self, _entity: &mut Entity) {}
+
+    /// Called for every event passed to ECS::handle_event().
+    fn on_event(&mut self, _entity: &mut Entity, _event: &Event) {}
+
+    /// Called when the script's Component is removed or its Entity despawned.
+    ///
+    /// If the script despawned its own Entity, the Entity no longer
+    /// exists by the time this is called.
+    fn on_destroy(&mut self, _entity: &mut Entity) {}
 }
@@ -1,5 +1,7 @@
 pub use crate::{
     component::*,
     ecs::Entity,
-    scriptable::Scriptable
+    event::{EventReader, EventWriter, Events},
+    scriptable::Scriptable,
+    time::Time
 };
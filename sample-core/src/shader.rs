@@ -4,16 +4,24 @@ use std::io::Read;
 use std::ffi::CString;
 use glam;
 use crate::Transform;
+use crate::main_thread::{self, GlObject, MainThread};
 
 // TODO: Be able to pass data so as to correctly calculate aspect ratio.
 /// Builder to construct a Shader.
 pub struct ShaderBuilder {
+    name: String,
     vertex: Option<CString>,
     fragment: Option<CString>,
     perspective: Option<(bool, f32, f32)>
 }
 
 impl ShaderBuilder {
+    /// Sets the name the Shader is referred to by in Assets and scenes.
+    pub fn name(&mut self, name: &str) -> &mut ShaderBuilder {
+        self.name = name.to_string();
+        self
+    }
+
     /// Reads the data for the vertex shader.
     pub fn vertex(&mut self, path: &str) -> &mut ShaderBuilder {
         let mut buffer = Vec::new();
@@ -56,7 +64,7 @@ impl ShaderBuilder {
     ///
     /// NOTE: Shader may be enabled after building.
     // TODO: Get proper compilation error messages.
-    pub fn build(&mut self) -> Result<Shader, String> {
+    pub fn build(&mut self, _main_thread: &MainThread) -> Result<Shader, String> {
         if self.vertex.is_none() {
             return Err(String::from("Vertex shader not supplied"));
         }
@@ -137,6 +145,7 @@ impl ShaderBuilder {
                 std::ffi::CString::new("model").unwrap().into_raw());
 
             Ok(Shader {
+                name: std::mem::take(&mut self.name),
                 id: p_id,
                 proj_loc: proj_loc,
                 model_loc: model_loc,
@@ -151,6 +160,7 @@ impl ShaderBuilder {
 // TODO: Include support for other FOV's
 #[derive(Clone, Debug)]
 pub struct Shader {
+    name: String,
     id: gl::types::GLuint,
     proj_loc: gl::types::GLint,
     model_loc: gl::types::GLint,
@@ -161,14 +171,20 @@ impl Shader {
     /// Creates a ShaderBuilder to build a Shader from.
     pub fn new() -> ShaderBuilder {
         ShaderBuilder {
+            name: String::new(),
             vertex: None,
             fragment: None,
             perspective: None
         }
     }
 
+    /// Name of the Shader, empty unless set when building.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Enables the Shader.
-    pub fn enable(&self) {
+    pub fn enable(&self, _main_thread: &MainThread) {
         unsafe { gl::UseProgram(self.id); }
     }
 
@@ -179,7 +195,7 @@ impl Shader {
 
     /// Updates the aspect ratio of the shader. This method
     /// does nothing if no type is assigned.
-    pub fn update_aspect_ratio(&self, width: f32, height: f32) {
+    pub fn update_aspect_ratio(&self, width: f32, height: f32, main_thread: &MainThread) {
         match self.perspective {
             Some(is_perspect) => {
                 let mat = if is_perspect {
@@ -198,7 +214,7 @@ impl Shader {
                         100.0)
                 };
 
-                self.enable();
+                self.enable(main_thread);
                 unsafe {
                     gl::UniformMatrix4fv(self.proj_loc,
                         1,
@@ -210,19 +226,40 @@ impl Shader {
         };
     }
 
-    pub fn set_transform(&self, transform: &Transform) {
-        self.enable();
+    pub fn set_transform(&self, transform: &Transform, main_thread: &MainThread) {
+        self.set_model(&transform.transformation(), main_thread);
+    }
+
+    /// Sets the model matrix of the shader.
+    pub fn set_model(&self, model: &glam::Mat4, main_thread: &MainThread) {
+        self.enable(main_thread);
         unsafe {
             gl::UniformMatrix4fv(self.model_loc,
                                  1,
                                  gl::FALSE,
-                                 transform.transformation().as_ref() as *const _);
+                                 model.as_ref() as *const _);
+        }
+    }
+}
+
+#[cfg(test)]
+impl Shader {
+    /// A Shader with only a name and nothing compiled, for tests without GL.
+    pub(crate) fn unloaded(name: &str) -> Shader {
+        Shader {
+            name: name.to_string(),
+            id: 0,
+            proj_loc: -1,
+            model_loc: -1,
+            perspective: None
         }
     }
 }
 
 impl Drop for Shader {
     fn drop(&mut self) {
-        unsafe { gl::DeleteShader(self.id); }
+        if self.id != 0 {
+            main_thread::delete_later(GlObject::Shader(self.id));
+        }
     }
 }
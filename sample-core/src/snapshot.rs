@@ -0,0 +1,238 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use crate::component::Component;
+use crate::ecs::{EntityId, EntitySlot};
+use crate::query::Storages;
+use crate::schedule::SystemKey;
+use crate::storage::{AnyStorage, ComponentStorage};
+
+/// A Component or resource whose state is saved by ECS::snapshot().
+///
+/// Types are registered with ECS::register_snapshot() or
+/// ECS::register_resource_snapshot() before taking snapshots.
+pub trait Snapshot: Sized + 'static {
+    /// Copies the state to save, e.g. `self.clone()`.
+    fn snapshot(&self) -> Self;
+}
+
+/// Saved state of an ECS: its Entities, the Components and resources of
+/// every registered type, and the change ticks.
+///
+/// Taken with ECS::snapshot() and restored with ECS::restore(), any
+/// number of times.
+pub struct WorldSnapshot {
+    pub(crate) entities: Vec<EntitySlot>,
+    pub(crate) free: Vec<u32>,
+    pub(crate) entity_ids: HashMap<String, EntityId>,
+    pub(crate) components: Storages,
+    pub(crate) resources: HashMap<TypeId, Box<dyn Any>>,
+    pub(crate) change_tick: u64,
+    pub(crate) last_run: u64,
+    /// Change ticks of the last run of every system.
+    pub(crate) system_ticks: HashMap<SystemKey, u64>,
+}
+
+impl WorldSnapshot {
+    /// Change tick of the ECS when the snapshot was taken.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+}
+
+/// Function copying the storage of a Component type.
+type StorageSnapshot = fn(&dyn AnyStorage) -> Box<dyn AnyStorage>;
+/// Function copying a resource.
+type ResourceSnapshot = fn(&dyn Any) -> Box<dyn Any>;
+
+/// Functions copying the saved state of every registered type.
+#[derive(Default)]
+pub(crate) struct Snapshotters {
+    components: HashMap<TypeId, StorageSnapshot>,
+    resources: HashMap<TypeId, ResourceSnapshot>,
+}
+
+impl Snapshotters {
+    pub(crate) fn add_component<C: Component + Snapshot>(&mut self) {
+        self.components.insert(TypeId::of::<C>(), |storage| {
+            let storage = storage.as_any()
+                .downcast_ref::<ComponentStorage<C>>()
+                .unwrap();
+            Box::new(storage.snapshot())
+        });
+    }
+
+    pub(crate) fn add_resource<T: Snapshot>(&mut self) {
+        self.resources.insert(TypeId::of::<T>(), |resource| {
+            Box::new(resource.downcast_ref::<T>().unwrap().snapshot())
+        });
+    }
+
+    /// Copies the storage of a Component type, if registered.
+    pub(crate) fn component(&self, data_type: TypeId, storage: &dyn AnyStorage) -> Option<Box<dyn AnyStorage>> {
+        self.components.get(&data_type).map(|snapshot| snapshot(storage))
+    }
+
+    /// Copies a resource, if its type is registered.
+    pub(crate) fn resource(&self, data_type: TypeId, resource: &dyn Any) -> Option<Box<dyn Any>> {
+        self.resources.get(&data_type).map(|snapshot| snapshot(resource))
+    }
+
+    pub(crate) fn has_component(&self, data_type: TypeId) -> bool {
+        self.components.contains_key(&data_type)
+    }
+
+    /// Registered resource types.
+    pub(crate) fn resource_types(&self) -> impl Iterator<Item = &TypeId> {
+        self.resources.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change::ComponentTicks;
+    use crate::ecs::{ECS, Entity};
+    use crate::query::Changed;
+    use crate::schedule::Stage;
+
+    #[derive(Clone)]
+    struct Body {
+        position: [f32; 2],
+        velocity: [f32; 2],
+    }
+
+    impl Component for Body {
+        fn create(_entity: &mut Entity) -> Self {
+            Body {
+                position: [0.0; 2],
+                velocity: [0.0; 2],
+            }
+        }
+    }
+
+    impl Snapshot for Body {
+        fn snapshot(&self) -> Self {
+            self.clone()
+        }
+    }
+
+    /// Not registered, so only dropped along with its Entity.
+    struct Label;
+
+    impl Component for Label {
+        fn create(_entity: &mut Entity) -> Self {
+            Label
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Score {
+        fallen: u32,
+        moved: u32,
+    }
+
+    impl Snapshot for Score {
+        fn snapshot(&self) -> Self {
+            self.clone()
+        }
+    }
+
+    fn integrate(ecs: &mut ECS) {
+        let dt = ecs.time().fixed_step();
+        for mut body in ecs.query::<&mut Body>() {
+            body.velocity[1] -= 9.81 * dt;
+            body.position[0] += body.velocity[0] * dt;
+            body.position[1] += body.velocity[1] * dt;
+        }
+    }
+
+    fn spawn_and_despawn(ecs: &mut ECS) {
+        let frame = ecs.time().frame_count();
+        if frame.is_multiple_of(3) {
+            let speed = (frame % 7) as f32 * 0.3;
+            ecs.commands()
+                .spawn("Projectile")
+                .add_component::<Body, _>(move |body| body.velocity = [speed, 5.0])
+                .add_component::<Label, _>(|_| {});
+        }
+
+        let fallen: Vec<EntityId> = ecs.query::<(EntityId, &Body)>()
+            .filter(|(_, body)| body.position[1] < -5.0)
+            .map(|(id, _)| id)
+            .collect();
+        for id in fallen {
+            ecs.commands().despawn(id);
+            ecs.resource_mut::<Score>().unwrap().fallen += 1;
+        }
+    }
+
+    fn count_moved(ecs: &mut ECS) {
+        let moved = ecs.query_filtered::<EntityId, Changed<Body>>().count() as u32;
+        ecs.resource_mut::<Score>().unwrap().moved += moved;
+    }
+
+    type Dump = (Vec<(EntityId, String, [u32; 4], ComponentTicks)>, u32, u32, u64, u64, u64);
+
+    /// Everything that has to match bit for bit.
+    fn dump(ecs: &mut ECS) -> Dump {
+        let bodies: Vec<(EntityId, [u32; 4])> = ecs.query::<(EntityId, &Body)>()
+            .map(|(id, body)| (id, [
+                body.position[0].to_bits(),
+                body.position[1].to_bits(),
+                body.velocity[0].to_bits(),
+                body.velocity[1].to_bits(),
+            ]))
+            .collect();
+        let bodies = bodies.into_iter()
+            .map(|(id, bits)| {
+                let name = ecs.entity_name(id).unwrap().to_string();
+                (id, name, bits, ecs.component_ticks::<Body>(id).unwrap())
+            })
+            .collect();
+
+        let score = ecs.resource::<Score>().unwrap();
+        let time = ecs.time();
+        (bodies, score.fallen, score.moved, time.elapsed().to_bits(), time.frame_count(), ecs.change_tick())
+    }
+
+    #[test]
+    fn stepping_after_rollback_is_bit_identical() {
+        let mut ecs = ECS::new();
+        ecs.register_snapshot::<Body>();
+        ecs.insert_resource(Score::default());
+        ecs.register_resource_snapshot::<Score>();
+        ecs.add_system(Stage::FixedUpdate, integrate);
+        ecs.add_system(Stage::Update, spawn_and_despawn);
+        ecs.add_system(Stage::PostUpdate, count_moved);
+
+        ecs.step(10, 1.0 / 30.0);
+        let before = dump(&mut ecs);
+        let labelled = ecs.entities_with::<Label>();
+        let snapshot = ecs.snapshot();
+
+        ecs.step(60, 1.0 / 30.0);
+        let first = dump(&mut ecs);
+        assert!(ecs.resource::<Score>().unwrap().fallen > 0);
+        let late = ecs.entities_with::<Label>()
+            .into_iter()
+            .find(|id| !labelled.contains(id))
+            .unwrap();
+
+        // Commands queued before restoring are dropped.
+        ecs.commands().spawn("Stray");
+        ecs.restore(&snapshot);
+        assert_eq!(dump(&mut ecs), before);
+        assert!(!ecs.is_alive(late));
+        // Labels are not saved, only dropped along with the late Entities.
+        assert!(ecs.entities_with::<Label>().iter().all(|id| ecs.is_alive(*id)));
+
+        ecs.step(60, 1.0 / 30.0);
+        assert_eq!(dump(&mut ecs), first);
+        assert!(ecs.entity_id("Stray").is_none());
+
+        // The snapshot can be restored again.
+        ecs.restore(&snapshot);
+        ecs.step(60, 1.0 / 30.0);
+        assert_eq!(dump(&mut ecs), first);
+    }
+}
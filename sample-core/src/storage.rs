@@ -0,0 +1,266 @@
+use std::any::Any;
+use crate::change::{ComponentTicks, Mut, Ticks};
+use crate::ecs::EntityId;
+use crate::snapshot::Snapshot;
+use crate::types::Id;
+
+/// Marks an Entity index without a Component in the sparse array.
+const EMPTY: u32 = u32::MAX;
+
+/// All Components of a single type, stored as a sparse set.
+///
+/// Components are packed without gaps, so iterating over them is a
+/// linear walk, while the sparse array maps an Entity index to the
+/// position of its Component without any hashing.
+pub struct ComponentStorage<C> {
+    /// Components, packed without gaps.
+    pub(crate) components: Vec<C>,
+    /// Entity owning the Component at the same position.
+    pub(crate) owners: Vec<EntityId>,
+    /// Change ticks of the Component at the same position.
+    ticks: Vec<ComponentTicks>,
+    /// Position in components for every Entity index, or EMPTY.
+    sparse: Vec<u32>,
+}
+
+impl<C> ComponentStorage<C> {
+    pub(crate) fn new() -> Self {
+        ComponentStorage {
+            components: Vec::new(),
+            owners: Vec::new(),
+            ticks: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+
+    /// Position of the Component of an Entity.
+    pub(crate) fn dense_index(&self, id: EntityId) -> Option<Id> {
+        let index = *self.sparse.get(id.index())?;
+        if index == EMPTY || self.owners[index as usize] != id {
+            return None;
+        }
+
+        Some(index as Id)
+    }
+
+    pub(crate) fn get(&self, id: EntityId) -> Option<&C> {
+        let index = self.dense_index(id)?;
+        self.components.get(index)
+    }
+
+    /// Gets the Component of an Entity, flagging it as changed at
+    /// ticks.this_run when written.
+    pub(crate) fn get_mut(&mut self, id: EntityId, ticks: Ticks) -> Option<Mut<'_, C>> {
+        let index = self.dense_index(id)?;
+        Some(Mut::new(&mut self.components[index], &mut self.ticks[index], ticks))
+    }
+
+    /// Change ticks of the Component of an Entity.
+    pub(crate) fn ticks(&self, id: EntityId) -> Option<ComponentTicks> {
+        let index = self.dense_index(id)?;
+        Some(self.ticks[index])
+    }
+
+    /// Inserts the Component of an Entity at a tick, replacing any previous one.
+    ///
+    /// A replaced Component counts as changed, a new one as added.
+    pub(crate) fn insert(&mut self, id: EntityId, comp: C, tick: u64) -> &mut C {
+        if let Some(index) = self.dense_index(id) {
+            self.components[index] = comp;
+            self.ticks[index].set_changed(tick);
+            return &mut self.components[index];
+        }
+
+        if self.sparse.len() <= id.index() {
+            self.sparse.resize(id.index() + 1, EMPTY);
+        }
+
+        self.sparse[id.index()] = self.components.len() as u32;
+        self.components.push(comp);
+        self.owners.push(id);
+        self.ticks.push(ComponentTicks::new(tick));
+        self.components.last_mut().unwrap()
+    }
+
+    /// Removes the Component of an Entity.
+    ///
+    /// The last Component is swapped into the freed position.
+    pub(crate) fn remove(&mut self, id: EntityId) -> Option<C> {
+        let index = self.dense_index(id)?;
+        self.sparse[id.index()] = EMPTY;
+        self.owners.swap_remove(index);
+        self.ticks.swap_remove(index);
+
+        if let Some(moved) = self.owners.get(index) {
+            self.sparse[moved.index()] = index as u32;
+        }
+
+        Some(self.components.swap_remove(index))
+    }
+
+    /// Copies the storage, with a snapshot of every Component.
+    pub(crate) fn snapshot(&self) -> Self where C: Snapshot {
+        ComponentStorage {
+            components: self.components.iter().map(Snapshot::snapshot).collect(),
+            owners: self.owners.clone(),
+            ticks: self.ticks.clone(),
+            sparse: self.sparse.clone(),
+        }
+    }
+
+    /// Raw parts of the storage, for reading the Components of many
+    /// Entities at once. Nothing may be written through them.
+    pub(crate) fn raw_read(&self) -> RawStorage<C> {
+        RawStorage {
+            components: self.components.as_ptr() as *mut C,
+            owners: self.owners.as_ptr(),
+            ticks: self.ticks.as_ptr() as *mut ComponentTicks,
+            len: self.owners.len(),
+            sparse: self.sparse.as_ptr(),
+            sparse_len: self.sparse.len(),
+        }
+    }
+
+    /// Raw parts of the storage, for fetching the Components of many
+    /// Entities at once.
+    pub(crate) fn raw(&mut self) -> RawStorage<C> {
+        RawStorage {
+            components: self.components.as_mut_ptr(),
+            owners: self.owners.as_ptr(),
+            ticks: self.ticks.as_mut_ptr(),
+            len: self.owners.len(),
+            sparse: self.sparse.as_ptr(),
+            sparse_len: self.sparse.len(),
+        }
+    }
+}
+
+/// Type erased access to a ComponentStorage.
+///
+/// Storages are Send and Sync, so systems on other threads can use them.
+pub trait AnyStorage: Send + Sync {
+    /// Checks if an Entity has a Component in the storage.
+    fn contains(&self, id: EntityId) -> bool;
+    /// Drops the Components of all Entities for which keep returns false,
+    /// without running any hooks.
+    fn retain(&mut self, keep: &dyn Fn(EntityId) -> bool);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<C: Send + Sync + 'static> AnyStorage for ComponentStorage<C> {
+    fn contains(&self, id: EntityId) -> bool {
+        self.dense_index(id).is_some()
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(EntityId) -> bool) {
+        // Walk backwards, so the Components swapped into gaps were already kept.
+        for index in (0..self.owners.len()).rev() {
+            let id = self.owners[index];
+            if !keep(id) {
+                self.remove(id);
+            }
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Raw parts of a ComponentStorage, used by queries.
+///
+/// Only valid while the storage is neither moved nor changed in size.
+pub struct RawStorage<C> {
+    components: *mut C,
+    owners: *const EntityId,
+    ticks: *mut ComponentTicks,
+    len: usize,
+    sparse: *const u32,
+    sparse_len: usize,
+}
+
+impl<C> Clone for RawStorage<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for RawStorage<C> {}
+
+impl<C> RawStorage<C> {
+    /// Number of Components in the storage.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Pointer to the Entities owning the Components.
+    pub(crate) fn owners(&self) -> *const EntityId {
+        self.owners
+    }
+
+    /// Position of the Component of an Entity.
+    ///
+    /// # Safety
+    /// The storage must still be valid.
+    pub(crate) unsafe fn dense_index(&self, id: EntityId) -> Option<Id> {
+        if id.index() >= self.sparse_len {
+            return None;
+        }
+
+        let index = *self.sparse.add(id.index());
+        if index == EMPTY || *self.owners.add(index as usize) != id {
+            return None;
+        }
+
+        Some(index as Id)
+    }
+
+    /// Pointer to the Component at a position.
+    ///
+    /// # Safety
+    /// The storage must still be valid, and index less than len().
+    pub(crate) unsafe fn component(&self, index: Id) -> *mut C {
+        self.components.add(index)
+    }
+
+    /// Pointer to the change ticks of the Component at a position.
+    ///
+    /// # Safety
+    /// The storage must still be valid, and index less than len().
+    pub(crate) unsafe fn ticks(&self, index: Id) -> *mut ComponentTicks {
+        self.ticks.add(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removal_moves_last_component_into_gap() {
+        let mut storage = ComponentStorage::new();
+        let a = EntityId::new(0, 0);
+        let b = EntityId::new(3, 0);
+        let c = EntityId::new(7, 1);
+        storage.insert(a, 'a', 1);
+        storage.insert(b, 'b', 1);
+        storage.insert(c, 'c', 2);
+
+        assert_eq!(storage.remove(a), Some('a'));
+        assert_eq!(storage.get(a), None);
+        assert_eq!(storage.get(b), Some(&'b'));
+        assert_eq!(storage.get(c), Some(&'c'));
+        assert_eq!(storage.ticks(c), Some(ComponentTicks::new(2)));
+        assert_eq!(storage.get(EntityId::new(7, 0)), None);
+
+        *storage.insert(b, 'x', 3) = 'y';
+        assert_eq!(storage.components, vec!['c', 'y']);
+        assert_eq!(storage.ticks(b).unwrap().added(), 1);
+        assert_eq!(storage.ticks(b).unwrap().changed(), 3);
+    }
+}
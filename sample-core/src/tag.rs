@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use crate::component::Component;
+use crate::ecs::{ECS, Entity, EntityId};
+use crate::reflect::Reflect;
+use crate::snapshot::Snapshot;
+
+/// Labels of an Entity, e.g. "Enemy", for finding groups of Entities
+/// without a Component type of their own.
+///
+/// Added with ECS::add_tag(), and removed along with its last tag.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Reflect)]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    /// Labels of the Entity, in the order they were added.
+    pub fn get(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Checks if the Entity has a tag.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+}
+
+impl Component for Tags {
+    fn create(_entity: &mut Entity) -> Self {
+        Tags::default()
+    }
+}
+
+impl Snapshot for Tags {
+    fn snapshot(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl ECS {
+    /// Tags an Entity. Returns false if the Entity was deleted.
+    pub fn add_tag(&mut self, id: EntityId, tag: &str) -> bool {
+        if self.has_tag(id, tag) {
+            return true;
+        }
+
+        if self.get_component::<Tags>(id).is_none() && self.add_component::<Tags>(id, &|_| {}).is_none() {
+            return false;
+        }
+
+        self.get_component_mut::<Tags>(id)
+            .unwrap()
+            .0
+            .push(tag.to_string());
+        true
+    }
+
+    /// Removes a tag from an Entity. Returns false if it was not tagged.
+    pub fn remove_tag(&mut self, id: EntityId, tag: &str) -> bool {
+        if !self.has_tag(id, tag) {
+            return false;
+        }
+
+        let mut tags = self.get_component_mut::<Tags>(id).unwrap();
+        tags.0.retain(|t| t != tag);
+        if tags.0.is_empty() {
+            self.remove_component::<Tags>(id);
+        }
+        true
+    }
+
+    /// Checks if an Entity has a tag.
+    pub fn has_tag(&self, id: EntityId, tag: &str) -> bool {
+        self.get_component::<Tags>(id)
+            .is_some_and(|tags| tags.contains(tag))
+    }
+
+    /// Ids of all Entities with a tag, e.g. `ecs.tagged("Enemy")`.
+    pub fn tagged(&self, tag: &str) -> Vec<EntityId> {
+        match self.storage::<Tags>() {
+            Some(storage) => storage.owners.iter()
+                .zip(&storage.components)
+                .filter(|(_, tags)| tags.contains(tag))
+                .map(|(id, _)| *id)
+                .collect(),
+            None => Vec::new()
+        }
+    }
+}
+
+impl Entity<'_> {
+    /// Tags the Entity.
+    pub fn add_tag(&mut self, tag: &str) {
+        let id = self.id();
+        self.scene().add_tag(id, tag);
+    }
+
+    /// Removes a tag from the Entity.
+    pub fn remove_tag(&mut self, tag: &str) {
+        let id = self.id();
+        self.scene().remove_tag(id, tag);
+    }
+
+    /// Checks if the Entity has a tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.get_component::<Tags>()
+            .is_some_and(|tags| tags.contains(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique_and_tags_group_entities() {
+        let mut ecs = ECS::new();
+        let player = ecs.new_entity("Player").id();
+        let enemies: Vec<EntityId> = (0..3)
+            .map(|_| ecs.new_entity("Enemy").id())
+            .collect();
+
+        assert_eq!(ecs.entity_name(enemies[0]), Some("Enemy"));
+        assert_eq!(ecs.entity_name(enemies[2]), Some("Enemy (2)"));
+        assert_eq!(ecs.find_by_name("Enemy (1)").unwrap().id(), enemies[1]);
+        assert_eq!(ecs.set_entity_name(enemies[2], "Player"), Some("Player (1)"));
+        assert!(ecs.find_by_name("Enemy (2)").is_none());
+        assert_eq!(ecs.find_by_name("Player").unwrap().id(), player);
+
+        for enemy in &enemies {
+            ecs.add_tag(*enemy, "Enemy");
+        }
+        ecs.add_tag(enemies[0], "Boss");
+        ecs.add_tag(enemies[0], "Boss");
+        assert_eq!(ecs.tagged("Enemy"), enemies);
+        assert_eq!(ecs.get_component::<Tags>(enemies[0]).unwrap().get(), &["Enemy", "Boss"]);
+
+        ecs.despawn(enemies[1]);
+        ecs.remove_tag(enemies[2], "Enemy");
+        assert_eq!(ecs.tagged("Enemy"), vec![enemies[0]]);
+        assert!(ecs.get_component::<Tags>(enemies[2]).is_none());
+        assert!(!ecs.add_tag(enemies[1], "Enemy"));
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::Path;
 use std::ffi::c_void;
+use crate::main_thread::{self, GlObject, MainThread};
 use gl::{
     self,
     types::{
@@ -15,6 +16,8 @@ use image::{open as open_image,
 /// A texture that gets wrapped onto a mesh.
 #[derive(Debug)]
 pub struct Texture {
+    /// Path the texture was loaded from.
+    path: String,
     /// Id of the texture.
     id: GLuint,
     /// Width of the texture.
@@ -25,14 +28,30 @@ pub struct Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
-        unsafe { gl::DeleteTextures(1, &self.id); }
+        if self.id != 0 {
+            main_thread::delete_later(GlObject::Texture(self.id));
+        }
+    }
+}
+
+#[cfg(test)]
+impl Texture {
+    /// A Texture with only a path and nothing uploaded, for tests without GL.
+    pub(crate) fn unloaded(path: &str) -> Texture {
+        Texture {
+            path: path.to_string(),
+            id: 0,
+            width: 0,
+            height: 0
+        }
     }
 }
 
 impl Texture {
     /// Creates a new texture from a path. Only supports RGB formats.
     /// NOTE: May have unexpected results with alpha data (e.g. PNGS)
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+    pub fn new<P: AsRef<Path>>(path: P, main_thread: &MainThread) -> Result<Self, String> {
+        let path_name = path.as_ref().to_string_lossy().into_owned();
         let mut image = match open_image(path) {
             Ok(image) => {
                image 
@@ -72,6 +91,7 @@ impl Texture {
         let raw = image.as_bytes();
 
         let mut texture = Texture {
+            path: path_name,
             id: 0,
             width: width,
             height: height
@@ -82,7 +102,7 @@ impl Texture {
             gl::BindTexture(gl::TEXTURE_2D, texture.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-            texture.linear_mipmap_nearest();
+            texture.linear_mipmap_nearest(main_thread);
             gl::TexImage2D(gl::TEXTURE_2D, 
                            0, 
                            format as i32,
@@ -97,8 +117,13 @@ impl Texture {
         Ok(texture)
     }
 
+    /// Path the texture was loaded from.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
     /// Enable the texture for drawing.
-    pub fn enable(&self) {
+    pub fn enable(&self, _main_thread: &MainThread) {
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, self.id);
@@ -106,7 +131,7 @@ impl Texture {
     }
 
     /// Set texture to linear filtering.
-    pub fn linear(&self) {
+    pub fn linear(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
@@ -115,7 +140,7 @@ impl Texture {
     }
 
     /// Set texture to nearest filtering.
-    pub fn nearest(&self) {
+    pub fn nearest(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
@@ -124,7 +149,7 @@ impl Texture {
     }
 
     /// Set texture to linear filtering using nearest mipmap.
-    pub fn linear_mipmap_nearest(&self) {
+    pub fn linear_mipmap_nearest(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_NEAREST as i32);
@@ -133,7 +158,7 @@ impl Texture {
     }
 
     /// Set texture to nearest filtering using nearest mipmap.
-    pub fn nearest_mipmap_nearest(&self) {
+    pub fn nearest_mipmap_nearest(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST_MIPMAP_NEAREST as i32);
@@ -142,7 +167,7 @@ impl Texture {
     }
 
     /// Set texture to linear filtering using linear combination of nearest mipmaps.
-    pub fn linear_mipmap_linear(&self) {
+    pub fn linear_mipmap_linear(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
@@ -151,7 +176,7 @@ impl Texture {
     }
 
     /// Set texture to nearest filtering using linear combination of nearest mipmaps.
-    pub fn nearest_mipmap_linear(&self) {
+    pub fn nearest_mipmap_linear(&self, _main_thread: &MainThread) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST_MIPMAP_LINEAR as i32);
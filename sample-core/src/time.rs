@@ -0,0 +1,207 @@
+use crate::snapshot::Snapshot;
+
+/// Frame timing of the game loop.
+///
+/// Advanced once per frame by the loop, and passed to every
+/// Component and script update.
+#[derive(Clone, Copy, Debug)]
+pub struct Time {
+    /// Scaled duration of the last frame, in seconds.
+    delta: f32,
+    /// Unscaled duration of the last frame, in seconds.
+    raw_delta: f32,
+    /// Scaled time since the start, in seconds.
+    elapsed: f64,
+    /// Number of frames advanced.
+    frame_count: u64,
+    /// Factor applied to the duration of frames.
+    time_scale: f32,
+    /// Whether time is paused.
+    paused: bool,
+    /// Duration of a fixed update, in seconds.
+    fixed_step: f32,
+    /// Scaled time not yet consumed by fixed updates.
+    accumulator: f32,
+    /// Most fixed updates a frame can accumulate.
+    max_fixed_steps: u32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time::START
+    }
+}
+
+impl Snapshot for Time {
+    fn snapshot(&self) -> Self {
+        *self
+    }
+}
+
+impl Time {
+    /// Time at zero, which has never advanced.
+    pub(crate) const START: Time = Time {
+        delta: 0.0,
+        raw_delta: 0.0,
+        elapsed: 0.0,
+        frame_count: 0,
+        time_scale: 1.0,
+        paused: false,
+        fixed_step: 1.0 / 60.0,
+        accumulator: 0.0,
+        max_fixed_steps: 8,
+    };
+
+    /// Creates a Time at zero, with a fixed step of 1/60th of a second
+    /// and up to 8 fixed updates per frame.
+    pub fn new() -> Self {
+        Time::default()
+    }
+
+    /// Advances time by the real duration of a frame, in seconds.
+    ///
+    /// While paused, the frame is counted but no time passes. Time beyond
+    /// the most fixed updates a frame can run is dropped, so a long frame
+    /// does not make the next ones longer still.
+    pub fn advance(&mut self, raw_delta: f32) {
+        self.raw_delta = raw_delta;
+        self.delta = if self.paused {
+            0.0
+        } else {
+            raw_delta * self.time_scale
+        };
+
+        self.elapsed += self.delta as f64;
+        self.accumulator = (self.accumulator + self.delta).min(self.fixed_step * self.max_fixed_steps as f32);
+        self.frame_count += 1;
+    }
+
+    /// Consumes one fixed step of the accumulated time, if enough has passed.
+    ///
+    /// Call in a loop after advance(), running a fixed update for every
+    /// step consumed.
+    pub fn consume_fixed_step(&mut self) -> bool {
+        if self.accumulator < self.fixed_step {
+            return false;
+        }
+
+        self.accumulator -= self.fixed_step;
+        true
+    }
+
+    /// Scaled duration of the last frame, in seconds.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Unscaled duration of the last frame, in seconds.
+    pub fn raw_delta(&self) -> f32 {
+        self.raw_delta
+    }
+
+    /// Scaled time since the start, in seconds.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Number of frames advanced.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Factor applied to the duration of frames.
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Sets the factor applied to the duration of frames.
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = scale.max(0.0);
+    }
+
+    /// Whether time is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes time.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Duration of a fixed update, in seconds.
+    pub fn fixed_step(&self) -> f32 {
+        self.fixed_step
+    }
+
+    /// Sets the duration of a fixed update, in seconds.
+    ///
+    /// Panics unless the step is positive, as fixed updates would never
+    /// consume any time otherwise.
+    pub fn set_fixed_step(&mut self, step: f32) {
+        assert!(step > 0.0 && step.is_finite(), "Fixed step has to be positive, got {}", step);
+        self.fixed_step = step;
+    }
+
+    /// Most fixed updates a frame can accumulate.
+    pub fn max_fixed_steps(&self) -> u32 {
+        self.max_fixed_steps
+    }
+
+    /// Sets the most fixed updates a frame can accumulate, at least 1.
+    pub fn set_max_fixed_steps(&mut self, steps: u32) {
+        self.max_fixed_steps = steps.max(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_and_pause_apply_to_delta() {
+        let mut time = Time::new();
+        time.set_time_scale(2.0);
+        time.advance(0.25);
+        assert_eq!(time.delta(), 0.5);
+        assert_eq!(time.raw_delta(), 0.25);
+
+        time.set_paused(true);
+        time.advance(0.25);
+        assert_eq!(time.delta(), 0.0);
+        assert_eq!(time.elapsed(), 0.5);
+        assert_eq!(time.frame_count(), 2);
+    }
+
+    #[test]
+    fn fixed_steps_follow_accumulated_time() {
+        let mut time = Time::new();
+        time.set_fixed_step(0.25);
+        time.advance(0.625);
+
+        let mut steps = 0;
+        while time.consume_fixed_step() {
+            steps += 1;
+        }
+        assert_eq!(steps, 2);
+
+        time.advance(0.125);
+        assert!(time.consume_fixed_step());
+        assert!(!time.consume_fixed_step());
+
+        // A long frame only runs the most fixed updates allowed.
+        time.set_max_fixed_steps(3);
+        time.advance(10.0);
+        let mut steps = 0;
+        while time.consume_fixed_step() {
+            steps += 1;
+        }
+        assert_eq!(steps, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fixed step has to be positive")]
+    fn fixed_step_of_zero_is_rejected() {
+        Time::new().set_fixed_step(0.0);
+    }
+}
@@ -0,0 +1,109 @@
+//! Derive macros of sample-core.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Index, Meta, NestedMeta};
+
+/// Derives `sample_core::reflect::Reflect` for a struct.
+///
+/// Named fields are reflected by their name and tuple fields by their
+/// index. Every reflected field has to implement Reflect itself, fields
+/// marked with `#[reflect(ignore)]` are left out.
+#[proc_macro_derive(Reflect, attributes(reflect))]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match reflect(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn reflect(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "Reflect can only be derived for structs")),
+    };
+
+    // Names of the fields, along with the expressions accessing them.
+    let mut names = Vec::new();
+    let mut members = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        if is_ignored(field)? {
+            continue;
+        }
+
+        match &field.ident {
+            Some(ident) => {
+                names.push(ident.to_string());
+                members.push(quote!(#ident));
+            }
+            None => {
+                let index = Index::from(index);
+                names.push(index.index.to_string());
+                members.push(quote!(#index));
+            }
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::sample_core::reflect::Reflect for #ident #type_generics #where_clause {
+            fn field_names(&self) -> ::std::vec::Vec<::std::string::String> {
+                ::std::vec![#(::std::string::String::from(#names)),*]
+            }
+
+            fn field(&self, name: &str) -> ::std::option::Option<&dyn ::sample_core::reflect::Reflect> {
+                match name {
+                    #(#names => ::std::option::Option::Some(&self.#members),)*
+                    _ => ::std::option::Option::None
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> ::std::option::Option<&mut dyn ::sample_core::reflect::Reflect> {
+                match name {
+                    #(#names => ::std::option::Option::Some(&mut self.#members),)*
+                    _ => ::std::option::Option::None
+                }
+            }
+
+            fn to_value(&self) -> ::sample_core::reflect::Value {
+                ::sample_core::reflect::struct_to_value(self)
+            }
+
+            fn set_value(&mut self, value: ::sample_core::reflect::Value) -> ::std::result::Result<(), ::std::string::String> {
+                ::sample_core::reflect::struct_set_value(self, value)
+            }
+
+            fn as_any(&self) -> &dyn ::std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn ::std::any::Any {
+                self
+            }
+        }
+    })
+}
+
+/// Checks for `#[reflect(ignore)]`.
+fn is_ignored(field: &syn::Field) -> Result<bool, Error> {
+    let mut ignored = false;
+    for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("reflect")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("ignore") => ignored = true,
+                        other => return Err(Error::new_spanned(other, "expected `ignore`")),
+                    }
+                }
+            }
+            other => return Err(Error::new_spanned(other, "expected `#[reflect(ignore)]`")),
+        }
+    }
+
+    Ok(ignored)
+}
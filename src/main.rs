@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::sync::Arc;
 use gl;
 use sdl2::{
     keyboard::Keycode,
@@ -6,9 +6,11 @@ use sdl2::{
     video::GLProfile,
 };
 use sample_core::{
+    assets::Assets,
+    main_thread::MainThread,
     shader::Shader,
     texture::Texture,
-    mesh::Mesh,
+    mesh::{Mesh, VertexLayout, VertexAttribute, Semantic, ComponentType, IndexType, PrimitiveMode, Shading},
     ecs::{ECS},
     component::components::*
 };
@@ -21,8 +23,7 @@ use scripts::plane::Plane;
 const WIDTH: u32 = 1366 / 2;
 const HEIGHT: u32 = 768 / 2;
 
-// Mainly just testing code, eventually main should just read files
-// TODO: Read files instead of hard code
+// Mainly just testing code, the entities are read from scenes/main.ron
 fn main() {
     // ----- Init ----- //
     let context = sdl2::init().unwrap();
@@ -41,59 +42,41 @@ fn main() {
 
     let _gl_context = window.gl_create_context().unwrap();
     gl::load_with(|name| video.gl_get_proc_address(name) as *const std::os::raw::c_void);
+    // The context was just made current on this thread.
+    let main_thread = unsafe { MainThread::new() };
     // ----- !Init ----- //
 
     // ----- Textures ----- //
-    //let texture = Rc::new(Texture::new("src/{{{IMAGE HERE}}}.png").unwrap());
+    //let texture = Arc::new(Texture::new("src/{{{IMAGE HERE}}}.png", &main_thread).unwrap());
     // ----- !Textures ----- //
     
     // ----- Shader ----- //
-    let shader = Rc::new(Shader::new()
+    let shader = Arc::new(Shader::new()
+        .name("default")
         .vertex("shaders/vertex.glsl")
         .fragment("shaders/fragment.glsl")
         .perspective(WIDTH, HEIGHT)
-        .build()
+        .build(&main_thread)
         .unwrap());
     // ----- !Shader ----- //
 
-    // ------ Data ----- //
-    let vertices: Vec<gl::types::GLfloat> = vec![
-        0.5, 0.5, 0.0,
-        0.5, -0.5, 0.0,
-        -0.5, -0.5, 0.0,
-        -0.5, 0.5, 0.0,
-    ];
-
-    let indices: Vec<gl::types::GLuint> = vec![
-        0, 1, 3,
-        1, 2, 3
-    ];
-
-    let uv: Vec<gl::types::GLfloat> = vec![
-        1.0, 0.0,
-        1.0, 1.0,
-        0.0, 1.0,
-        0.0, 0.0
-    ];
-    // ------ !Data ----- //
-
     // ----- Mesh ----- //
-    let mesh = add_model("src/boxes.gltf").expect("Model failed");
-    /*let mesh = Rc::new(Mesh::new()
-        .vertices(vertices)
-        .indices(indices)
-        .uv(uv)
-        .build()
+    let mesh = add_model("src/boxes.gltf", &main_thread).expect("Model failed");
+    /*let mesh = Arc::new(Mesh::new()
+        .data(sample_core::mesh::shapes::quad(1.0, 1.0))
+        .build(&main_thread)
         .unwrap());*/
     // ----- !Mesh ----- //
     
+    let mut assets = Assets::new();
+    assets.add_mesh(mesh);
+    assets.add_shader(shader);
+
     // ----- ECS ----- //
     let mut ecs = ECS::new();
-    let entity = ecs.new_entity("Plane");
-    entity.add_component::<MeshComponent>(&|comp| {
-        comp.add_mstm(mesh.clone(), shader.clone(), None, None);
-    });
-    entity.add_component::<ScriptComponent<Plane>>(&|_| {} );
+    ecs.set_main_thread(main_thread);
+    ecs.register_scene_component::<ScriptComponent<Plane>>("Plane");
+    ecs.load_scene("scenes/main.ron", &mut assets).expect("Scene failed");
     // ----- !ECS ----- //
 
     unsafe {
@@ -102,7 +85,9 @@ fn main() {
     }
 
     let mut events = context.event_pump().unwrap();
-    let _timer = context.timer().unwrap();
+    let mut timer = context.timer().unwrap();
+    let frequency = timer.performance_frequency() as f32;
+    let mut last_frame = timer.performance_counter();
 
     'game_loop: loop {
         for event in events.poll_iter() {
@@ -112,18 +97,24 @@ fn main() {
                 },
                 _ => {}
             }
+            ecs.handle_event(&event);
         }
 
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         }
 
+        let now = timer.performance_counter();
+        ecs.advance_time((now - last_frame) as f32 / frequency);
+        last_frame = now;
+
         ecs.update();
         window.gl_swap_window();
     }
 }
 
-pub fn add_model<P: AsRef<std::path::Path>>(path: P) -> Result<Rc<Mesh>, String> {
+pub fn add_model<P: AsRef<std::path::Path>>(path: P, main_thread: &MainThread) -> Result<Arc<Mesh>, String> {
+    let name = path.as_ref().to_string_lossy().into_owned();
     let (document, buffers, images) = match gltf::import(path) {
         Ok(t) => t,
         Err(e) => return Err(format!("{}", e)) 
@@ -133,23 +124,63 @@ pub fn add_model<P: AsRef<std::path::Path>>(path: P) -> Result<Rc<Mesh>, String>
     let p = m.primitives().next().unwrap();
 
     let pos_accessor = p.get(&gltf::Semantic::Positions).unwrap();
-    let pos_view = pos_accessor.view().unwrap();
-    let pos_offset = pos_view.offset();
-    let pos_length = pos_view.length();
-    let pos_buffer = {
-        let index = pos_view.buffer().index();
-        let buffer = &buffers[index];
-        &buffer[pos_offset..pos_offset + pos_length]
+    let (pos_buffer, pos_stride) = accessor_bytes(&pos_accessor, &buffers);
+    let mut layout = VertexLayout::new()
+        .attribute(VertexAttribute::new(Semantic::Position, 0, ComponentType::F32, 3).stride(pos_stride));
+
+    // Attributes the model lacks, like normals, are generated by the MeshBuilder,
+    // with flat normals as glTF asks for.
+    let mut attribute_buffers = Vec::new();
+    let optional = vec![
+        (gltf::Semantic::TexCoords(0), Semantic::TexCoord(0), 1),
+        (gltf::Semantic::Normals, Semantic::Normal, 2),
+        (gltf::Semantic::Tangents, Semantic::Tangent, 3),
+    ];
+    for (gltf_semantic, semantic, location) in optional {
+        if let Some(accessor) = p.get(&gltf_semantic) {
+            let (buffer, stride) = accessor_bytes(&accessor, &buffers);
+            let component_type = match accessor.data_type() {
+                gltf::accessor::DataType::I8 => ComponentType::I8,
+                gltf::accessor::DataType::U8 => ComponentType::U8,
+                gltf::accessor::DataType::I16 => ComponentType::I16,
+                gltf::accessor::DataType::U16 => ComponentType::U16,
+                gltf::accessor::DataType::U32 => ComponentType::U32,
+                gltf::accessor::DataType::F32 => ComponentType::F32,
+            };
+            layout = layout.attribute(VertexAttribute::new(semantic, location, component_type, accessor.dimensions().multiplicity() as u32)
+                .normalized(accessor.normalized())
+                .stride(stride)
+                .buffer(attribute_buffers.len() + 1));
+            attribute_buffers.push(buffer.to_vec());
+        }
+    }
+
+    // Primitives without indices draw their vertices in order.
+    let (ind_buffer, index_type): (&[u8], IndexType) = match p.indices() {
+        Some(ind_accessor) => {
+            let index_type = match ind_accessor.data_type() {
+                gltf::accessor::DataType::U8 => IndexType::U8,
+                gltf::accessor::DataType::U16 => IndexType::U16,
+                gltf::accessor::DataType::U32 => IndexType::U32,
+                data_type => return Err(format!("Unsupported index type {:?}", data_type))
+            };
+            let ind_view = ind_accessor.view().unwrap();
+            let ind_offset = ind_view.offset() + ind_accessor.offset();
+            let ind_length = ind_accessor.count() * index_type.size();
+            let index = ind_view.buffer().index();
+            (&buffers[index][ind_offset..ind_offset + ind_length], index_type)
+        },
+        None => (&[], IndexType::U16)
     };
 
-    let ind_accessor = p.indices().unwrap();
-    let ind_view = ind_accessor.view().unwrap();
-    let ind_offset = ind_view.offset();
-    let ind_length = ind_view.length();
-    let ind_buffer = {
-        let index = ind_view.buffer().index();
-        let buffer = &buffers[index];
-        &buffer[ind_offset..ind_offset + ind_length]
+    let mode = match p.mode() {
+        gltf::mesh::Mode::Points => PrimitiveMode::Points,
+        gltf::mesh::Mode::Lines => PrimitiveMode::Lines,
+        gltf::mesh::Mode::LineLoop => PrimitiveMode::LineLoop,
+        gltf::mesh::Mode::LineStrip => PrimitiveMode::LineStrip,
+        gltf::mesh::Mode::Triangles => PrimitiveMode::Triangles,
+        gltf::mesh::Mode::TriangleStrip => PrimitiveMode::TriangleStrip,
+        gltf::mesh::Mode::TriangleFan => PrimitiveMode::TriangleFan,
     };
 
     println!("Index count from buffer reference: {}", ind_buffer.len());
@@ -163,13 +194,27 @@ pub fn add_model<P: AsRef<std::path::Path>>(path: P) -> Result<Rc<Mesh>, String>
     let vertices = pos_buffer.to_vec();
     let indices = ind_buffer.to_vec();
 
-    let mesh = Rc::new(Mesh::new()
-        .vertices(vertices)
+    let builder = attribute_buffers.into_iter().fold(Mesh::new().vertices(vertices), |builder, buffer| builder.buffer(buffer));
+    let mesh = Arc::new(builder
+        .name(name)
         .indices(indices)
-        .uv(Vec::new())
-        .build()
+        .index_type(index_type)
+        .mode(mode)
+        .shading(Shading::Flat)
+        .layout(layout)
+        .build(main_thread)
         .unwrap()
     );
 
     return Ok(mesh);
+}
+
+/// Helper function for the bytes of an accessor and their stride, only
+/// from its first to its last value, as the view may be shared.
+fn accessor_bytes<'a>(accessor: &gltf::Accessor, buffers: &'a [gltf::buffer::Data]) -> (&'a [u8], usize) {
+    let view = accessor.view().unwrap();
+    let stride = view.stride().unwrap_or(0);
+    let offset = view.offset() + accessor.offset();
+    let length = (accessor.count() - 1) * stride.max(accessor.size()) + accessor.size();
+    (&buffers[view.buffer().index()][offset..offset + length], stride)
 }
\ No newline at end of file